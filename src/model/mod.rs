@@ -1,2 +1,6 @@
 pub mod db_struct;
+pub mod save_model;
+pub mod vmc_backup;
+pub mod vmc_check;
 pub mod vmc_core_model;
+pub mod vmc_undelete;
@@ -0,0 +1,32 @@
+use crate::model::vmc_core_model::RawFSEntry;
+
+// One file of a save directory, with the directory entry it had on the card
+#[derive(Debug, Clone)]
+pub struct SaveFile {
+    pub entry: RawFSEntry,
+    pub data: Vec<u8>,
+}
+
+impl SaveFile {
+    pub fn name(&self) -> String {
+        self.entry.name_str()
+    }
+}
+
+// A whole save directory detached from any card, as stored in single-save archives
+// (.psu, .max, .cbs, ...). `entry` keeps the directory's own mode bits and timestamps.
+#[derive(Debug, Clone)]
+pub struct SaveDir {
+    pub entry: RawFSEntry,
+    pub files: Vec<SaveFile>,
+}
+
+impl SaveDir {
+    pub fn name(&self) -> String {
+        self.entry.name_str()
+    }
+
+    pub fn file(&self, name: &str) -> Option<&SaveFile> {
+        self.files.iter().find(|f| f.name() == name)
+    }
+}
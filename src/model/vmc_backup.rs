@@ -0,0 +1,258 @@
+// Backup-block transactions. Flash is rewritten an erase block at a time, so a crash
+// between erasing a block and writing it back loses the whole block. Like the PS2's
+// own card driver, every block write first goes to backup_block1, then the number of
+// the target block is recorded in backup_block2, and only then is the target
+// rewritten. backup_block2 is erased again once the target is complete.
+//
+// backup_block2, first page: the target block with bit 31 set (u32 LE), as the PS2's card
+// driver writes it. Without the flag bit (an erased or zeroed page) no copy is pending.
+//
+// Each step is synced to the backend before the next one starts, otherwise the OS could
+// reorder them and defeat the scheme. The price: every changed block costs one block
+// read and four block writes (backup_block1, record, target, erase), so rewriting a
+// single 1 KB cluster moves about 40 KB and waits on four syncs.
+
+use crate::model::vmc_core_model::Vmc;
+use crate::vmc::ecc::ecc_calculate_page;
+use std::fs::File;
+use std::io::{self, Cursor, Read, Seek, SeekFrom, Write};
+
+// Backends that can make written data durable. Writable cards need it so the steps
+// of a block copy reach the storage in order.
+pub trait SyncData {
+    fn sync_data(&mut self) -> io::Result<()>;
+}
+
+impl SyncData for File {
+    fn sync_data(&mut self) -> io::Result<()> {
+        File::sync_data(self)
+    }
+}
+
+// Memory has nothing to persist
+impl SyncData for Cursor<Vec<u8>> {
+    fn sync_data(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl SyncData for Cursor<&mut Vec<u8>> {
+    fn sync_data(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl SyncData for Cursor<&mut [u8]> {
+    fn sync_data(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl SyncData for Vec<u8> {
+    fn sync_data(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl<W: Write + SyncData> SyncData for io::BufWriter<W> {
+    fn sync_data(&mut self) -> io::Result<()> {
+        self.flush()?;
+        self.get_mut().sync_data()
+    }
+}
+
+const COPY_PENDING: u32 = 0x80000000;
+const ERASED: u8 = 0xFF;
+
+// A block copy that was recorded but never confirmed as finished
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PendingBlockCopy {
+    pub block: u32,
+    // The target was erased and never programmed, so its old contents are gone
+    pub target_erased: bool,
+    // The target already holds the new contents; only the record was left behind
+    pub target_complete: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BlockRecovery {
+    // Finish the write by copying backup_block1 over the target
+    RollForward,
+    // Drop the record and keep the target as it is. The record cannot prove a target
+    // untouched, so this is only accepted when the target provably holds a whole block:
+    // when it already matches backup_block1. Every other state must roll forward.
+    RollBack,
+}
+
+impl<B: Read + Seek> Vmc<B> {
+    fn blocks_per_card(&self) -> u32 {
+        let pages_per_card =
+            self.superblock.clusters_per_card as u64 * self.superblock.pages_per_cluster as u64;
+        (pages_per_card / self.superblock.pages_per_block as u64) as u32
+    }
+
+    // Size of one erase block in the image, spare areas included
+    fn raw_block_size(&self) -> u64 {
+        self.superblock.pages_per_block as u64 * self.raw_page_size as u64
+    }
+
+    // Cards whose superblock names no usable pair of backup blocks are written in place.
+    // Block 0 holds the superblock and can never be a backup block.
+    fn has_backup_blocks(&self) -> bool {
+        let (first, second) = (self.superblock.backup_block1, self.superblock.backup_block2);
+        let blocks = self.blocks_per_card();
+        first != second && (1..blocks).contains(&first) && (1..blocks).contains(&second)
+    }
+
+    fn is_backup_block(&self, block: u32) -> bool {
+        block == self.superblock.backup_block1 || block == self.superblock.backup_block2
+    }
+
+    fn read_raw_block(&mut self, block: u32) -> io::Result<Vec<u8>> {
+        let size = self.raw_block_size();
+        let mut raw = vec![0u8; size as usize];
+        self.file.seek(SeekFrom::Start(block as u64 * size))?;
+        self.file.read_exact(&mut raw)?;
+        Ok(raw)
+    }
+
+    // Look for the record of an unfinished block copy in backup_block2
+    pub(crate) fn read_pending_copy(&mut self) -> io::Result<Option<PendingBlockCopy>> {
+        if !self.has_backup_blocks() {
+            return Ok(None);
+        }
+        let record = self.read_raw_block(self.superblock.backup_block2)?;
+        let value = u32::from_le_bytes(record[..4].try_into().unwrap());
+        let block = value & !COPY_PENDING;
+        if value & COPY_PENDING == 0
+            || block >= self.blocks_per_card()
+            || self.is_backup_block(block)
+        {
+            return Ok(None);
+        }
+
+        let target = self.read_raw_block(block)?;
+        let backup = self.read_raw_block(self.superblock.backup_block1)?;
+        Ok(Some(PendingBlockCopy {
+            block,
+            target_erased: target.iter().all(|&b| b == ERASED),
+            target_complete: target == backup,
+        }))
+    }
+
+    // The copy interrupted by a crash, as found when the card was opened
+    pub fn pending_block_copy(&self) -> Option<PendingBlockCopy> {
+        self.pending_copy
+    }
+}
+
+impl<B: Read + Write + Seek + SyncData> Vmc<B> {
+    fn write_raw_block(&mut self, block: u32, raw: &[u8]) -> io::Result<()> {
+        self.file
+            .seek(SeekFrom::Start(block as u64 * self.raw_block_size()))?;
+        self.file.write_all(raw)
+    }
+
+    // Write a whole block as one step of a block copy and wait until it is stored
+    fn write_block_durably(&mut self, block: u32, raw: &[u8]) -> io::Result<()> {
+        self.write_raw_block(block, raw)?;
+        self.file.flush()?;
+        self.file.sync_data()
+    }
+
+    fn erase_block(&mut self, block: u32) -> io::Result<()> {
+        let erased = vec![ERASED; self.raw_block_size() as usize];
+        self.write_block_durably(block, &erased)
+    }
+
+    // backup_block2 with the copy record in its first page, ECC included on raw dumps
+    fn copy_record(&self, block: u32) -> Vec<u8> {
+        let page_size = self.superblock.page_size as usize;
+        let mut raw = vec![ERASED; self.raw_block_size() as usize];
+        let mut page = vec![ERASED; page_size];
+        page[..4].copy_from_slice(&(block | COPY_PENDING).to_le_bytes());
+        raw[..page_size].copy_from_slice(&page);
+        let spare_size = self.raw_page_size as usize - page_size;
+        if spare_size > 0 {
+            raw[page_size..page_size + spare_size]
+                .copy_from_slice(&ecc_calculate_page(&page, spare_size));
+        }
+        raw
+    }
+
+    // Write raw page data at `offset`, one erase block at a time through the backup
+    // blocks. Blocks whose contents would not change are skipped.
+    pub(crate) fn write_raw_pages(&mut self, offset: u64, raw: &[u8]) -> io::Result<()> {
+        if !self.has_backup_blocks() {
+            self.file.seek(SeekFrom::Start(offset))?;
+            return self.file.write_all(raw);
+        }
+        if let Some(pending) = self.pending_copy {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "Block {} has an unfinished copy from an interrupted write; roll it forward or back first",
+                    pending.block
+                ),
+            ));
+        }
+
+        let block_size = self.raw_block_size();
+        let mut written = 0;
+        while written < raw.len() {
+            let position = offset + written as u64;
+            let block = (position / block_size) as u32;
+            let start = (position % block_size) as usize;
+            let len = (block_size as usize - start).min(raw.len() - written);
+
+            let old = self.read_raw_block(block)?;
+            let mut new = old.clone();
+            new[start..start + len].copy_from_slice(&raw[written..written + len]);
+            written += len;
+            if new == old {
+                continue;
+            }
+            if self.is_backup_block(block) {
+                self.write_raw_block(block, &new)?;
+                continue;
+            }
+
+            let record = self.copy_record(block);
+            self.write_block_durably(self.superblock.backup_block1, &new)?;
+            self.write_block_durably(self.superblock.backup_block2, &record)?;
+            self.write_block_durably(block, &new)?;
+            self.erase_block(self.superblock.backup_block2)?;
+        }
+        Ok(())
+    }
+
+    // Settle a copy left behind by an interrupted write, then reload the FAT since
+    // the block may have held part of it
+    pub fn recover_block_copy(&mut self, recovery: BlockRecovery) -> io::Result<()> {
+        let Some(pending) = self.pending_copy else {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                "No block copy is pending",
+            ));
+        };
+        match recovery {
+            BlockRecovery::RollForward => {
+                let data = self.read_raw_block(self.superblock.backup_block1)?;
+                self.write_block_durably(pending.block, &data)?;
+            }
+            BlockRecovery::RollBack if !pending.target_complete => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!(
+                        "Block {} may be partly rewritten and can only be rolled forward",
+                        pending.block
+                    ),
+                ));
+            }
+            BlockRecovery::RollBack => {}
+        }
+        self.erase_block(self.superblock.backup_block2)?;
+        self.pending_copy = None;
+        self.reload_fat()
+    }
+}
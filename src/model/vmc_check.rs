@@ -0,0 +1,612 @@
+// Filesystem consistency check. Every directory is walked from the root and every
+// chain is followed through the FAT, recording which entry owns each cluster, so
+// clusters reached twice (cross-links), chains that loop or leave the FAT, and
+// allocated clusters nobody owns (orphans) can all be reported.
+//
+// repair() walks the card the same way and records a fix next to every issue: bad
+// chains are cut at their last usable cluster, lengths are clamped to what the chains
+// hold, and orphaned chains are filed under LOST.FOUND as FILEnnnn.CHK. Data in bad
+// blocks has no fix: it is reported for information only and does not make a card
+// unclean, since the save can still be read and is best copied off the card.
+
+use crate::model::vmc_backup::SyncData;
+use crate::model::vmc_core_model::{
+    DIR_ENTRY_SIZE, EM_EXISTS, FAT_CHAIN_END, FAT_FREE, INVALID_CLUSTER_PTR, RawFSEntry, Vmc,
+    fat_flag, fat_next, parse_fs_entry_from_bytes,
+};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+
+// Directory in the root that receives unreachable chains
+pub const LOST_FOUND_DIR: &str = "LOST.FOUND";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CheckIssue {
+    // A cluster belongs to the chains of two different entries
+    CrossLinked {
+        cluster: u32,
+        first: String,
+        second: String,
+    },
+    // The chain returns to a cluster it already passed through
+    ChainLoop {
+        path: String,
+        cluster: u32,
+    },
+    // The FAT entry of `cluster` points past the end of the FAT
+    ChainOutOfFat {
+        path: String,
+        cluster: u32,
+    },
+    // The chain continues into a cluster the FAT marks as free
+    ChainIntoFreeCluster {
+        path: String,
+        cluster: u32,
+    },
+    // The entry or its chain uses a cluster at or past max_allocatable_clusters
+    ClusterOutOfRange {
+        path: String,
+        cluster: u32,
+    },
+    // A directory claims more entries than its chain can hold, or fewer than "." and ".."
+    DirLengthMismatch {
+        path: String,
+        length: u32,
+        capacity: u32,
+    },
+    // A file is longer than the clusters of its chain
+    FileExceedsChain {
+        path: String,
+        length: u32,
+        chain_bytes: u64,
+    },
+    // Allocated in the FAT but not reachable from any directory entry
+    OrphanedClusters {
+        clusters: Vec<u32>,
+    },
+    // The chain uses a cluster in an erase block on the bad block list. Informational:
+    // repair leaves the data where it is.
+    DataInBadBlock {
+        path: String,
+        block: u32,
+    },
+}
+
+impl fmt::Display for CheckIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CheckIssue::CrossLinked {
+                cluster,
+                first,
+                second,
+            } => write!(
+                f,
+                "cluster {cluster} is cross-linked between '{first}' and '{second}'"
+            ),
+            CheckIssue::ChainLoop { path, cluster } => {
+                write!(f, "'{path}': chain loops back to cluster {cluster}")
+            }
+            CheckIssue::ChainOutOfFat { path, cluster } => write!(
+                f,
+                "'{path}': chain runs past the end of the FAT after cluster {cluster}"
+            ),
+            CheckIssue::ChainIntoFreeCluster { path, cluster } => {
+                write!(f, "'{path}': chain continues into free cluster {cluster}")
+            }
+            CheckIssue::ClusterOutOfRange { path, cluster } => write!(
+                f,
+                "'{path}': cluster {cluster} is past the last allocatable cluster"
+            ),
+            CheckIssue::DirLengthMismatch {
+                path,
+                length,
+                capacity,
+            } => write!(
+                f,
+                "'{path}': directory claims {length} entries, its chain holds {capacity}"
+            ),
+            CheckIssue::FileExceedsChain {
+                path,
+                length,
+                chain_bytes,
+            } => write!(
+                f,
+                "'{path}': file is {length} bytes, its chain only holds {chain_bytes}"
+            ),
+            CheckIssue::DataInBadBlock { path, block } => {
+                write!(f, "'{path}': data lies in bad block {block}")
+            }
+            CheckIssue::OrphanedClusters { clusters } => write!(
+                f,
+                "{} allocated cluster(s) not used by any entry, starting at {}",
+                clusters.len(),
+                clusters[0]
+            ),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CheckReport {
+    pub directories: usize,
+    pub files: usize,
+    pub clusters_in_use: u32,
+    // Blocks on the superblock's bad block list; listing them is not an issue
+    pub bad_blocks: Vec<u32>,
+    pub issues: Vec<CheckIssue>,
+}
+
+impl CheckIssue {
+    // Issues that are worth knowing about but that repair() does not fix
+    pub fn is_informational(&self) -> bool {
+        matches!(self, CheckIssue::DataInBadBlock { .. })
+    }
+}
+
+impl CheckReport {
+    pub fn is_clean(&self) -> bool {
+        self.problems().is_empty()
+    }
+
+    // The issues that are not informational
+    pub fn problems(&self) -> Vec<&CheckIssue> {
+        self.issues
+            .iter()
+            .filter(|i| !i.is_informational())
+            .collect()
+    }
+}
+
+// What check --repair changed, and how the card looks afterwards
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RepairReport {
+    // Issues found before anything was changed
+    pub found: CheckReport,
+    pub truncated_chains: usize,
+    pub fixed_lengths: usize,
+    // Entries whose first cluster was unusable: files are emptied, directories removed
+    pub detached_entries: usize,
+    pub freed_clusters: usize,
+    // Files created under LOST.FOUND for unreachable chains
+    pub recovered: Vec<String>,
+    // Result of checking the card again after the repair
+    pub remaining: CheckReport,
+}
+
+// A change repair() makes for an issue, recorded during the read-only walk.
+// Entries are addressed by the first cluster of their directory and their slot index.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Fix {
+    // Make the cluster the last one of its chain
+    EndChain(u32),
+    SetLength {
+        dir_cluster: u32,
+        index: usize,
+        length: u32,
+    },
+    Detach {
+        dir_cluster: u32,
+        index: usize,
+    },
+}
+
+// State of one check run: the owner of every cluster seen so far
+struct Checker {
+    owners: HashMap<u32, String>,
+    report: CheckReport,
+    fixes: Vec<Fix>,
+}
+
+impl Checker {
+    // Claim the clusters of a chain for `path`. Returns how many clusters come before
+    // the first one already owned by another entry (the whole chain if none is).
+    fn claim(&mut self, path: &str, chain: &[u32]) -> usize {
+        let mut exclusive = chain.len();
+        for (i, &cluster) in chain.iter().enumerate() {
+            match self.owners.get(&cluster) {
+                Some(owner) => {
+                    self.report.issues.push(CheckIssue::CrossLinked {
+                        cluster,
+                        first: owner.clone(),
+                        second: path.to_string(),
+                    });
+                    exclusive = exclusive.min(i);
+                }
+                None => {
+                    self.owners.insert(cluster, path.to_string());
+                }
+            }
+        }
+        exclusive
+    }
+
+    // Cut a chain down to its first `keep` clusters, detaching the entry at
+    // `location` when nothing is left
+    fn cut_chain(&mut self, chain: &[u32], keep: usize, location: (u32, usize)) {
+        let (dir_cluster, index) = location;
+        match keep {
+            0 => self.fixes.push(Fix::Detach { dir_cluster, index }),
+            _ => self.fixes.push(Fix::EndChain(chain[keep - 1])),
+        }
+    }
+}
+
+impl<B: Read + Seek> Vmc<B> {
+    // Follow a chain like build_cluster_chain, but report how it ends badly instead of
+    // stopping silently. The returned chain only holds clusters that can be read; the
+    // flag tells whether it ended with a proper end-of-chain marker.
+    fn trace_chain(
+        &self,
+        path: &str,
+        start: u32,
+        issues: &mut Vec<CheckIssue>,
+    ) -> (Vec<u32>, bool) {
+        let fat = &self.fat.fat;
+        let limit = (self.superblock.max_allocatable_clusters as usize).min(fat.len());
+        let mut chain = Vec::new();
+        let mut seen = HashSet::new();
+        let mut current = start;
+        loop {
+            if current as usize >= limit {
+                issues.push(CheckIssue::ClusterOutOfRange {
+                    path: path.to_string(),
+                    cluster: current,
+                });
+                break;
+            }
+            if !seen.insert(current) {
+                issues.push(CheckIssue::ChainLoop {
+                    path: path.to_string(),
+                    cluster: current,
+                });
+                break;
+            }
+            chain.push(current);
+
+            let raw_entry = fat[current as usize];
+            if raw_entry == INVALID_CLUSTER_PTR {
+                return (chain, true);
+            }
+            if fat_flag(raw_entry) & 0x80 == 0 {
+                issues.push(CheckIssue::ChainIntoFreeCluster {
+                    path: path.to_string(),
+                    cluster: current,
+                });
+                break;
+            }
+            let next = fat_next(raw_entry);
+            if next as usize >= fat.len() {
+                issues.push(CheckIssue::ChainOutOfFat {
+                    path: path.to_string(),
+                    cluster: current,
+                });
+                break;
+            }
+            current = next;
+        }
+        (chain, false)
+    }
+
+    fn report_bad_blocks(&self, path: &str, chain: &[u32], issues: &mut Vec<CheckIssue>) {
+        let clusters_per_block = self.superblock.clusters_per_block();
+        let mut last_block = None;
+        for &cluster in chain.iter().filter(|&&c| self.is_bad_cluster(c)) {
+            let block = (self.superblock.alloc_offset + cluster) / clusters_per_block;
+            if last_block != Some(block) {
+                issues.push(CheckIssue::DataInBadBlock {
+                    path: path.to_string(),
+                    block,
+                });
+                last_block = Some(block);
+            }
+        }
+    }
+
+    // The first `count` entries stored in a directory chain
+    fn read_chain_entries(&mut self, chain: &[u32], count: usize) -> io::Result<Vec<RawFSEntry>> {
+        let mut entries = Vec::with_capacity(count);
+        for &cluster in chain {
+            let data = self.read_cluster(cluster)?;
+            for bytes in data.chunks_exact(DIR_ENTRY_SIZE) {
+                if entries.len() == count {
+                    return Ok(entries);
+                }
+                entries.push(parse_fs_entry_from_bytes(bytes).ok_or_else(|| {
+                    io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        "Failed to parse directory entry",
+                    )
+                })?);
+            }
+        }
+        Ok(entries)
+    }
+
+    // Walk the whole card and report every inconsistency between the FAT and the
+    // directory tree. Nothing is modified.
+    pub fn check(&mut self) -> io::Result<CheckReport> {
+        Ok(self.scan()?.report)
+    }
+
+    fn scan(&mut self) -> io::Result<Checker> {
+        let mut checker = Checker {
+            owners: HashMap::new(),
+            report: CheckReport::default(),
+            fixes: Vec::new(),
+        };
+        let cluster_size = self.superblock.cluster_size as u64;
+        let entries_per_cluster = cluster_size as usize / DIR_ENTRY_SIZE;
+
+        // The root's "." entry holds its entry count
+        let root = self.superblock.rootdir_cluster;
+        if root >= self.superblock.max_allocatable_clusters || root as usize >= self.fat.fat.len() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "Root directory cluster is past the allocatable area",
+            ));
+        }
+        let root_entry = self.read_chain_entries(&[root], 1)?[0];
+
+        // (path, first cluster, entry count, slot holding the count) of directories
+        // still to visit
+        let mut pending = vec![(String::new(), root, root_entry.length, (root, 0))];
+        while let Some((path, cluster, length, location)) = pending.pop() {
+            let display_path = if path.is_empty() { "/" } else { &path };
+            let (chain, ended) =
+                self.trace_chain(display_path, cluster, &mut checker.report.issues);
+            checker.report.directories += 1;
+            let keep = checker.claim(display_path, &chain);
+            if !ended || keep < chain.len() {
+                checker.cut_chain(&chain, keep, location);
+            }
+            if keep == 0 {
+                continue;
+            }
+            self.report_bad_blocks(display_path, &chain[..keep], &mut checker.report.issues);
+
+            // Only the clusters owned by this directory are read
+            let capacity = (keep * entries_per_cluster) as u32;
+            if length < 2 || length > capacity {
+                checker.report.issues.push(CheckIssue::DirLengthMismatch {
+                    path: display_path.to_string(),
+                    length,
+                    capacity,
+                });
+                // With 512-byte clusters a one-cluster directory cannot even hold "."
+                // and "..". No length fits, so the issue is left in the report as one
+                // repair cannot fix.
+                if capacity >= 2 {
+                    checker.fixes.push(Fix::SetLength {
+                        dir_cluster: location.0,
+                        index: location.1,
+                        length: length.clamp(2, capacity),
+                    });
+                }
+            }
+
+            let entries = self.read_chain_entries(&chain[..keep], length.min(capacity) as usize)?;
+            let mut subdirs = Vec::new();
+            for (index, entry) in entries.iter().enumerate().skip(2) {
+                if !entry.exists() {
+                    continue;
+                }
+                let child = if path.is_empty() {
+                    entry.name_str()
+                } else {
+                    format!("{path}/{}", entry.name_str())
+                };
+                let location = (cluster, index);
+
+                if entry.is_dir() {
+                    subdirs.push((child, entry.cluster, entry.length, location));
+                    continue;
+                }
+
+                checker.report.files += 1;
+                if entry.cluster == INVALID_CLUSTER_PTR {
+                    if entry.length > 0 {
+                        checker.report.issues.push(CheckIssue::FileExceedsChain {
+                            path: child,
+                            length: entry.length,
+                            chain_bytes: 0,
+                        });
+                        checker.fixes.push(Fix::SetLength {
+                            dir_cluster: cluster,
+                            index,
+                            length: 0,
+                        });
+                    }
+                    continue;
+                }
+                let (file_chain, ended) =
+                    self.trace_chain(&child, entry.cluster, &mut checker.report.issues);
+                let chain_bytes = file_chain.len() as u64 * cluster_size;
+                if entry.length as u64 > chain_bytes {
+                    checker.report.issues.push(CheckIssue::FileExceedsChain {
+                        path: child.clone(),
+                        length: entry.length,
+                        chain_bytes,
+                    });
+                }
+
+                let keep = checker.claim(&child, &file_chain);
+                self.report_bad_blocks(&child, &file_chain[..keep], &mut checker.report.issues);
+                if !ended || keep < file_chain.len() {
+                    checker.cut_chain(&file_chain, keep, location);
+                }
+                let kept_bytes = keep as u64 * cluster_size;
+                if keep > 0 && entry.length as u64 > kept_bytes {
+                    checker.fixes.push(Fix::SetLength {
+                        dir_cluster: cluster,
+                        index,
+                        length: kept_bytes as u32,
+                    });
+                }
+            }
+            // Keep the walk depth-first in directory order
+            pending.extend(subdirs.into_iter().rev());
+        }
+
+        let limit = (self.superblock.max_allocatable_clusters as usize).min(self.fat.fat.len());
+        let orphans: Vec<u32> = (0..limit as u32)
+            .filter(|c| fat_flag(self.fat.fat[*c as usize]) & 0x80 != 0)
+            .filter(|c| !checker.owners.contains_key(c))
+            .collect();
+        if !orphans.is_empty() {
+            checker
+                .report
+                .issues
+                .push(CheckIssue::OrphanedClusters { clusters: orphans });
+        }
+
+        checker.report.clusters_in_use = checker.owners.len() as u32;
+        checker.report.bad_blocks = self.superblock.bad_blocks();
+        Ok(checker)
+    }
+}
+
+impl<B: Read + Write + Seek + SyncData> Vmc<B> {
+    // Split orphaned clusters into chains, ending each one where it stops being an
+    // orphan, and free clusters that only form loops. Returns (first cluster, length)
+    // of every chain and the number of freed clusters.
+    fn collect_orphan_chains(&mut self, orphans: &[u32]) -> (Vec<(u32, usize)>, usize) {
+        let orphan_set: HashSet<u32> = orphans.iter().copied().collect();
+        let successor = |fat: &[u32], cluster: u32| {
+            let raw_entry = fat[cluster as usize];
+            let next = fat_next(raw_entry);
+            (raw_entry != FAT_CHAIN_END && orphan_set.contains(&next)).then_some(next)
+        };
+        let pointed_to: HashSet<u32> = orphans
+            .iter()
+            .filter_map(|&c| successor(&self.fat.fat, c))
+            .collect();
+
+        let mut visited = HashSet::new();
+        let mut chains = Vec::new();
+        for &head in orphans.iter().filter(|c| !pointed_to.contains(c)) {
+            let mut last = head;
+            let mut count = 1;
+            visited.insert(head);
+            while let Some(next) = successor(&self.fat.fat, last) {
+                if !visited.insert(next) {
+                    break;
+                }
+                last = next;
+                count += 1;
+            }
+            self.fat.fat[last as usize] = FAT_CHAIN_END;
+            chains.push((head, count));
+        }
+
+        let mut freed = 0;
+        for &cluster in orphans.iter().filter(|c| !visited.contains(c)) {
+            self.fat.fat[cluster as usize] = FAT_FREE;
+            freed += 1;
+        }
+        (chains, freed)
+    }
+
+    // Next free FILEnnnn.CHK name in LOST.FOUND
+    fn lost_found_names(&mut self, count: usize) -> io::Result<Vec<String>> {
+        let existing: HashSet<String> = match self.read_dir(LOST_FOUND_DIR) {
+            Ok(entries) => entries.into_iter().map(|e| e.name).collect(),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                self.create_dir(LOST_FOUND_DIR)?;
+                HashSet::new()
+            }
+            Err(e) => return Err(e),
+        };
+        Ok((0..)
+            .map(|n| format!("FILE{n:04}.CHK"))
+            .filter(|name| !existing.contains(name))
+            .take(count)
+            .collect())
+    }
+
+    // Fix everything check() reports. The untouched image is first copied to `backup`
+    // and synced before the card is touched, so a repair that makes things worse can
+    // always be undone, even after a crash.
+    pub fn repair<W: Write + SyncData>(&mut self, backup: &mut W) -> io::Result<RepairReport> {
+        self.file.seek(SeekFrom::Start(0))?;
+        io::copy(&mut self.file, backup)?;
+        backup.flush()?;
+        backup.sync_data()?;
+
+        let checker = self.scan()?;
+        let mut report = RepairReport {
+            found: checker.report,
+            ..RepairReport::default()
+        };
+
+        // Chains first, so directory slots are then found through the repaired FAT
+        let mut ends: Vec<u32> = checker
+            .fixes
+            .iter()
+            .filter_map(|fix| match fix {
+                Fix::EndChain(cluster) => Some(*cluster),
+                _ => None,
+            })
+            .collect();
+        ends.sort_unstable();
+        ends.dedup();
+        for &cluster in &ends {
+            self.fat.fat[cluster as usize] = FAT_CHAIN_END;
+        }
+        report.truncated_chains = ends.len();
+
+        let orphans = report
+            .found
+            .issues
+            .iter()
+            .find_map(|issue| match issue {
+                CheckIssue::OrphanedClusters { clusters } => Some(clusters.clone()),
+                _ => None,
+            })
+            .unwrap_or_default();
+        let (lost_chains, freed) = self.collect_orphan_chains(&orphans);
+        report.freed_clusters = freed;
+        self.flush_fat()?;
+
+        for fix in &checker.fixes {
+            match *fix {
+                Fix::EndChain(_) => {}
+                Fix::SetLength {
+                    dir_cluster,
+                    index,
+                    length,
+                } => {
+                    let mut entry = self.read_dir_entry(dir_cluster, index)?;
+                    entry.length = length;
+                    self.write_dir_entry(dir_cluster, index, &entry)?;
+                    report.fixed_lengths += 1;
+                }
+                Fix::Detach { dir_cluster, index } => {
+                    let mut entry = self.read_dir_entry(dir_cluster, index)?;
+                    if entry.is_dir() {
+                        entry.mode &= !EM_EXISTS;
+                    } else {
+                        entry.cluster = INVALID_CLUSTER_PTR;
+                        entry.length = 0;
+                    }
+                    self.write_dir_entry(dir_cluster, index, &entry)?;
+                    report.detached_entries += 1;
+                }
+            }
+        }
+
+        if !lost_chains.is_empty() {
+            let cluster_size = self.superblock.cluster_size as usize;
+            let names = self.lost_found_names(lost_chains.len())?;
+            for ((cluster, count), name) in lost_chains.into_iter().zip(names) {
+                let length = (count * cluster_size) as u32;
+                self.link_chain(LOST_FOUND_DIR, &name, cluster, length)?;
+                report.recovered.push(format!("{LOST_FOUND_DIR}/{name}"));
+            }
+        }
+        self.file.flush()?;
+
+        report.remaining = self.check()?;
+        Ok(report)
+    }
+}
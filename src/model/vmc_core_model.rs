@@ -1,34 +1,159 @@
-use byteorder::{LittleEndian, ReadBytesExt};
-use std::collections::HashSet;
-use std::fs::File;
-use std::io::{self, Cursor, Read, Seek, SeekFrom};
+use crate::model::save_model::{SaveDir, SaveFile};
+use crate::model::vmc_backup::{PendingBlockCopy, SyncData};
+use crate::vmc::ecc::{EccReport, ecc_calculate_page, ecc_check_page};
+use crate::vmc::icon::{self, Icon};
+use crate::vmc::{psu, psv, sjis};
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use std::collections::{BTreeSet, HashSet};
+use std::fs::{File, OpenOptions};
+use std::io::{self, Cursor, Read, Seek, SeekFrom, Write};
 use std::path::Path;
-use std::string::FromUtf8Error;
-
-const INVALID_CLUSTER_PTR: u32 = 0xFFFFFFFF;
-const EM_EXISTS: u16 = 0x8000;
-const EM_DIRECTORY: u16 = 0x0010;
-
-fn bytes_to_string(bytes: &[u8]) -> Result<String, FromUtf8Error> {
-    let s = String::from_utf8(bytes.iter().copied().take_while(|&b| b != 0).collect())?;
-    Ok(s)
+use std::time::{SystemTime, UNIX_EPOCH};
+
+pub(crate) const INVALID_CLUSTER_PTR: u32 = 0xFFFFFFFF;
+pub(crate) const EM_EXISTS: u16 = 0x8000;
+const EM_DIRECTORY: u16 = 0x0020;
+const EM_FILE: u16 = 0x0010;
+
+// FAT entry values used when allocating and freeing clusters
+pub(crate) const FAT_ALLOCATED: u32 = 0x80000000;
+pub(crate) const FAT_FREE: u32 = 0x7FFFFFFF;
+pub(crate) const FAT_CHAIN_END: u32 = 0xFFFFFFFF;
+
+// Default modes used by the PS2 browser for new entries
+pub const FILE_MODE: u16 = 0x8497;
+pub const DIR_MODE: u16 = 0x8427;
+pub const DOTDOT_MODE: u16 = 0xA426;
+
+pub(crate) const DIR_ENTRY_SIZE: usize = 512;
+// Raw hardware dumps store 16 spare (ECC) bytes after every 512-byte page
+const SPARE_SIZE: u32 = 16;
+const MAX_NAME_LEN: usize = 31;
+
+// Layout constants used when formatting a new card
+const SUPERBLOCK_MAGIC: &[u8; 28] = b"Sony PS2 Memory Card Format ";
+const SUPERBLOCK_VERSION: &[u8] = b"1.2.0.0";
+const STANDARD_PAGE_SIZE: u16 = 512;
+const STANDARD_PAGES_PER_CLUSTER: u16 = 2;
+const STANDARD_PAGES_PER_BLOCK: u16 = 16;
+const STANDARD_CLUSTERS_PER_CARD: u32 = 8192;
+const MAX_CARD_SIZE_MB: u32 = 2048;
+const FIRST_IFC_CLUSTER: u32 = 8;
+const MAX_IFC_CLUSTERS: u32 = 32;
+const CARD_TYPE_PS2: u8 = 2;
+const CARD_FLAGS_DEFAULT: u8 = 0x52;
+
+fn bytes_to_string(bytes: &[u8]) -> String {
+    sjis::decode(bytes)
 }
 
 // Helper functions for FAT entry interpretation
-fn fat_flag(raw_entry: u32) -> u8 {
+pub(crate) fn fat_flag(raw_entry: u32) -> u8 {
     ((raw_entry >> 24) & 0xFF) as u8
 }
 
-fn fat_next(raw_entry: u32) -> u32 {
+pub(crate) fn fat_next(raw_entry: u32) -> u32 {
     raw_entry & 0xFFFFFF
 }
 
+pub(crate) fn fat_is_free(raw_entry: u32) -> bool {
+    fat_flag(raw_entry) == 0x7F && fat_next(raw_entry) == 0xFFFFFF
+}
+
+fn validate_entry_name(name: &str) -> io::Result<()> {
+    if name.is_empty() || name == "." || name == ".." || name.contains('/') {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("Invalid entry name: '{name}'"),
+        ));
+    }
+    if sjis::encode(name)?.len() > MAX_NAME_LEN {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("Entry name longer than {MAX_NAME_LEN} bytes: '{name}'"),
+        ));
+    }
+    Ok(())
+}
+
+// Timestamp as stored in directory entries. The PS2 clock runs on JST (UTC+9).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Ps2Time {
+    pub sec: u8,
+    pub min: u8,
+    pub hour: u8,
+    pub day: u8,
+    pub month: u8,
+    pub year: u16,
+}
+
+impl Ps2Time {
+    pub fn now() -> Self {
+        let secs = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0)
+            + 9 * 3600;
+        Self::from_unix(secs)
+    }
+
+    // The 8-byte on-card layout: [unused, sec, min, hour, day, month, year (u16 LE)]
+    pub fn from_bytes(bytes: &[u8; 8]) -> Self {
+        Ps2Time {
+            sec: bytes[1],
+            min: bytes[2],
+            hour: bytes[3],
+            day: bytes[4],
+            month: bytes[5],
+            year: u16::from_le_bytes([bytes[6], bytes[7]]),
+        }
+    }
+
+    pub fn to_bytes(&self) -> [u8; 8] {
+        let [year_lo, year_hi] = self.year.to_le_bytes();
+        [
+            0, self.sec, self.min, self.hour, self.day, self.month, year_lo, year_hi,
+        ]
+    }
+
+    // An all-zero timestamp means the writing tool did not set one
+    pub fn is_unset(&self) -> bool {
+        *self == Ps2Time::default()
+    }
+
+    pub fn from_unix(secs: u64) -> Self {
+        let days = (secs / 86400) as i64;
+        let rem = secs % 86400;
+
+        // Civil date from days since 1970-01-01 (Howard Hinnant's algorithm)
+        let z = days + 719468;
+        let era = z.div_euclid(146097);
+        let doe = z - era * 146097;
+        let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+        let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+        let mp = (5 * doy + 2) / 153;
+        let day = (doy - (153 * mp + 2) / 5 + 1) as u8;
+        let month = if mp < 10 { mp + 3 } else { mp - 9 } as u8;
+        let year = (yoe + era * 400 + if month <= 2 { 1 } else { 0 }) as u16;
+
+        Ps2Time {
+            sec: (rem % 60) as u8,
+            min: ((rem / 60) % 60) as u8,
+            hour: (rem / 3600) as u8,
+            day,
+            month,
+            year,
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct VmcSuperblock {
     pub magic: String,
     pub version: String,
     pub page_size: i16,
     pub pages_per_cluster: u16,
+    pub pages_per_block: u16,
     pub cluster_size: u32,
     pub clusters_per_card: u32,
     pub alloc_offset: u32,
@@ -151,6 +276,143 @@ pub fn parse_fs_entry_from_bytes(bytes: &[u8]) -> Option<RawFSEntry> {
     })
 }
 
+impl RawFSEntry {
+    pub fn new(name: &str, mode: u16, length: u32, cluster: u32, time: Ps2Time) -> Self {
+        let name_buf = sjis::encode_field(name, MAX_NAME_LEN + 1)
+            .try_into()
+            .unwrap();
+
+        let mut entry = RawFSEntry {
+            mode,
+            _pad1: 0,
+            length,
+            created_sec: 0,
+            created_min: 0,
+            created_hour: 0,
+            created_day: 0,
+            created_month: 0,
+            _pad2: 0,
+            created_year: 0,
+            cluster,
+            dir_entry: 0,
+            modified_sec: 0,
+            modified_min: 0,
+            modified_hour: 0,
+            modified_day: 0,
+            modified_month: 0,
+            _pad3: 0,
+            modified_year: 0,
+            attr: 0,
+            _pad4: [0; 28],
+            name: name_buf,
+            _pad5: [0; 412],
+        };
+        entry.set_created(time);
+        entry.set_modified(time);
+        entry
+    }
+
+    pub fn exists(&self) -> bool {
+        (self.mode & EM_EXISTS) != 0
+    }
+
+    pub fn is_dir(&self) -> bool {
+        (self.mode & EM_DIRECTORY) != 0
+    }
+
+    pub fn is_file(&self) -> bool {
+        (self.mode & EM_FILE) != 0
+    }
+
+    pub fn name_str(&self) -> String {
+        bytes_to_string(&self.name)
+    }
+
+    pub fn created(&self) -> Ps2Time {
+        Ps2Time {
+            sec: self.created_sec,
+            min: self.created_min,
+            hour: self.created_hour,
+            day: self.created_day,
+            month: self.created_month,
+            year: self.created_year,
+        }
+    }
+
+    pub fn modified(&self) -> Ps2Time {
+        Ps2Time {
+            sec: self.modified_sec,
+            min: self.modified_min,
+            hour: self.modified_hour,
+            day: self.modified_day,
+            month: self.modified_month,
+            year: self.modified_year,
+        }
+    }
+
+    pub fn set_created(&mut self, time: Ps2Time) {
+        self.created_sec = time.sec;
+        self.created_min = time.min;
+        self.created_hour = time.hour;
+        self.created_day = time.day;
+        self.created_month = time.month;
+        self._pad2 = time.month;
+        self.created_year = time.year;
+    }
+
+    pub fn set_modified(&mut self, time: Ps2Time) {
+        self.modified_sec = time.sec;
+        self.modified_min = time.min;
+        self.modified_hour = time.hour;
+        self.modified_day = time.day;
+        self.modified_month = time.month;
+        self._pad3 = time.month;
+        self.modified_year = time.year;
+    }
+
+    // Inverse of parse_fs_entry_from_bytes, using the same shifted date layout
+    pub fn to_bytes(&self) -> [u8; 512] {
+        let mut buf = [0u8; 512];
+        let mut cursor = Cursor::new(&mut buf[..]);
+
+        // Writes into a fixed 512-byte buffer cannot fail
+        cursor.write_u16::<LittleEndian>(self.mode).unwrap();
+        cursor.write_u16::<LittleEndian>(self._pad1).unwrap();
+        cursor.write_u32::<LittleEndian>(self.length).unwrap();
+        cursor
+            .write_all(&[
+                0,
+                self.created_sec,
+                self.created_min,
+                self.created_hour,
+                self.created_day,
+                self.created_month,
+            ])
+            .unwrap();
+        cursor.write_u16::<LittleEndian>(self.created_year).unwrap();
+        cursor.write_u32::<LittleEndian>(self.cluster).unwrap();
+        cursor.write_u32::<LittleEndian>(self.dir_entry).unwrap();
+        cursor
+            .write_all(&[
+                0,
+                self.modified_sec,
+                self.modified_min,
+                self.modified_hour,
+                self.modified_day,
+                self.modified_month,
+            ])
+            .unwrap();
+        cursor
+            .write_u16::<LittleEndian>(self.modified_year)
+            .unwrap();
+        cursor.write_u32::<LittleEndian>(self.attr).unwrap();
+        cursor.write_all(&self._pad4).unwrap();
+        cursor.write_all(&self.name).unwrap();
+        cursor.write_all(&self._pad5).unwrap();
+        buf
+    }
+}
+
 impl VmcSuperblock {
     pub fn from_reader<R: Read>(reader: &mut R) -> io::Result<Self> {
         let mut buf = [0u8; 384];
@@ -159,7 +421,7 @@ impl VmcSuperblock {
 
         let mut magic_buf = [0u8; 28];
         cursor.read_exact(&mut magic_buf)?;
-        if &magic_buf[..] != b"Sony PS2 Memory Card Format " {
+        if &magic_buf != SUPERBLOCK_MAGIC {
             return Err(io::Error::new(
                 io::ErrorKind::InvalidData,
                 "Magic string tidak valid",
@@ -172,8 +434,10 @@ impl VmcSuperblock {
         cursor.seek(SeekFrom::Start(0x28))?;
         let page_size = cursor.read_i16::<LittleEndian>()?;
         let pages_per_cluster = cursor.read_u16::<LittleEndian>()?;
+        let pages_per_block = cursor.read_u16::<LittleEndian>()?;
 
-        cursor.seek(SeekFrom::Start(0x34))?;
+        cursor.seek(SeekFrom::Start(0x30))?;
+        let clusters_per_card = cursor.read_u32::<LittleEndian>()?;
         let alloc_offset = cursor.read_u32::<LittleEndian>()?;
 
         cursor.seek(SeekFrom::Start(0x3C))?;
@@ -200,12 +464,13 @@ impl VmcSuperblock {
         let max_allocatable_clusters = cursor.read_u32::<LittleEndian>()?;
 
         Ok(VmcSuperblock {
-            magic: bytes_to_string(&magic_buf).unwrap_or_default(),
-            version: bytes_to_string(&version_buf).unwrap_or_default(),
+            magic: bytes_to_string(&magic_buf),
+            version: bytes_to_string(&version_buf),
             page_size,
             pages_per_cluster,
+            pages_per_block,
             cluster_size,
-            clusters_per_card: 65536,
+            clusters_per_card,
             alloc_offset,
             max_allocatable_clusters,
             rootdir_cluster,
@@ -217,6 +482,223 @@ impl VmcSuperblock {
             cardflags,
         })
     }
+
+    pub fn clusters_per_block(&self) -> u32 {
+        (self.pages_per_block / self.pages_per_cluster).max(1) as u32
+    }
+
+    // Unused slots of the bad block list hold 0xFFFFFFFF. Block 0 carries the
+    // superblock itself, so a zeroed list is read as empty rather than as block 0.
+    pub fn is_bad_block(&self, block: u32) -> bool {
+        block != 0 && block != INVALID_CLUSTER_PTR && self.bad_block_list.contains(&block)
+    }
+
+    pub fn bad_blocks(&self) -> Vec<u32> {
+        let mut blocks: Vec<u32> = self
+            .bad_block_list
+            .iter()
+            .copied()
+            .filter(|&b| self.is_bad_block(b))
+            .collect();
+        blocks.sort_unstable();
+        blocks.dedup();
+        blocks
+    }
+
+    // Record erase blocks to keep out of use when formatting. Only blocks wholly inside
+    // the allocatable area past the root directory can be listed; the superblock, FAT
+    // and backup blocks cannot move elsewhere.
+    pub fn set_bad_blocks(&mut self, blocks: &[u32]) -> io::Result<()> {
+        if blocks.len() > self.bad_block_list.len() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "At most {} bad blocks can be listed",
+                    self.bad_block_list.len()
+                ),
+            ));
+        }
+        let clusters_per_block = self.clusters_per_block();
+        let first_usable = self.alloc_offset + self.rootdir_cluster + 1;
+        let end = self.alloc_offset + self.max_allocatable_clusters;
+        for &block in blocks {
+            let start = block as u64 * clusters_per_block as u64;
+            if start < first_usable as u64 || start + clusters_per_block as u64 > end as u64 {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("Block {block} is outside the allocatable area"),
+                ));
+            }
+        }
+        self.bad_block_list = [INVALID_CLUSTER_PTR; 32];
+        self.bad_block_list[..blocks.len()].copy_from_slice(blocks);
+        Ok(())
+    }
+
+    // Size in bytes of a plain card image (without ECC spare areas)
+    pub fn card_size(&self) -> u64 {
+        self.clusters_per_card as u64 * self.cluster_size as u64
+    }
+
+    // Reject geometry that would make the offset math below meaningless and work out
+    // the on-disk page size: plain images store bare pages, raw dumps add a spare area
+    pub fn raw_page_size(&self, file_len: u64) -> io::Result<u32> {
+        let invalid = |msg: String| Err(io::Error::new(io::ErrorKind::InvalidData, msg));
+
+        if self.page_size <= 0 || self.pages_per_cluster == 0 || self.pages_per_block == 0 {
+            return invalid(format!(
+                "Invalid page geometry: page_size {}, pages_per_cluster {}, pages_per_block {}",
+                self.page_size, self.pages_per_cluster, self.pages_per_block
+            ));
+        }
+        if self.cluster_size != self.page_size as u32 * self.pages_per_cluster as u32 {
+            return invalid(format!(
+                "Cluster size {} does not match {} pages of {} bytes",
+                self.cluster_size, self.pages_per_cluster, self.page_size
+            ));
+        }
+        if self.clusters_per_card == 0 || self.alloc_offset >= self.clusters_per_card {
+            return invalid(format!(
+                "Invalid card size: {} clusters, allocation offset {}",
+                self.clusters_per_card, self.alloc_offset
+            ));
+        }
+
+        let page_size = self.page_size as u32;
+        let pages_per_card = self.clusters_per_card as u64 * self.pages_per_cluster as u64;
+        if file_len == self.card_size() {
+            Ok(page_size)
+        } else if file_len == pages_per_card * (page_size + SPARE_SIZE) as u64 {
+            Ok(page_size + SPARE_SIZE)
+        } else {
+            invalid(format!(
+                "File size {file_len} does not match card size {} ({} clusters, with or without ECC)",
+                self.card_size(),
+                self.clusters_per_card
+            ))
+        }
+    }
+
+    // Geometry the BIOS uses when formatting a card of `clusters_per_card` 1 KB clusters:
+    // indirect FAT at cluster 8, FAT right after it, and the last two erase blocks kept
+    // as backup blocks.
+    pub fn for_card(clusters_per_card: u32) -> Self {
+        let cluster_size = STANDARD_PAGE_SIZE as u32 * STANDARD_PAGES_PER_CLUSTER as u32;
+        let clusters_per_block = (STANDARD_PAGES_PER_BLOCK / STANDARD_PAGES_PER_CLUSTER) as u32;
+        let entries_per_cluster = cluster_size / 4;
+        let blocks_per_card = clusters_per_card / clusters_per_block;
+
+        let allocatable =
+            (clusters_per_card - (FIRST_IFC_CLUSTER + 2)) / clusters_per_block * clusters_per_block;
+        let mut fat_clusters = allocatable.div_ceil(entries_per_cluster);
+        let mut ifc_clusters = fat_clusters.div_ceil(entries_per_cluster);
+        if ifc_clusters > MAX_IFC_CLUSTERS {
+            ifc_clusters = MAX_IFC_CLUSTERS;
+            fat_clusters = ifc_clusters * entries_per_cluster;
+        }
+
+        let alloc_offset = FIRST_IFC_CLUSTER + ifc_clusters + fat_clusters;
+        let max_allocatable_clusters = (blocks_per_card - 2) * clusters_per_block - alloc_offset;
+
+        let mut ifc_ptr_list = [0u32; 32];
+        for (i, ptr) in ifc_ptr_list
+            .iter_mut()
+            .take(ifc_clusters as usize)
+            .enumerate()
+        {
+            *ptr = FIRST_IFC_CLUSTER + i as u32;
+        }
+
+        VmcSuperblock {
+            magic: bytes_to_string(SUPERBLOCK_MAGIC),
+            version: bytes_to_string(SUPERBLOCK_VERSION),
+            page_size: STANDARD_PAGE_SIZE as i16,
+            pages_per_cluster: STANDARD_PAGES_PER_CLUSTER,
+            pages_per_block: STANDARD_PAGES_PER_BLOCK,
+            cluster_size,
+            clusters_per_card,
+            alloc_offset,
+            max_allocatable_clusters,
+            rootdir_cluster: 0,
+            backup_block1: blocks_per_card - 1,
+            backup_block2: blocks_per_card - 2,
+            ifc_ptr_list,
+            bad_block_list: [INVALID_CLUSTER_PTR; 32],
+            cardtype: CARD_TYPE_PS2,
+            cardflags: CARD_FLAGS_DEFAULT,
+        }
+    }
+
+    // Serialize into the first page of the card, mirroring the offsets read by from_reader
+    pub fn to_bytes(&self) -> [u8; 512] {
+        let mut buf = [0u8; 512];
+        let mut cursor = Cursor::new(&mut buf[..]);
+        let clusters_per_block = (self.pages_per_block / self.pages_per_cluster.max(1)) as u32;
+
+        // Writes into a fixed 512-byte buffer cannot fail
+        cursor.write_all(SUPERBLOCK_MAGIC).unwrap();
+        cursor.write_all(self.version.as_bytes()).unwrap();
+
+        cursor.seek(SeekFrom::Start(0x28)).unwrap();
+        cursor.write_i16::<LittleEndian>(self.page_size).unwrap();
+        cursor
+            .write_u16::<LittleEndian>(self.pages_per_cluster)
+            .unwrap();
+        cursor
+            .write_u16::<LittleEndian>(self.pages_per_block)
+            .unwrap();
+        cursor.write_u16::<LittleEndian>(0xFF00).unwrap();
+        cursor
+            .write_u32::<LittleEndian>(self.clusters_per_card)
+            .unwrap();
+        cursor.write_u32::<LittleEndian>(self.alloc_offset).unwrap();
+        cursor
+            .write_u32::<LittleEndian>(self.max_allocatable_clusters)
+            .unwrap();
+        cursor
+            .write_u32::<LittleEndian>(self.rootdir_cluster)
+            .unwrap();
+        cursor
+            .write_u32::<LittleEndian>(self.backup_block1)
+            .unwrap();
+        cursor
+            .write_u32::<LittleEndian>(self.backup_block2)
+            .unwrap();
+
+        cursor.seek(SeekFrom::Start(0x50)).unwrap();
+        for &ifc in &self.ifc_ptr_list {
+            cursor.write_u32::<LittleEndian>(ifc).unwrap();
+        }
+        for &bad_block in &self.bad_block_list {
+            cursor.write_u32::<LittleEndian>(bad_block).unwrap();
+        }
+
+        cursor.seek(SeekFrom::Start(0x150)).unwrap();
+        cursor.write_u8(self.cardtype).unwrap();
+        cursor.write_u8(self.cardflags).unwrap();
+
+        cursor.seek(SeekFrom::Start(0x154)).unwrap();
+        cursor.write_u32::<LittleEndian>(self.cluster_size).unwrap();
+        cursor
+            .write_u32::<LittleEndian>(self.cluster_size / 4)
+            .unwrap();
+        cursor
+            .write_u32::<LittleEndian>(clusters_per_block)
+            .unwrap();
+        cursor.write_i32::<LittleEndian>(-1).unwrap(); // cardform
+        cursor
+            .write_u32::<LittleEndian>(self.rootdir_cluster)
+            .unwrap();
+        cursor.write_u32::<LittleEndian>(0x200).unwrap();
+        cursor.write_u32::<LittleEndian>(1).unwrap();
+        cursor
+            .write_u32::<LittleEndian>(self.max_allocatable_clusters)
+            .unwrap();
+        cursor.write_u32::<LittleEndian>(0).unwrap();
+        cursor.write_u32::<LittleEndian>(0).unwrap();
+        cursor.write_i32::<LittleEndian>(-1).unwrap();
+        buf
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -251,32 +733,12 @@ impl FSEntry {
         }
 
         let name_bytes = raw.name;
-        let name = bytes_to_string(&name_bytes).unwrap_or_default();
+        let name = bytes_to_string(&name_bytes);
         if name.is_empty() {
             return None;
         }
 
-        // Better directory detection logic for VMC files
-        // 0x8427 is definitely a directory (for . and ..)
-        // 0x8497 can be either file or directory - need to check the name
-        let is_directory = if mode_val == 0x8427 {
-            true
-        } else if mode_val == 0x8497 {
-            // For 0x8497, determine by name patterns
-            // Directories usually don't have file extensions or are special names
-            name == "."
-                || name == ".."
-                || (!name.contains('.')
-                    && !name
-                        .chars()
-                        .all(|c| c.is_ascii_uppercase() || c.is_ascii_digit() || c == '-'))
-        } else if mode_val == 0x8417 {
-            // 0x8417 seems to be used for some files
-            false
-        } else {
-            // Other modes - use the EM_DIRECTORY flag as fallback
-            (mode_val & EM_DIRECTORY) != 0
-        };
+        let is_directory = (mode_val & EM_DIRECTORY) != 0;
 
         Some(FSEntry {
             name,
@@ -318,39 +780,334 @@ impl FSEntry {
     }
 }
 
+// icon.sys layout: magic, title line break, background and lighting, title, icon names
+pub const ICON_SYS_SIZE: usize = 964;
+const ICON_SYS_MAGIC: &[u8; 4] = b"PS2D";
+const ICON_SYS_TITLE_OFFSET: usize = 0xC0;
+pub const ICON_SYS_TITLE_SIZE: usize = 68;
+const ICON_SYS_ICON_NAMES_OFFSET: usize = 0x104;
+const ICON_SYS_ICON_NAME_SIZE: usize = 64;
+
+// The icon.sys file every save directory carries: the two-line title shown by the PS2
+// browser (Shift-JIS), the background gradient and the lighting of the 3D icon
+#[derive(Debug, Clone, PartialEq)]
+pub struct IconSys {
+    pub line_break: u16, // byte offset of the second title line
+    pub bg_transparency: u32,
+    pub bg_colors: [[u32; 4]; 4], // RGBA of the top-left, top-right, bottom-left and bottom-right corners
+    pub light_dirs: [[f32; 4]; 3],
+    pub light_colors: [[f32; 4]; 3],
+    pub ambient: [f32; 4],
+    pub title: [u8; ICON_SYS_TITLE_SIZE],
+    pub icon_file: String,
+    pub copy_icon_file: String,
+    pub delete_icon_file: String,
+}
+
+impl IconSys {
+    pub fn from_bytes(data: &[u8]) -> io::Result<Self> {
+        if data.len() < ICON_SYS_SIZE || &data[..4] != ICON_SYS_MAGIC {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "Not an icon.sys file: missing PS2D magic",
+            ));
+        }
+
+        let mut cursor = Cursor::new(&data[6..]);
+        let line_break = cursor.read_u16::<LittleEndian>()?;
+        cursor.set_position(0x0C - 6);
+        let bg_transparency = cursor.read_u32::<LittleEndian>()?;
+        let mut bg_colors = [[0u32; 4]; 4];
+        for value in bg_colors.iter_mut().flatten() {
+            *value = cursor.read_u32::<LittleEndian>()?;
+        }
+        let mut read_vectors = |count: usize| -> io::Result<Vec<[f32; 4]>> {
+            (0..count)
+                .map(|_| {
+                    let mut v = [0f32; 4];
+                    for x in &mut v {
+                        *x = cursor.read_f32::<LittleEndian>()?;
+                    }
+                    Ok(v)
+                })
+                .collect()
+        };
+        let light_dirs = read_vectors(3)?.try_into().unwrap();
+        let light_colors = read_vectors(3)?.try_into().unwrap();
+        let ambient = read_vectors(1)?[0];
+
+        let icon_name = |i: usize| {
+            let start = ICON_SYS_ICON_NAMES_OFFSET + i * ICON_SYS_ICON_NAME_SIZE;
+            bytes_to_string(&data[start..start + ICON_SYS_ICON_NAME_SIZE])
+        };
+
+        Ok(IconSys {
+            line_break,
+            bg_transparency,
+            bg_colors,
+            light_dirs,
+            light_colors,
+            ambient,
+            title: data[ICON_SYS_TITLE_OFFSET..ICON_SYS_TITLE_OFFSET + ICON_SYS_TITLE_SIZE]
+                .try_into()
+                .unwrap(),
+            icon_file: icon_name(0),
+            copy_icon_file: icon_name(1),
+            delete_icon_file: icon_name(2),
+        })
+    }
+
+    // The two title lines, split at the line break offset
+    pub fn title_lines(&self) -> (String, String) {
+        let end = self
+            .title
+            .iter()
+            .position(|&b| b == 0)
+            .unwrap_or(ICON_SYS_TITLE_SIZE);
+        let split = (self.line_break as usize).min(end);
+        let decode = |bytes: &[u8]| sjis::decode(bytes).trim().to_string();
+        (
+            decode(&self.title[..split]),
+            decode(&self.title[split..end]),
+        )
+    }
+
+    // Both title lines joined with a space, as a single-line title
+    pub fn title(&self) -> String {
+        match self.title_lines() {
+            (first, second) if second.is_empty() => first,
+            (first, second) if first.is_empty() => second,
+            (first, second) => format!("{first} {second}"),
+        }
+    }
+
+    // Replace the title. ASCII is stored full-width since the browser cannot draw
+    // single-byte characters, and the line break is set to where `second` starts.
+    pub fn set_title(&mut self, first: &str, second: &str) -> io::Result<()> {
+        let first = sjis::encode(&sjis::to_full_width(first))?;
+        let second = sjis::encode(&sjis::to_full_width(second))?;
+        // Keep room for a double-byte terminator
+        if first.len() + second.len() > ICON_SYS_TITLE_SIZE - 2 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "Title is longer than {} bytes in Shift-JIS",
+                    ICON_SYS_TITLE_SIZE - 2
+                ),
+            ));
+        }
+
+        self.title = [0; ICON_SYS_TITLE_SIZE];
+        self.title[..first.len()].copy_from_slice(&first);
+        self.title[first.len()..first.len() + second.len()].copy_from_slice(&second);
+        self.line_break = first.len() as u16;
+        Ok(())
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut data = Vec::with_capacity(ICON_SYS_SIZE);
+        data.extend_from_slice(ICON_SYS_MAGIC);
+        data.write_u16::<LittleEndian>(0).unwrap();
+        data.write_u16::<LittleEndian>(self.line_break).unwrap();
+        data.write_u32::<LittleEndian>(0).unwrap();
+        data.write_u32::<LittleEndian>(self.bg_transparency)
+            .unwrap();
+        for &value in self.bg_colors.iter().flatten() {
+            data.write_u32::<LittleEndian>(value).unwrap();
+        }
+        let vectors = self
+            .light_dirs
+            .iter()
+            .chain(&self.light_colors)
+            .chain([&self.ambient]);
+        for &value in vectors.flatten() {
+            data.write_f32::<LittleEndian>(value).unwrap();
+        }
+        data.extend_from_slice(&self.title);
+        for name in [
+            &self.icon_file,
+            &self.copy_icon_file,
+            &self.delete_icon_file,
+        ] {
+            data.extend_from_slice(&sjis::encode_field(name, ICON_SYS_ICON_NAME_SIZE));
+        }
+        data.resize(ICON_SYS_SIZE, 0);
+        data
+    }
+}
+
+#[derive(Default)]
 pub struct FatTable {
     pub fat: Vec<u32>,
+    pub fat_clusters: Vec<u32>,
 }
 
-pub struct Vmc {
-    pub file: File, // Made public for access from vmc_core.rs
+// A memory card image read from any seekable backend: a File by default, or e.g. a
+// Cursor<Vec<u8>> for images held in memory. Mutations need a Write backend as well.
+pub struct Vmc<B = File> {
+    pub file: B, // Made public for access from vmc_core.rs
     pub superblock: VmcSuperblock,
-    fat: FatTable,
+    pub(crate) fat: FatTable,
+    pub(crate) raw_page_size: u32,
+    pub(crate) pending_copy: Option<PendingBlockCopy>,
+    bad_block_reads: BTreeSet<u32>,
 }
 
 impl Vmc {
     pub fn new<P: AsRef<Path>>(path: P) -> io::Result<Self> {
-        let mut file = File::open(path)?;
+        Self::from_backend(File::open(path)?)
+    }
+
+    // Open the card for both reading and writing
+    pub fn open_writable<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        Self::from_backend(OpenOptions::new().read(true).write(true).open(path)?)
+    }
+
+    // Write a freshly formatted, empty 8 MB card to `path`, replacing any existing file
+    pub fn format<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        Self::format_with_size(path, 8)
+    }
+
+    // Same as format, for any card size that is a multiple of 8 MB (16, 32, 64 MB and up)
+    pub fn format_with_size<P: AsRef<Path>>(path: P, size_mb: u32) -> io::Result<Self> {
+        Self::format_with_bad_blocks(path, size_mb, &[])
+    }
+
+    // Format a card whose erase blocks `bad_blocks` are known to be worn out; they go
+    // into the superblock's bad block list and are never allocated
+    pub fn format_with_bad_blocks<P: AsRef<Path>>(
+        path: P,
+        size_mb: u32,
+        bad_blocks: &[u32],
+    ) -> io::Result<Self> {
+        check_card_size(size_mb)?;
+        let clusters_per_card = size_mb * (STANDARD_CLUSTERS_PER_CARD / 8);
+        let mut superblock = VmcSuperblock::for_card(clusters_per_card);
+        superblock.set_bad_blocks(bad_blocks)?;
+        let mut file = io::BufWriter::new(File::create(&path)?);
+        write_blank_image(&superblock, &mut file)?;
+        file.into_inner()?.sync_all()?;
+        Self::open_writable(path)
+    }
+
+    // The image of an empty card held in memory, for format_in_memory
+    fn blank_image(sb: &VmcSuperblock) -> Vec<u8> {
+        let mut image = Vec::with_capacity(sb.card_size() as usize);
+        // Writes into a Vec cannot fail
+        write_blank_image(sb, &mut image).unwrap();
+        image
+    }
+}
+
+// Stream the image of an empty card cluster by cluster: erased (0xFF) space holding the
+// superblock, the indirect FAT and FAT, and a root directory with its "." and ".."
+// entries. Nothing larger than a cluster is buffered, so a 2 GB card formats in
+// constant memory.
+fn write_blank_image<W: Write>(sb: &VmcSuperblock, out: &mut W) -> io::Result<()> {
+    let cluster_size = sb.cluster_size as usize;
+    let entries_per_cluster = cluster_size / 4;
+    let ifc_clusters: Vec<u32> = sb
+        .ifc_ptr_list
+        .iter()
+        .copied()
+        .take_while(|&ifc| ifc != 0 && ifc != INVALID_CLUSTER_PTR)
+        .collect();
+    let first_fat = FIRST_IFC_CLUSTER + ifc_clusters.len() as u32;
+    let fat_clusters = (sb.alloc_offset - first_fat) as usize;
+    let root_cluster = sb.alloc_offset + sb.rootdir_cluster;
+    let now = Ps2Time::now();
+
+    let erased = vec![0xFFu8; cluster_size];
+    let mut buf = vec![0u8; cluster_size];
+    for card_cluster in 0..sb.clusters_per_card {
+        buf.copy_from_slice(&erased);
+        if card_cluster == 0 {
+            buf[..DIR_ENTRY_SIZE].copy_from_slice(&sb.to_bytes());
+        } else if let Some(i) = ifc_clusters.iter().position(|&c| c == card_cluster) {
+            let first = i * entries_per_cluster;
+            for fat_index in first..fat_clusters.min(first + entries_per_cluster) {
+                let offset = (fat_index - first) * 4;
+                buf[offset..offset + 4]
+                    .copy_from_slice(&(first_fat + fat_index as u32).to_le_bytes());
+            }
+        } else if (first_fat..sb.alloc_offset).contains(&card_cluster) {
+            let first = (card_cluster - first_fat) as usize * entries_per_cluster;
+            for (slot, value) in buf.chunks_exact_mut(4).enumerate() {
+                let entry = if first + slot == sb.rootdir_cluster as usize {
+                    FAT_CHAIN_END
+                } else {
+                    FAT_FREE
+                };
+                value.copy_from_slice(&entry.to_le_bytes());
+            }
+        } else if card_cluster == root_cluster {
+            buf.fill(0);
+            buf[..DIR_ENTRY_SIZE]
+                .copy_from_slice(&RawFSEntry::new(".", DIR_MODE, 2, 0, now).to_bytes());
+            buf[DIR_ENTRY_SIZE..2 * DIR_ENTRY_SIZE]
+                .copy_from_slice(&RawFSEntry::new("..", DOTDOT_MODE, 0, 0, now).to_bytes());
+        }
+        out.write_all(&buf)?;
+    }
+    out.flush()
+}
+
+impl Vmc<Cursor<Vec<u8>>> {
+    // Format an empty card entirely in memory (size as in Vmc::format_with_size)
+    pub fn format_in_memory(size_mb: u32) -> io::Result<Self> {
+        Self::format_in_memory_with_bad_blocks(size_mb, &[])
+    }
+
+    pub fn format_in_memory_with_bad_blocks(size_mb: u32, bad_blocks: &[u32]) -> io::Result<Self> {
+        check_card_size(size_mb)?;
+        let mut superblock = VmcSuperblock::for_card(size_mb * (STANDARD_CLUSTERS_PER_CARD / 8));
+        superblock.set_bad_blocks(bad_blocks)?;
+        Self::from_backend(Cursor::new(Vmc::blank_image(&superblock)))
+    }
+}
+
+impl<B: Read + Seek> Vmc<B> {
+    // Open a card image from an already opened backend (file, buffer, memory map...)
+    pub fn from_backend(mut file: B) -> io::Result<Self> {
+        let file_len = file.seek(SeekFrom::End(0))?;
+        file.seek(SeekFrom::Start(0))?;
         let superblock = VmcSuperblock::from_reader(&mut file)?;
-        let fat = Self::load_fat(&mut file, &superblock)?;
-        Ok(Vmc {
+        let raw_page_size = superblock.raw_page_size(file_len)?;
+        let mut vmc = Vmc {
             file,
             superblock,
-            fat,
-        })
+            fat: FatTable::default(),
+            raw_page_size,
+            pending_copy: None,
+            bad_block_reads: BTreeSet::new(),
+        };
+        vmc.fat = vmc.load_fat()?;
+        vmc.pending_copy = vmc.read_pending_copy()?;
+        Ok(vmc)
     }
 
-    fn load_fat(file: &mut File, sb: &VmcSuperblock) -> io::Result<FatTable> {
-        let entries_per_cluster = sb.cluster_size as usize / 4;
+    // Give back the backend, e.g. to get at the bytes of an in-memory card
+    pub fn into_inner(self) -> B {
+        self.file
+    }
+
+    // True for raw dumps whose pages carry a 16-byte spare/ECC area
+    pub fn has_ecc(&self) -> bool {
+        self.raw_page_size != self.superblock.page_size as u32
+    }
+
+    fn load_fat(&mut self) -> io::Result<FatTable> {
+        let entries_per_cluster = self.superblock.cluster_size as usize / 4;
         let mut fat_cluster_ptrs = Vec::new();
 
-        for &ifc in &sb.ifc_ptr_list {
+        for ifc in self.superblock.ifc_ptr_list {
             if ifc == 0 || ifc == INVALID_CLUSTER_PTR {
                 break;
             }
-            file.seek(SeekFrom::Start(ifc as u64 * sb.cluster_size as u64))?;
+            let ifc_data = self.read_card_cluster(ifc)?;
+            let mut cursor = Cursor::new(&ifc_data);
             for _ in 0..entries_per_cluster {
-                let entry = file.read_u32::<LittleEndian>()?;
+                let entry = cursor.read_u32::<LittleEndian>()?;
                 if entry == INVALID_CLUSTER_PTR {
                     break;
                 }
@@ -360,27 +1117,45 @@ impl Vmc {
 
         let mut fat = Vec::with_capacity(fat_cluster_ptrs.len() * entries_per_cluster);
         for &fat_ptr in &fat_cluster_ptrs {
-            file.seek(SeekFrom::Start(fat_ptr as u64 * sb.cluster_size as u64))?;
+            let fat_data = self.read_card_cluster(fat_ptr)?;
+            let mut cursor = Cursor::new(&fat_data);
             for _ in 0..entries_per_cluster {
-                fat.push(file.read_u32::<LittleEndian>()?);
+                fat.push(cursor.read_u32::<LittleEndian>()?);
             }
         }
-        Ok(FatTable { fat })
+        Ok(FatTable {
+            fat,
+            fat_clusters: fat_cluster_ptrs,
+        })
     }
 
+    // Only clusters below max_allocatable_clusters can hold data; the FAT of larger
+    // layouts also has (free-looking) entries past that limit. Clusters in bad blocks
+    // are never handed out, so they do not count as free either.
     pub fn count_free_clusters(&self) -> u32 {
+        let limit = (self.superblock.max_allocatable_clusters as usize).min(self.fat.fat.len());
         let mut free_count = 0;
-        for &raw_entry in &self.fat.fat {
-            let flag = fat_flag(raw_entry);
-            let cluster = fat_next(raw_entry);
-
-            if flag == 0x7F && cluster == 0xFFFFFF {
+        for (cluster, &raw_entry) in self.fat.fat[..limit].iter().enumerate() {
+            if fat_is_free(raw_entry) && !self.is_bad_cluster(cluster as u32) {
                 free_count += 1;
             }
         }
         free_count
     }
 
+    // Whether an allocatable cluster lies in a block on the bad block list
+    pub fn is_bad_cluster(&self, cluster: u32) -> bool {
+        let card_cluster = self.superblock.alloc_offset as u64 + cluster as u64;
+        let block = card_cluster / self.superblock.clusters_per_block() as u64;
+        self.superblock.is_bad_block(block as u32)
+    }
+
+    // Bad blocks that reads have touched since the card was opened. Data read from them
+    // may be damaged even when its ECC looks fine.
+    pub fn bad_block_reads(&self) -> Vec<u32> {
+        self.bad_block_reads.iter().copied().collect()
+    }
+
     // Made this method public so it can be used from vmc_core.rs
     pub fn build_cluster_chain(&self, start_cluster: u32) -> Vec<u32> {
         let mut chain = Vec::new();
@@ -409,11 +1184,7 @@ impl Vmc {
     }
 
     pub fn list_root_directory(&mut self) -> io::Result<Vec<FSEntry>> {
-        let file_size = self.file.metadata()?.len();
-        let root_offset = (self.superblock.alloc_offset + self.superblock.rootdir_cluster) as u64
-            * self.superblock.cluster_size as u64;
-
-        if root_offset >= file_size {
+        if !self.is_cluster_on_card(self.superblock.rootdir_cluster) {
             return Err(io::Error::new(
                 io::ErrorKind::InvalidData,
                 "Root directory offset exceeds file size",
@@ -421,11 +1192,10 @@ impl Vmc {
         }
 
         // Read the root directory header to get expected entry count
-        self.file.seek(SeekFrom::Start(root_offset))?;
-        let entry_size = std::mem::size_of::<RawFSEntry>();
-        let mut header_buf = vec![0u8; entry_size];
-        self.file.read_exact(&mut header_buf)?;
-        let root_hdr: RawFSEntry = unsafe { std::ptr::read(header_buf.as_ptr() as *const _) };
+        let root_cluster = self.read_cluster(self.superblock.rootdir_cluster)?;
+        let root_hdr = parse_fs_entry_from_bytes(&root_cluster).ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidData, "Failed to parse root directory")
+        })?;
         let expected_len = root_hdr.length;
 
         let cluster_chain = self.build_cluster_chain(self.superblock.rootdir_cluster);
@@ -436,17 +1206,11 @@ impl Vmc {
         let mut read_count = 0;
 
         for &cluster in &cluster_chain {
-            let cluster_offset = (self.superblock.alloc_offset + cluster) as u64
-                * self.superblock.cluster_size as u64;
-
-            if cluster_offset >= file_size {
+            if !self.is_cluster_on_card(cluster) {
                 break;
             }
 
-            self.file.seek(SeekFrom::Start(cluster_offset))?;
-
-            let mut cluster_buf = vec![0u8; self.superblock.cluster_size as usize];
-            self.file.read_exact(&mut cluster_buf)?;
+            let cluster_buf = self.read_cluster(cluster)?;
 
             for i in 0..entries_per_cluster {
                 if read_count >= expected_len {
@@ -478,4 +1242,987 @@ impl Vmc {
 
         Ok(entries)
     }
+
+    fn is_cluster_on_card(&self, cluster: u32) -> bool {
+        (self.superblock.alloc_offset as u64 + cluster as u64)
+            < self.superblock.clusters_per_card as u64
+    }
+
+    // Read `count` pages starting at absolute page `page`. Spare areas are dropped after
+    // using their ECC to fix single-bit errors; uncorrectable pages are returned as-is.
+    fn read_pages(&mut self, page: u64, count: usize) -> io::Result<Vec<u8>> {
+        let page_size = self.superblock.page_size as usize;
+        let raw_page_size = self.raw_page_size as usize;
+        let mut raw = vec![0u8; count * raw_page_size];
+        self.file
+            .seek(SeekFrom::Start(page * raw_page_size as u64))?;
+        self.file.read_exact(&mut raw)?;
+
+        let pages_per_block = self.superblock.pages_per_block as u64;
+        for block in page / pages_per_block..(page + count as u64).div_ceil(pages_per_block) {
+            if self.superblock.is_bad_block(block as u32) {
+                self.bad_block_reads.insert(block as u32);
+            }
+        }
+
+        if raw_page_size == page_size {
+            return Ok(raw);
+        }
+
+        let mut data = Vec::with_capacity(count * page_size);
+        for raw_page in raw.chunks_mut(raw_page_size) {
+            let (page_data, spare) = raw_page.split_at_mut(page_size);
+            ecc_check_page(page_data, spare);
+            data.extend_from_slice(page_data);
+        }
+        Ok(data)
+    }
+
+    // Copy the whole card to `out` page by page, either as a plain image or as a raw
+    // dump with freshly computed ECC. Pages read from a raw dump are ECC-corrected first.
+    pub fn export_image<W: Write>(&mut self, out: &mut W, with_ecc: bool) -> io::Result<()> {
+        let page_size = self.superblock.page_size as usize;
+        let pages_per_block = self.superblock.pages_per_block as u64;
+        let pages_per_card =
+            self.superblock.clusters_per_card as u64 * self.superblock.pages_per_cluster as u64;
+
+        for block_start in (0..pages_per_card).step_by(pages_per_block as usize) {
+            let pages = pages_per_block.min(pages_per_card - block_start);
+            let data = self.read_pages(block_start, pages as usize)?;
+            if !with_ecc {
+                out.write_all(&data)?;
+                continue;
+            }
+            for page in data.chunks(page_size) {
+                out.write_all(page)?;
+                out.write_all(&ecc_calculate_page(page, SPARE_SIZE as usize))?;
+            }
+        }
+        out.flush()
+    }
+
+    // Check the ECC of every page of a raw dump without modifying it
+    pub fn verify_ecc(&mut self) -> io::Result<EccReport> {
+        if !self.has_ecc() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "Image has no ECC spare areas",
+            ));
+        }
+
+        let page_size = self.superblock.page_size as usize;
+        let raw_page_size = self.raw_page_size as usize;
+        let pages_per_block = self.superblock.pages_per_block as u64;
+        let pages_per_card =
+            self.superblock.clusters_per_card as u64 * self.superblock.pages_per_cluster as u64;
+
+        let mut report = EccReport::default();
+        let mut raw = vec![0u8; pages_per_block as usize * raw_page_size];
+        self.file.seek(SeekFrom::Start(0))?;
+        for block_start in (0..pages_per_card).step_by(pages_per_block as usize) {
+            let pages = pages_per_block.min(pages_per_card - block_start);
+            let raw = &mut raw[..pages as usize * raw_page_size];
+            self.file.read_exact(raw)?;
+            for (i, raw_page) in raw.chunks_mut(raw_page_size).enumerate() {
+                let (page_data, spare) = raw_page.split_at_mut(page_size);
+                report.add(block_start + i as u64, ecc_check_page(page_data, spare));
+            }
+        }
+        Ok(report)
+    }
+
+    // Read a cluster by absolute cluster number (superblock, indirect FAT and FAT live here)
+    pub fn read_card_cluster(&mut self, card_cluster: u32) -> io::Result<Vec<u8>> {
+        let pages_per_cluster = self.superblock.pages_per_cluster as u64;
+        self.read_pages(
+            card_cluster as u64 * pages_per_cluster,
+            pages_per_cluster as usize,
+        )
+    }
+
+    // Absolute cluster number of an allocatable cluster. Cluster numbers come from
+    // directory entries and the FAT, so a damaged card can hold any value here.
+    fn card_cluster(&self, cluster: u32) -> io::Result<u32> {
+        self.superblock
+            .alloc_offset
+            .checked_add(cluster)
+            .filter(|_| self.is_cluster_on_card(cluster))
+            .ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("Cluster {cluster:#X} lies outside the card"),
+                )
+            })
+    }
+
+    // Read a cluster of the allocatable area, as numbered in the FAT
+    pub fn read_cluster(&mut self, cluster: u32) -> io::Result<Vec<u8>> {
+        self.read_card_cluster(self.card_cluster(cluster)?)
+    }
+
+    // Throw away uncommitted allocations after a failed write
+    pub(crate) fn reload_fat(&mut self) -> io::Result<()> {
+        self.fat = self.load_fat()?;
+        Ok(())
+    }
+
+    // Cluster holding entry `index` of a directory, and the entry's byte offset inside it
+    fn dir_entry_location(&self, dir_cluster: u32, index: usize) -> io::Result<(u32, usize)> {
+        let entries_per_cluster = self.superblock.cluster_size as usize / DIR_ENTRY_SIZE;
+        let chain = self.build_cluster_chain(dir_cluster);
+        let cluster = chain.get(index / entries_per_cluster).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Directory entry {index} lies outside its cluster chain"),
+            )
+        })?;
+        Ok((*cluster, (index % entries_per_cluster) * DIR_ENTRY_SIZE))
+    }
+
+    pub(crate) fn read_dir_entry(
+        &mut self,
+        dir_cluster: u32,
+        index: usize,
+    ) -> io::Result<RawFSEntry> {
+        let (cluster, offset) = self.dir_entry_location(dir_cluster, index)?;
+        let buf = self.read_cluster(cluster)?;
+        parse_fs_entry_from_bytes(&buf[offset..offset + DIR_ENTRY_SIZE]).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                "Failed to parse directory entry",
+            )
+        })
+    }
+
+    pub(crate) fn read_dir_raw(
+        &mut self,
+        dir_cluster: u32,
+        count: usize,
+    ) -> io::Result<Vec<RawFSEntry>> {
+        (0..count)
+            .map(|i| self.read_dir_entry(dir_cluster, i))
+            .collect()
+    }
+
+    // The root directory has no entry of its own; its "." entry stands in for it
+    // and holds the root's entry count
+    fn root_location(&mut self) -> io::Result<EntryLocation> {
+        let root = self.superblock.rootdir_cluster;
+        let mut entry = self.read_dir_entry(root, 0)?;
+        entry.cluster = root;
+        Ok(EntryLocation {
+            dir_cluster: root,
+            index: 0,
+            entry,
+        })
+    }
+
+    // Resolve a '/'-separated path such as "BASLUS-21050/icon.sys". An empty path or "/"
+    // is the root directory.
+    fn lookup(&mut self, path: &str) -> io::Result<EntryLocation> {
+        let mut stack = vec![self.root_location()?];
+
+        for component in path.split('/').filter(|c| !c.is_empty() && *c != ".") {
+            if component == ".." {
+                if stack.len() > 1 {
+                    stack.pop();
+                }
+                continue;
+            }
+
+            let current = stack[stack.len() - 1].entry;
+            if !current.is_dir() {
+                return Err(io::Error::new(
+                    io::ErrorKind::NotFound,
+                    format!("'{path}': '{}' is not a directory", current.name_str()),
+                ));
+            }
+
+            let entries = self.read_dir_raw(current.cluster, current.length as usize)?;
+            let index = entries
+                .iter()
+                .enumerate()
+                .skip(2)
+                .find(|(_, e)| e.exists() && e.name_str() == component)
+                .map(|(i, _)| i)
+                .ok_or_else(|| {
+                    io::Error::new(io::ErrorKind::NotFound, format!("'{path}' not found"))
+                })?;
+
+            stack.push(EntryLocation {
+                dir_cluster: current.cluster,
+                index,
+                entry: entries[index],
+            });
+        }
+
+        Ok(stack.pop().expect("the root location is never popped"))
+    }
+
+    fn lookup_dir(&mut self, path: &str) -> io::Result<EntryLocation> {
+        let location = self.lookup(path)?;
+        if !location.entry.is_dir() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("'{path}' is not a directory"),
+            ));
+        }
+        Ok(location)
+    }
+
+    fn read_children(&mut self, dir_cluster: u32, count: u32) -> io::Result<Vec<FSEntry>> {
+        Ok(self
+            .read_dir_raw(dir_cluster, count as usize)?
+            .iter()
+            .skip(2)
+            .filter_map(FSEntry::from_raw)
+            .filter(|e| e.name != "." && e.name != "..")
+            .collect())
+    }
+
+    // Entries of the directory at `path`, without "." and ".."
+    pub fn read_dir(&mut self, path: &str) -> io::Result<Vec<FSEntry>> {
+        let dir = self.lookup_dir(path)?;
+        self.read_children(dir.entry.cluster, dir.entry.length)
+    }
+
+    pub fn metadata(&mut self, path: &str) -> io::Result<FSEntry> {
+        let location = self.lookup(path)?;
+        FSEntry::from_raw(&location.entry).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("'{path}' has an unreadable directory entry"),
+            )
+        })
+    }
+
+    // Open a file on the card for streaming reads, e.g. "BASLUS-21050/icon.sys"
+    pub fn open(&mut self, path: &str) -> io::Result<VmcFile<'_, B>> {
+        let location = self.lookup(path)?;
+        if location.entry.is_dir() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("'{path}' is a directory"),
+            ));
+        }
+        Ok(self.open_chain(location.entry.cluster, location.entry.length))
+    }
+
+    // Stream `length` bytes from the cluster chain starting at `start_cluster`
+    pub fn open_chain(&mut self, start_cluster: u32, length: u32) -> VmcFile<'_, B> {
+        VmcFile {
+            vmc: self,
+            start_cluster,
+            length: length as u64,
+            pos: 0,
+            chain_cursor: None,
+            cached_index: None,
+            buffer: Vec::new(),
+        }
+    }
+
+    // Copy a save directory and its files off the card, keeping their directory entries
+    pub fn export_save(&mut self, path: &str) -> io::Result<SaveDir> {
+        let dir = self.lookup_dir(path)?;
+        if dir.index == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "The root directory is not a save",
+            ));
+        }
+
+        let entries = self.read_dir_raw(dir.entry.cluster, dir.entry.length as usize)?;
+        let mut files = Vec::new();
+        for entry in entries.iter().skip(2).filter(|e| e.exists()) {
+            if entry.is_dir() {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!(
+                        "'{path}/{}': nested directories cannot be exported",
+                        entry.name_str()
+                    ),
+                ));
+            }
+            let mut data = Vec::new();
+            self.open_chain(entry.cluster, entry.length)
+                .read_to_end(&mut data)?;
+            files.push(SaveFile {
+                entry: *entry,
+                data,
+            });
+        }
+
+        Ok(SaveDir {
+            entry: dir.entry,
+            files,
+        })
+    }
+
+    pub fn export_psu<W: Write>(&mut self, path: &str, out: &mut W) -> io::Result<()> {
+        let save = self.export_save(path)?;
+        psu::write_psu(&save, out)
+    }
+
+    // Export as a signed .PSV for the PS3's PS2 Classics save manager
+    pub fn export_psv<W: Write>(&mut self, path: &str, out: &mut W) -> io::Result<()> {
+        let save = self.export_save(path)?;
+        psv::write_psv(&save, out)
+    }
+
+    // Depth-first iterator over every entry on the card, directories before their contents
+    pub fn walk(&mut self) -> Walk<'_, B> {
+        Walk {
+            vmc: self,
+            pending: Vec::new(),
+            visited: HashSet::new(),
+            started: false,
+        }
+    }
+
+    fn find_file(&mut self, dir: &RawFSEntry, file_name: &str) -> Option<(usize, RawFSEntry)> {
+        self.read_dir_raw(dir.cluster, dir.length as usize)
+            .ok()?
+            .into_iter()
+            .enumerate()
+            .skip(2)
+            .find(|(_, e)| e.exists() && !e.is_dir() && e.name_str() == file_name)
+    }
+
+    // Parse the icon.sys of a save directory
+    pub fn icon_sys(&mut self, dir_path: &str) -> io::Result<IconSys> {
+        let mut data = Vec::new();
+        self.open(&format!("{dir_path}/icon.sys"))?
+            .read_to_end(&mut data)?;
+        IconSys::from_bytes(&data)
+    }
+
+    // Parse the 3D icon a save directory shows in the browser, as named by its icon.sys
+    pub fn icon(&mut self, dir_path: &str) -> io::Result<Icon> {
+        let icon_file = self.icon_sys(dir_path)?.icon_file;
+        let mut data = Vec::new();
+        self.open(&format!("{dir_path}/{icon_file}"))?
+            .read_to_end(&mut data)?;
+        icon::read_icon(&data)
+    }
+
+    pub fn read_file(&mut self, dir_path: &str, file_name: &str) -> io::Result<Vec<u8>> {
+        let dir = self.lookup_dir(dir_path)?;
+        let (_, entry) = self.find_file(&dir.entry, file_name).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("File '{dir_path}/{file_name}' not found"),
+            )
+        })?;
+
+        // The buffer grows with the clusters actually read, not with the entry's length
+        let mut data = Vec::new();
+        self.open_chain(entry.cluster, entry.length)
+            .read_to_end(&mut data)?;
+        Ok(data)
+    }
+}
+
+impl<B: Read + Write + Seek + SyncData> Vmc<B> {
+    // Write whole pages starting at absolute page `page`, regenerating ECC for raw dumps.
+    // The write goes through the backup blocks (see vmc_backup).
+    fn write_pages(&mut self, page: u64, data: &[u8]) -> io::Result<()> {
+        let page_size = self.superblock.page_size as usize;
+        let raw_page_size = self.raw_page_size as usize;
+        let offset = page * raw_page_size as u64;
+
+        if raw_page_size == page_size {
+            return self.write_raw_pages(offset, data);
+        }
+
+        let mut raw = Vec::with_capacity(data.len() / page_size * raw_page_size);
+        for page_data in data.chunks(page_size) {
+            raw.extend_from_slice(page_data);
+            raw.extend_from_slice(&ecc_calculate_page(page_data, raw_page_size - page_size));
+        }
+        self.write_raw_pages(offset, &raw)
+    }
+
+    // Writes one absolute cluster, zero-padding data shorter than the cluster size
+    fn write_card_cluster(&mut self, card_cluster: u32, data: &[u8]) -> io::Result<()> {
+        let mut buf = vec![0u8; self.superblock.cluster_size as usize];
+        buf[..data.len()].copy_from_slice(data);
+        let pages_per_cluster = self.superblock.pages_per_cluster as u64;
+        self.write_pages(card_cluster as u64 * pages_per_cluster, &buf)
+    }
+
+    fn write_cluster(&mut self, cluster: u32, data: &[u8]) -> io::Result<()> {
+        self.write_card_cluster(self.card_cluster(cluster)?, data)
+    }
+
+    // Allocates and links a new chain of `count` clusters in the in-memory FAT
+    fn allocate_clusters(&mut self, count: usize) -> io::Result<Vec<u32>> {
+        let limit = (self.superblock.max_allocatable_clusters as usize).min(self.fat.fat.len());
+        let free: Vec<u32> = (0..limit)
+            .filter(|&c| fat_is_free(self.fat.fat[c]) && !self.is_bad_cluster(c as u32))
+            .take(count)
+            .map(|c| c as u32)
+            .collect();
+
+        if free.len() < count {
+            return Err(io::Error::new(
+                io::ErrorKind::StorageFull,
+                format!(
+                    "Not enough free clusters ({count} needed, {} available)",
+                    free.len()
+                ),
+            ));
+        }
+
+        for (i, &cluster) in free.iter().enumerate() {
+            self.fat.fat[cluster as usize] = match free.get(i + 1) {
+                Some(&next) => FAT_ALLOCATED | next,
+                None => FAT_CHAIN_END,
+            };
+        }
+        Ok(free)
+    }
+
+    fn free_chain(&mut self, start_cluster: u32) {
+        if start_cluster == INVALID_CLUSTER_PTR {
+            return;
+        }
+        for cluster in self.build_cluster_chain(start_cluster) {
+            if let Some(entry) = self.fat.fat.get_mut(cluster as usize) {
+                *entry = FAT_FREE;
+            }
+        }
+    }
+
+    // Writes the in-memory FAT and the indirect FAT pointers back to the card
+    pub(crate) fn flush_fat(&mut self) -> io::Result<()> {
+        let entries_per_cluster = self.superblock.cluster_size as usize / 4;
+
+        for i in 0..self.fat.fat_clusters.len() {
+            let mut buf = Vec::with_capacity(entries_per_cluster * 4);
+            for &entry in &self.fat.fat[i * entries_per_cluster..(i + 1) * entries_per_cluster] {
+                buf.write_u32::<LittleEndian>(entry)?;
+            }
+            self.write_card_cluster(self.fat.fat_clusters[i], &buf)?;
+        }
+
+        // Only the pointer slots are rewritten, the rest of each indirect cluster is kept
+        for i in 0..self.fat.fat_clusters.len().div_ceil(entries_per_cluster) {
+            let ifc = self.superblock.ifc_ptr_list[i];
+            let mut buf = self.read_card_cluster(ifc)?;
+            let end = self
+                .fat
+                .fat_clusters
+                .len()
+                .min((i + 1) * entries_per_cluster);
+            for (slot, &ptr) in self.fat.fat_clusters[i * entries_per_cluster..end]
+                .iter()
+                .enumerate()
+            {
+                buf[slot * 4..slot * 4 + 4].copy_from_slice(&ptr.to_le_bytes());
+            }
+            self.write_card_cluster(ifc, &buf)?;
+        }
+
+        self.file.flush()
+    }
+
+    pub(crate) fn write_dir_entry(
+        &mut self,
+        dir_cluster: u32,
+        index: usize,
+        entry: &RawFSEntry,
+    ) -> io::Result<()> {
+        let (cluster, offset) = self.dir_entry_location(dir_cluster, index)?;
+        let mut buf = self.read_cluster(cluster)?;
+        buf[offset..offset + DIR_ENTRY_SIZE].copy_from_slice(&entry.to_bytes());
+        self.write_cluster(cluster, &buf)
+    }
+
+    fn write_location(&mut self, location: &EntryLocation) -> io::Result<()> {
+        let mut entry = location.entry;
+        if location.index == 0 {
+            // Root "." entry: keep whatever cluster value the card stored
+            entry.cluster = self.read_dir_entry(location.dir_cluster, 0)?.cluster;
+        }
+        self.write_dir_entry(location.dir_cluster, location.index, &entry)
+    }
+
+    // Returns a free slot index in the directory, growing its cluster chain when full.
+    // Slots 0 and 1 always hold "." and "..".
+    fn claim_dir_slot(&mut self, dir_cluster: u32, entries: &[RawFSEntry]) -> io::Result<usize> {
+        if let Some(pos) = entries.iter().skip(2).position(|e| !e.exists()) {
+            return Ok(pos + 2);
+        }
+
+        let index = entries.len();
+        let entries_per_cluster = self.superblock.cluster_size as usize / DIR_ENTRY_SIZE;
+        let chain = self.build_cluster_chain(dir_cluster);
+        if index >= chain.len() * entries_per_cluster {
+            let new_cluster = self.allocate_clusters(1)?[0];
+            if let Some(&last) = chain.last() {
+                self.fat.fat[last as usize] = FAT_ALLOCATED | new_cluster;
+            }
+            self.write_cluster(new_cluster, &[])?;
+        }
+        Ok(index)
+    }
+
+    // Create a file in a directory, or replace the contents of an existing one
+    pub fn write_file(&mut self, dir_path: &str, file_name: &str, data: &[u8]) -> io::Result<()> {
+        validate_entry_name(file_name)?;
+        let result = self.write_file_inner(dir_path, file_name, data);
+        if result.is_err() {
+            self.reload_fat()?;
+        }
+        result
+    }
+
+    fn write_file_inner(&mut self, dir_path: &str, file_name: &str, data: &[u8]) -> io::Result<()> {
+        let mut dir = self.lookup_dir(dir_path)?;
+        let entries = self.read_dir_raw(dir.entry.cluster, dir.entry.length as usize)?;
+        let now = Ps2Time::now();
+
+        let existing = entries
+            .iter()
+            .enumerate()
+            .skip(2)
+            .find(|(_, e)| e.exists() && e.name_str() == file_name)
+            .map(|(i, _)| i);
+        let (index, mut entry) = match existing {
+            Some(i) if entries[i].is_dir() => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("'{file_name}' is a directory"),
+                ));
+            }
+            Some(i) => {
+                self.free_chain(entries[i].cluster);
+                (i, entries[i])
+            }
+            None => {
+                let i = self.claim_dir_slot(dir.entry.cluster, &entries)?;
+                (
+                    i,
+                    RawFSEntry::new(file_name, FILE_MODE, 0, FAT_CHAIN_END, now),
+                )
+            }
+        };
+
+        let cluster_size = self.superblock.cluster_size as usize;
+        let chain = self.allocate_clusters(data.len().div_ceil(cluster_size))?;
+        for (&cluster, chunk) in chain.iter().zip(data.chunks(cluster_size)) {
+            self.write_cluster(cluster, chunk)?;
+        }
+        self.flush_fat()?;
+
+        entry.length = data.len() as u32;
+        entry.cluster = chain.first().copied().unwrap_or(FAT_CHAIN_END);
+        entry.set_modified(now);
+        self.write_dir_entry(dir.entry.cluster, index, &entry)?;
+
+        if index >= dir.entry.length as usize {
+            dir.entry.length = index as u32 + 1;
+        }
+        dir.entry.set_modified(now);
+        self.write_location(&dir)?;
+        self.file.flush()
+    }
+
+    // Write an edited icon.sys back into its save directory
+    pub fn write_icon_sys(&mut self, dir_path: &str, icon_sys: &IconSys) -> io::Result<()> {
+        self.write_file(dir_path, "icon.sys", &icon_sys.to_bytes())
+    }
+
+    // Delete a file, releasing its clusters.
+    // The directory slot keeps its name and cluster but loses the exists bit.
+    pub fn delete_file(&mut self, dir_path: &str, file_name: &str) -> io::Result<()> {
+        let mut dir = self.lookup_dir(dir_path)?;
+        let (index, mut entry) = self.find_file(&dir.entry, file_name).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("File '{dir_path}/{file_name}' not found"),
+            )
+        })?;
+
+        self.free_chain(entry.cluster);
+        self.flush_fat()?;
+
+        entry.mode &= !EM_EXISTS;
+        self.write_dir_entry(dir.entry.cluster, index, &entry)?;
+
+        dir.entry.set_modified(Ps2Time::now());
+        self.write_location(&dir)?;
+        self.file.flush()
+    }
+
+    // Add a file entry for a chain that is already allocated in the FAT, as when
+    // check --repair files an unreachable chain under LOST.FOUND
+    pub(crate) fn link_chain(
+        &mut self,
+        dir_path: &str,
+        file_name: &str,
+        cluster: u32,
+        length: u32,
+    ) -> io::Result<()> {
+        validate_entry_name(file_name)?;
+        let mut dir = self.lookup_dir(dir_path)?;
+        let entries = self.read_dir_raw(dir.entry.cluster, dir.entry.length as usize)?;
+        let now = Ps2Time::now();
+
+        let index = self.claim_dir_slot(dir.entry.cluster, &entries)?;
+        self.flush_fat()?;
+        let entry = RawFSEntry::new(file_name, FILE_MODE, length, cluster, now);
+        self.write_dir_entry(dir.entry.cluster, index, &entry)?;
+
+        if index >= dir.entry.length as usize {
+            dir.entry.length = index as u32 + 1;
+        }
+        dir.entry.set_modified(now);
+        self.write_location(&dir)?;
+        self.file.flush()
+    }
+
+    // Create an empty directory with its "." and ".." entries. Save directories live in
+    // the root, so a plain name like "BASLUS-21050" is the usual argument.
+    pub fn create_dir(&mut self, path: &str) -> io::Result<()> {
+        let (parent_path, dir_name) = split_parent(path);
+        validate_entry_name(dir_name)?;
+        let result = self.create_dir_inner(parent_path, dir_name);
+        if result.is_err() {
+            self.reload_fat()?;
+        }
+        result
+    }
+
+    fn create_dir_inner(&mut self, parent_path: &str, dir_name: &str) -> io::Result<()> {
+        let mut parent = self.lookup_dir(parent_path)?;
+        let parent_cluster = parent.entry.cluster;
+        let entries = self.read_dir_raw(parent_cluster, parent.entry.length as usize)?;
+        if entries
+            .iter()
+            .skip(2)
+            .any(|e| e.exists() && e.name_str() == dir_name)
+        {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!("'{dir_name}' already exists"),
+            ));
+        }
+
+        let now = Ps2Time::now();
+        let index = self.claim_dir_slot(parent_cluster, &entries)?;
+        let dir_cluster = self.allocate_clusters(1)?[0];
+
+        let mut dot = RawFSEntry::new(".", DIR_MODE, 0, parent_cluster, now);
+        dot.dir_entry = index as u32;
+        let dotdot = RawFSEntry::new("..", DOTDOT_MODE, 0, 0, now);
+        let mut cluster_buf = Vec::with_capacity(2 * DIR_ENTRY_SIZE);
+        cluster_buf.extend_from_slice(&dot.to_bytes());
+        cluster_buf.extend_from_slice(&dotdot.to_bytes());
+        self.write_cluster(dir_cluster, &cluster_buf)?;
+        self.flush_fat()?;
+
+        let entry = RawFSEntry::new(dir_name, DIR_MODE, 2, dir_cluster, now);
+        self.write_dir_entry(parent_cluster, index, &entry)?;
+
+        if index >= entries.len() {
+            parent.entry.length = index as u32 + 1;
+        }
+        parent.entry.set_modified(now);
+        self.write_location(&parent)?;
+        self.file.flush()
+    }
+
+    // Delete a directory together with everything inside it
+    pub fn remove_dir(&mut self, path: &str) -> io::Result<()> {
+        let (parent_path, _) = split_parent(path);
+        let mut dir = self.lookup_dir(path)?;
+        if dir.index == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "Cannot remove the root directory",
+            ));
+        }
+
+        // The root is seeded too, so a damaged entry linking back up is caught
+        let mut visited = HashSet::from([self.superblock.rootdir_cluster, dir.entry.cluster]);
+        if let Err(e) =
+            self.free_dir_contents(dir.entry.cluster, dir.entry.length as usize, &mut visited)
+        {
+            // Nothing was written yet; drop the half-freed FAT
+            self.reload_fat()?;
+            return Err(e);
+        }
+        self.free_chain(dir.entry.cluster);
+        self.flush_fat()?;
+
+        dir.entry.mode &= !EM_EXISTS;
+        self.write_location(&dir)?;
+
+        let mut parent = self.lookup_dir(parent_path)?;
+        parent.entry.set_modified(Ps2Time::now());
+        self.write_location(&parent)?;
+        self.file.flush()
+    }
+
+    // Recreate an exported save as a new root directory, restoring the mode bits,
+    // attributes and timestamps of the directory and its files. Returns the save's name.
+    pub fn import_save(&mut self, save: &SaveDir) -> io::Result<String> {
+        let name = save.name();
+        self.create_dir(&name)?;
+        if let Err(e) = self.import_save_files(&name, save) {
+            // Don't leave a half-imported save behind
+            let _ = self.remove_dir(&name);
+            return Err(e);
+        }
+        Ok(name)
+    }
+
+    pub fn import_psu<R: Read>(&mut self, input: &mut R) -> io::Result<String> {
+        let save = psu::read_psu(input)?;
+        self.import_save(&save)
+    }
+
+    // Import a PS3 .PSV export, refusing files whose signature does not verify
+    pub fn import_psv<R: Read>(&mut self, input: &mut R) -> io::Result<String> {
+        let save = psv::read_signed_psv(input)?;
+        self.import_save(&save)
+    }
+
+    fn import_save_files(&mut self, dir_name: &str, save: &SaveDir) -> io::Result<()> {
+        for file in &save.files {
+            let file_name = file.name();
+            self.write_file(dir_name, &file_name, &file.data)?;
+            self.restore_metadata(&format!("{dir_name}/{file_name}"), &file.entry)?;
+        }
+        self.restore_metadata(dir_name, &save.entry)
+    }
+
+    // Copy mode bits, attributes and timestamps from `source`, keeping the entry's type
+    fn restore_metadata(&mut self, path: &str, source: &RawFSEntry) -> io::Result<()> {
+        let mut location = self.lookup(path)?;
+        let entry = &mut location.entry;
+        let type_bits = EM_DIRECTORY | EM_FILE;
+        entry.mode = (source.mode & !type_bits) | (entry.mode & type_bits) | EM_EXISTS;
+        entry.attr = source.attr;
+        entry.set_created(source.created());
+        entry.set_modified(source.modified());
+        self.write_location(&location)?;
+        self.file.flush()
+    }
+
+    // Free everything below a directory. `visited` holds the directory clusters seen so
+    // far; meeting one again means the directories form a loop on a damaged card.
+    fn free_dir_contents(
+        &mut self,
+        dir_cluster: u32,
+        count: usize,
+        visited: &mut HashSet<u32>,
+    ) -> io::Result<()> {
+        let entries = self.read_dir_raw(dir_cluster, count)?;
+        for entry in entries.iter().skip(2).filter(|e| e.exists()) {
+            if entry.is_dir() {
+                if !visited.insert(entry.cluster) {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!(
+                            "Directory cluster {} is reached twice; the directories form a loop",
+                            entry.cluster
+                        ),
+                    ));
+                }
+                self.free_dir_contents(entry.cluster, entry.length as usize, visited)?;
+            }
+            self.free_chain(entry.cluster);
+        }
+        Ok(())
+    }
+}
+
+fn check_card_size(size_mb: u32) -> io::Result<()> {
+    if size_mb == 0 || !size_mb.is_multiple_of(8) || size_mb > MAX_CARD_SIZE_MB {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "Unsupported card size {size_mb} MB (use a multiple of 8 up to {MAX_CARD_SIZE_MB})"
+            ),
+        ));
+    }
+    Ok(())
+}
+
+// Split "A/B/name" into ("A/B", "name"); a bare name has the root as parent
+fn split_parent(path: &str) -> (&str, &str) {
+    let path = path.trim_end_matches('/');
+    match path.rsplit_once('/') {
+        Some((parent, name)) => (parent, name),
+        None => ("", path),
+    }
+}
+
+// A directory entry together with the slot it is stored in, so it can be written back
+#[derive(Debug, Clone, Copy)]
+struct EntryLocation {
+    dir_cluster: u32,
+    index: usize,
+    entry: RawFSEntry,
+}
+
+#[derive(Debug, Clone)]
+pub struct WalkEntry {
+    pub path: String,
+    pub entry: FSEntry,
+}
+
+// Iterator returned by Vmc::walk. Directories are read lazily as they are reached,
+// and a directory cluster is never entered twice, so corrupted cards cannot loop.
+pub struct Walk<'a, B = File> {
+    vmc: &'a mut Vmc<B>,
+    pending: Vec<WalkEntry>,
+    visited: HashSet<u32>,
+    started: bool,
+}
+
+impl<B> Walk<'_, B> {
+    fn push_children(&mut self, parent_path: &str, children: Vec<FSEntry>) {
+        for entry in children.into_iter().rev() {
+            let path = if parent_path.is_empty() {
+                entry.name.clone()
+            } else {
+                format!("{parent_path}/{}", entry.name)
+            };
+            self.pending.push(WalkEntry { path, entry });
+        }
+    }
+}
+
+impl<B: Read + Seek> Iterator for Walk<'_, B> {
+    type Item = io::Result<WalkEntry>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if !self.started {
+            self.started = true;
+            let root = match self.vmc.root_location() {
+                Ok(root) => root,
+                Err(e) => return Some(Err(e)),
+            };
+            self.visited.insert(root.entry.cluster);
+            match self
+                .vmc
+                .read_children(root.entry.cluster, root.entry.length)
+            {
+                Ok(children) => self.push_children("", children),
+                Err(e) => return Some(Err(e)),
+            }
+        }
+
+        let next = self.pending.pop()?;
+        if next.entry.is_directory && self.visited.insert(next.entry.cluster) {
+            match self
+                .vmc
+                .read_children(next.entry.cluster, next.entry.length)
+            {
+                Ok(children) => self.push_children(&next.path, children),
+                Err(e) => return Some(Err(e)),
+            }
+        }
+        Some(Ok(next))
+    }
+}
+
+// Read + Seek handle over a file stored on the card, returned by Vmc::open.
+// The FAT chain is followed only as far as the reads need and one cluster is cached.
+pub struct VmcFile<'a, B = File> {
+    vmc: &'a mut Vmc<B>,
+    start_cluster: u32,
+    length: u64,
+    pos: u64,
+    // Last resolved (chain index, cluster) pair, so sequential reads never rewalk the chain
+    chain_cursor: Option<(u64, u32)>,
+    cached_index: Option<u64>,
+    buffer: Vec<u8>,
+}
+
+impl<B> VmcFile<'_, B> {
+    pub fn len(&self) -> u64 {
+        self.length
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.length == 0
+    }
+
+    fn cluster_at(&mut self, index: u64) -> io::Result<u32> {
+        let (mut i, mut cluster) = match self.chain_cursor {
+            Some((i, cluster)) if i <= index => (i, cluster),
+            _ => (0, self.start_cluster),
+        };
+
+        while i < index {
+            let raw_entry = self
+                .vmc
+                .fat
+                .fat
+                .get(cluster as usize)
+                .copied()
+                .unwrap_or(FAT_CHAIN_END);
+            if fat_flag(raw_entry) != (FAT_ALLOCATED >> 24) as u8 {
+                return Err(io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    "Cluster chain ends before the end of the file",
+                ));
+            }
+            cluster = fat_next(raw_entry);
+            i += 1;
+        }
+        if cluster as usize >= self.vmc.fat.fat.len() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Cluster {cluster:#X} of the file lies outside the FAT"),
+            ));
+        }
+
+        self.chain_cursor = Some((i, cluster));
+        Ok(cluster)
+    }
+}
+
+impl<B: Read + Seek> Read for VmcFile<'_, B> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() || self.pos >= self.length {
+            return Ok(0);
+        }
+
+        let cluster_size = self.vmc.superblock.cluster_size as u64;
+        let index = self.pos / cluster_size;
+        if self.cached_index != Some(index) {
+            let cluster = self.cluster_at(index)?;
+            self.buffer = self.vmc.read_cluster(cluster)?;
+            self.cached_index = Some(index);
+        }
+
+        let offset = (self.pos % cluster_size) as usize;
+        let available = (self.length - self.pos).min(cluster_size - offset as u64) as usize;
+        let count = available.min(buf.len());
+        buf[..count].copy_from_slice(&self.buffer[offset..offset + count]);
+        self.pos += count as u64;
+        Ok(count)
+    }
+}
+
+impl<B: Read + Seek> Seek for VmcFile<'_, B> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let target = match pos {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::End(delta) => self.length.checked_add_signed(delta),
+            SeekFrom::Current(delta) => self.pos.checked_add_signed(delta),
+        };
+        self.pos = target.ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "Invalid seek to a negative or overflowing position",
+            )
+        })?;
+        Ok(self.pos)
+    }
 }
@@ -0,0 +1,227 @@
+// Recovery of deleted files and save directories. Deleting only clears EM_EXISTS in
+// the directory slot and marks the clusters free, so the name, length and first
+// cluster survive until the slot or the clusters are reused.
+//
+// The links between the clusters are gone, though. The allocator always hands out
+// the lowest free clusters, so the chain is guessed as the first cluster followed by
+// the next free ones; when that run has gaps the guess may be wrong.
+
+use crate::model::vmc_backup::SyncData;
+use crate::model::vmc_core_model::{
+    DIR_ENTRY_SIZE, EM_EXISTS, FAT_ALLOCATED, FAT_CHAIN_END, INVALID_CLUSTER_PTR, Ps2Time,
+    RawFSEntry, Vmc, fat_is_free,
+};
+use std::collections::HashSet;
+use std::io::{self, Read, Seek, Write};
+
+// How likely a deleted entry is to come back intact
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Recoverability {
+    // The guessed chain is one unbroken run of free clusters
+    Contiguous,
+    // Enough free clusters, but the run skips clusters in use, so parts may be wrong
+    Fragmented,
+    // The first cluster is in use again, or too few free clusters are left
+    Overwritten,
+}
+
+#[derive(Debug, Clone)]
+pub struct DeletedEntry {
+    pub path: String,
+    pub is_dir: bool,
+    pub length: u32,
+    pub modified: Ps2Time,
+    pub recoverability: Recoverability,
+    // The chain a restore would link, in order
+    pub clusters: Vec<u32>,
+    dir_cluster: u32,
+    index: usize,
+    entry: RawFSEntry,
+}
+
+impl<B: Read + Seek> Vmc<B> {
+    // Clusters an entry's data needs: bytes for files, entries for directories
+    fn clusters_needed(&self, entry: &RawFSEntry) -> usize {
+        let cluster_size = self.superblock.cluster_size as usize;
+        if entry.is_dir() {
+            (entry.length as usize).div_ceil(cluster_size / DIR_ENTRY_SIZE)
+        } else {
+            (entry.length as usize).div_ceil(cluster_size)
+        }
+    }
+
+    fn guess_chain(&self, entry: &RawFSEntry) -> (Vec<u32>, Recoverability) {
+        let count = self.clusters_needed(entry);
+        if count == 0 {
+            return (Vec::new(), Recoverability::Contiguous);
+        }
+        let first = entry.cluster;
+        let limit = (self.superblock.max_allocatable_clusters as usize).min(self.fat.fat.len());
+        if first as usize >= limit || !fat_is_free(self.fat.fat[first as usize]) {
+            return (Vec::new(), Recoverability::Overwritten);
+        }
+
+        let chain: Vec<u32> = (first..limit as u32)
+            .filter(|&c| fat_is_free(self.fat.fat[c as usize]) && !self.is_bad_cluster(c))
+            .take(count)
+            .collect();
+        let recoverability = if chain.len() < count {
+            Recoverability::Overwritten
+        } else if (chain[count - 1] - first) as usize == count - 1 {
+            Recoverability::Contiguous
+        } else {
+            Recoverability::Fragmented
+        };
+        (chain, recoverability)
+    }
+
+    // Every deleted entry still present in a live directory, in directory order.
+    // Files inside a deleted directory are not listed; they come back with it.
+    // Directories reached a second time (a loop on a damaged card) are not scanned again.
+    pub fn deleted_entries(&mut self) -> io::Result<Vec<DeletedEntry>> {
+        let root = self.superblock.rootdir_cluster;
+        let root_length = self.read_dir_entry(root, 0)?.length;
+        let mut deleted = Vec::new();
+        let mut visited = HashSet::from([root]);
+        let mut pending = vec![(String::new(), root, root_length)];
+        while let Some((path, cluster, length)) = pending.pop() {
+            let entries = self.read_dir_raw(cluster, length as usize)?;
+            let mut subdirs = Vec::new();
+            for (index, entry) in entries.into_iter().enumerate().skip(2) {
+                // Slots that never held anything have no name
+                if entry.name[0] == 0 {
+                    continue;
+                }
+                let child = if path.is_empty() {
+                    entry.name_str()
+                } else {
+                    format!("{path}/{}", entry.name_str())
+                };
+                if entry.exists() {
+                    if entry.is_dir() && visited.insert(entry.cluster) {
+                        subdirs.push((child, entry.cluster, entry.length));
+                    }
+                    continue;
+                }
+
+                let (clusters, recoverability) = self.guess_chain(&entry);
+                deleted.push(DeletedEntry {
+                    path: child,
+                    is_dir: entry.is_dir(),
+                    length: entry.length,
+                    modified: entry.modified(),
+                    recoverability,
+                    clusters,
+                    dir_cluster: cluster,
+                    index,
+                    entry,
+                });
+            }
+            pending.extend(subdirs.into_iter().rev());
+        }
+        Ok(deleted)
+    }
+}
+
+impl<B: Read + Write + Seek + SyncData> Vmc<B> {
+    fn link_clusters(&mut self, chain: &[u32]) {
+        for (i, &cluster) in chain.iter().enumerate() {
+            self.fat.fat[cluster as usize] = match chain.get(i + 1) {
+                Some(&next) => FAT_ALLOCATED | next,
+                None => FAT_CHAIN_END,
+            };
+        }
+    }
+
+    // Re-link the chains of the entries a deleted directory still lists. Entries whose
+    // clusters were reused are dropped rather than restored pointing at foreign data.
+    fn relink_dir_contents(
+        &mut self,
+        dir_cluster: u32,
+        count: usize,
+        visited: &mut HashSet<u32>,
+    ) -> io::Result<()> {
+        if !visited.insert(dir_cluster) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "Directory cluster {dir_cluster} is reached twice; the directories form a loop"
+                ),
+            ));
+        }
+        let entries = self.read_dir_raw(dir_cluster, count)?;
+        for (index, mut entry) in entries.into_iter().enumerate().skip(2) {
+            if !entry.exists() || entry.cluster == INVALID_CLUSTER_PTR {
+                continue;
+            }
+            let (chain, recoverability) = self.guess_chain(&entry);
+            if recoverability == Recoverability::Overwritten {
+                entry.mode &= !EM_EXISTS;
+                self.write_dir_entry(dir_cluster, index, &entry)?;
+                continue;
+            }
+            self.link_clusters(&chain);
+            if entry.is_dir() {
+                self.relink_dir_contents(entry.cluster, entry.length as usize, visited)?;
+            }
+        }
+        Ok(())
+    }
+
+    // Bring a deleted entry from deleted_entries() back: its guessed chain is linked
+    // in the FAT and the exists bit set again. A directory brings its files with it.
+    pub fn undelete(&mut self, deleted: &DeletedEntry) -> io::Result<()> {
+        let mut entry = self.read_dir_entry(deleted.dir_cluster, deleted.index)?;
+        if entry.to_bytes() != deleted.entry.to_bytes() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("The slot of '{}' has changed since the scan", deleted.path),
+            ));
+        }
+        let (chain, recoverability) = self.guess_chain(&entry);
+        if recoverability == Recoverability::Overwritten {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "'{}' cannot be recovered, its clusters are in use",
+                    deleted.path
+                ),
+            ));
+        }
+
+        let name = entry.name_str();
+        let parent = self.read_dir_entry(deleted.dir_cluster, 0)?;
+        let parent_length = if deleted.dir_cluster == self.superblock.rootdir_cluster {
+            parent.length
+        } else {
+            // A subdirectory's count lives in its own entry, one level up
+            self.read_dir_entry(parent.cluster, parent.dir_entry as usize)?
+                .length
+        };
+        let siblings = self.read_dir_raw(deleted.dir_cluster, parent_length as usize)?;
+        if siblings
+            .iter()
+            .skip(2)
+            .any(|e| e.exists() && e.name_str() == name)
+        {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!("'{}' already exists", deleted.path),
+            ));
+        }
+
+        self.link_clusters(&chain);
+        if entry.is_dir()
+            && let Err(e) =
+                self.relink_dir_contents(entry.cluster, entry.length as usize, &mut HashSet::new())
+        {
+            self.reload_fat()?;
+            return Err(e);
+        }
+        self.flush_fat()?;
+
+        entry.mode |= EM_EXISTS;
+        self.write_dir_entry(deleted.dir_cluster, deleted.index, &entry)?;
+        self.file.flush()
+    }
+}
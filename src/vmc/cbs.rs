@@ -0,0 +1,197 @@
+// CodeBreaker save archives (.cbs). A header describing the save directory is followed
+// by a body that is zlib-compressed and then RC4-encrypted with a fixed key. The
+// decrypted body is a sequence of 64-byte file headers, each followed by its data.
+//
+// 0x00 magic "CFU\0"     0x04 version          0x08 header length
+// 0x0C body size         0x10 compressed size (some writers store the file size)
+// 0x14 directory name [32]   0x34 created   0x3C modified   0x4C directory mode
+// 0x5C title, up to the header length
+
+use crate::model::save_model::{SaveDir, SaveFile};
+use crate::model::vmc_core_model::{DIR_MODE, Ps2Time, RawFSEntry};
+use crate::vmc::{psu, sjis};
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use flate2::Compression;
+use flate2::read::ZlibDecoder;
+use flate2::write::ZlibEncoder;
+use std::io::{self, Cursor, Read, Write};
+
+pub const CBS_MAGIC: &[u8; 4] = b"CFU\0";
+const CBS_VERSION: u32 = 0x1F40;
+const CBS_HEADER_SIZE: usize = 0x128;
+const CBS_TITLE_OFFSET: usize = 0x5C;
+const CBS_FILE_HEADER_SIZE: usize = 64;
+const CBS_NAME_SIZE: usize = 32;
+const CBS_DIRECTORY_BIT: u16 = 0x0020;
+
+// RC4 state used by CodeBreaker. It is the permutation itself, with no key schedule.
+const CBS_RC4_STATE: [u8; 256] = [
+    0x5f, 0x1f, 0x85, 0x6f, 0x31, 0xaa, 0x3b, 0x18, 0x21, 0xb9, 0xce, 0x1c, 0x07, 0x4c, 0x9c, 0xb4,
+    0x81, 0xb8, 0xef, 0x98, 0x59, 0xae, 0xf9, 0x26, 0xe3, 0x80, 0xa3, 0x29, 0x2d, 0x73, 0x51, 0x62,
+    0x7c, 0x64, 0x46, 0xf4, 0x34, 0x1a, 0xf6, 0xe1, 0xba, 0x3a, 0x0d, 0x82, 0x79, 0x0a, 0x5c, 0x16,
+    0x71, 0x49, 0x8e, 0xac, 0x8c, 0x9f, 0x35, 0x19, 0x45, 0x94, 0x3f, 0x56, 0x0c, 0x91, 0x00, 0x0b,
+    0xd7, 0xb0, 0xdd, 0x39, 0x66, 0xa1, 0x76, 0x52, 0x13, 0x57, 0xf3, 0xbb, 0x4e, 0xe5, 0xdc, 0xf0,
+    0x65, 0x84, 0xb2, 0xd6, 0xdf, 0x15, 0x3c, 0x63, 0x1d, 0x89, 0x14, 0xbd, 0xd2, 0x36, 0xfe, 0xb1,
+    0xca, 0x8b, 0xa4, 0xc6, 0x9e, 0x67, 0x47, 0x37, 0x42, 0x6d, 0x6a, 0x03, 0x92, 0x70, 0x05, 0x7d,
+    0x96, 0x2f, 0x40, 0x90, 0xc4, 0xf1, 0x3e, 0x3d, 0x01, 0xf7, 0x68, 0x1e, 0xc3, 0xfc, 0x72, 0xb5,
+    0x54, 0xcf, 0xe7, 0x41, 0xe4, 0x4d, 0x83, 0x55, 0x12, 0x22, 0x09, 0x78, 0xfa, 0xde, 0xa7, 0x06,
+    0x08, 0x23, 0xbf, 0x0f, 0xcc, 0xc1, 0x97, 0x61, 0xc5, 0x4a, 0xe6, 0xa0, 0x11, 0xc2, 0xea, 0x74,
+    0x02, 0x87, 0xd5, 0xd1, 0x9d, 0xb7, 0x7e, 0x38, 0x60, 0x53, 0x95, 0x8d, 0x25, 0x77, 0x10, 0x5e,
+    0x9b, 0x7f, 0xd8, 0x6e, 0xda, 0xa2, 0x2e, 0x20, 0x4f, 0xcd, 0x8f, 0xcb, 0xbe, 0x5a, 0xe0, 0xed,
+    0x2c, 0x9a, 0xd4, 0xe2, 0xaf, 0xd0, 0xa9, 0xe8, 0xad, 0x7a, 0xbc, 0xa8, 0xf2, 0xee, 0xeb, 0xf5,
+    0xa6, 0x99, 0x28, 0x24, 0x6c, 0x2b, 0x75, 0x5d, 0xf8, 0xd3, 0x86, 0x17, 0xfb, 0xc0, 0x7b, 0xb3,
+    0x58, 0xdb, 0xc7, 0x4b, 0xff, 0x04, 0x50, 0xe9, 0x88, 0x69, 0xc9, 0x2a, 0xab, 0xfd, 0x5b, 0x1b,
+    0x8a, 0xd9, 0xec, 0x27, 0x44, 0x0e, 0x33, 0xc8, 0x6b, 0x93, 0x32, 0x48, 0xb6, 0x30, 0x43, 0xa5,
+];
+
+// A decoded .cbs file: the save itself plus the title stored in the header
+#[derive(Debug, Clone)]
+pub struct CbsSave {
+    pub save: SaveDir,
+    pub title: String,
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+// Encryption and decryption are the same operation
+fn rc4_crypt(data: &mut [u8]) {
+    let mut state = CBS_RC4_STATE;
+    let mut j = 0u8;
+    for (n, byte) in data.iter_mut().enumerate() {
+        let i = (n + 1) as u8;
+        j = j.wrapping_add(state[i as usize]);
+        state.swap(i as usize, j as usize);
+        *byte ^= state[state[i as usize].wrapping_add(state[j as usize]) as usize];
+    }
+}
+
+fn name_field(bytes: &[u8]) -> String {
+    sjis::decode(bytes)
+}
+
+fn put_name_field(out: &mut Vec<u8>, name: &str, size: usize) {
+    out.extend_from_slice(&sjis::encode_field(name, size));
+}
+
+fn time_field(bytes: &[u8]) -> Ps2Time {
+    let time = Ps2Time::from_bytes(bytes.try_into().unwrap());
+    if time.is_unset() {
+        Ps2Time::now()
+    } else {
+        time
+    }
+}
+
+pub fn read_cbs<R: Read>(reader: &mut R) -> io::Result<CbsSave> {
+    let mut magic = [0u8; 4];
+    reader.read_exact(&mut magic)?;
+    if &magic != CBS_MAGIC {
+        return Err(invalid("Not a .cbs file: missing CFU magic"));
+    }
+    let _version = reader.read_u32::<LittleEndian>()?;
+    let header_len = reader.read_u32::<LittleEndian>()? as usize;
+    if header_len < CBS_TITLE_OFFSET + CBS_NAME_SIZE {
+        return Err(invalid("Header of .cbs file is too short"));
+    }
+
+    let mut header = vec![0u8; 12];
+    header.extend(psu::read_exact_vec(reader, header_len as u64 - 12)?);
+    let mut cursor = Cursor::new(&header[0x0C..]);
+    let body_len = cursor.read_u32::<LittleEndian>()?;
+    let _compressed_len = cursor.read_u32::<LittleEndian>()?;
+    let dir_name = name_field(&header[0x14..0x34]);
+    let created = time_field(&header[0x34..0x3C]);
+    let modified = time_field(&header[0x3C..0x44]);
+    let mut dir_mode = u32::from_le_bytes(header[0x4C..0x50].try_into().unwrap()) as u16;
+    let title = name_field(&header[CBS_TITLE_OFFSET..]);
+    // Not every writer fills in the mode
+    if dir_mode & CBS_DIRECTORY_BIT == 0 {
+        dir_mode = DIR_MODE;
+    }
+
+    let mut compressed = Vec::new();
+    reader.read_to_end(&mut compressed)?;
+    rc4_crypt(&mut compressed);
+    let mut body = Vec::new();
+    ZlibDecoder::new(&compressed[..])
+        .take(body_len as u64)
+        .read_to_end(&mut body)?;
+    if body.len() != body_len as usize {
+        return Err(io::Error::new(
+            io::ErrorKind::UnexpectedEof,
+            "Compressed body of .cbs file is truncated",
+        ));
+    }
+
+    let mut files = Vec::new();
+    let mut rest = &body[..];
+    while !rest.is_empty() {
+        if rest.len() < CBS_FILE_HEADER_SIZE {
+            return Err(invalid("Truncated file header in .cbs body"));
+        }
+        let (file_header, data) = rest.split_at(CBS_FILE_HEADER_SIZE);
+        let size = u32::from_le_bytes(file_header[0x10..0x14].try_into().unwrap()) as usize;
+        let mode = u16::from_le_bytes([file_header[0x14], file_header[0x15]]);
+        let name = name_field(&file_header[0x20..0x40]);
+        if data.len() < size {
+            return Err(invalid("Truncated file data in .cbs body"));
+        }
+        if mode & CBS_DIRECTORY_BIT != 0 {
+            return Err(invalid("Subdirectories in .cbs files are not supported"));
+        }
+
+        let mut entry = RawFSEntry::new(&name, mode, size as u32, 0, Ps2Time::default());
+        entry.set_created(time_field(&file_header[0x00..0x08]));
+        entry.set_modified(time_field(&file_header[0x08..0x10]));
+        files.push(SaveFile {
+            entry,
+            data: data[..size].to_vec(),
+        });
+        rest = &data[size..];
+    }
+
+    let mut entry = RawFSEntry::new(&dir_name, dir_mode, files.len() as u32 + 2, 0, created);
+    entry.set_modified(modified);
+    Ok(CbsSave {
+        save: SaveDir { entry, files },
+        title,
+    })
+}
+
+pub fn write_cbs<W: Write>(save: &SaveDir, title: &str, writer: &mut W) -> io::Result<()> {
+    let mut body = Vec::new();
+    for file in &save.files {
+        body.extend_from_slice(&file.entry.created().to_bytes());
+        body.extend_from_slice(&file.entry.modified().to_bytes());
+        body.write_u32::<LittleEndian>(file.data.len() as u32)?;
+        body.write_u16::<LittleEndian>(file.entry.mode)?;
+        body.resize(body.len() + 10, 0);
+        put_name_field(&mut body, &file.name(), CBS_NAME_SIZE);
+        body.extend_from_slice(&file.data);
+    }
+
+    let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(&body)?;
+    let mut compressed = encoder.finish()?;
+    rc4_crypt(&mut compressed);
+
+    let mut header = Vec::with_capacity(CBS_HEADER_SIZE);
+    header.extend_from_slice(CBS_MAGIC);
+    header.write_u32::<LittleEndian>(CBS_VERSION)?;
+    header.write_u32::<LittleEndian>(CBS_HEADER_SIZE as u32)?;
+    header.write_u32::<LittleEndian>(body.len() as u32)?;
+    header.write_u32::<LittleEndian>((CBS_HEADER_SIZE + compressed.len()) as u32)?;
+    put_name_field(&mut header, &save.name(), CBS_NAME_SIZE);
+    header.extend_from_slice(&save.entry.created().to_bytes());
+    header.extend_from_slice(&save.entry.modified().to_bytes());
+    header.resize(0x4C, 0);
+    header.write_u32::<LittleEndian>(save.entry.mode as u32)?;
+    header.resize(CBS_TITLE_OFFSET, 0);
+    put_name_field(&mut header, title, CBS_HEADER_SIZE - CBS_TITLE_OFFSET);
+
+    writer.write_all(&header)?;
+    writer.write_all(&compressed)?;
+    writer.flush()
+}
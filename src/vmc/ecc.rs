@@ -0,0 +1,142 @@
+// Hamming-style ECC used by PS2 memory cards. Every 512-byte page is split into
+// four 128-byte chunks, each protected by three bytes (column parity and two line
+// parities) stored at the start of the page's 16-byte spare area.
+
+pub const ECC_CHUNK_SIZE: usize = 128;
+pub const ECC_BYTES_PER_CHUNK: usize = 3;
+
+// Bit masks selecting the byte bits that feed each column parity bit
+const COLUMN_PARITY_MASKS: [u8; 7] = [0x55, 0x33, 0x0F, 0x00, 0xAA, 0xCC, 0xF0];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EccStatus {
+    Ok,
+    Corrected,
+    Uncorrectable,
+}
+
+// Page-by-page result of checking a raw dump
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct EccReport {
+    pub pages_good: u64,
+    pub pages_corrected: u64,
+    pub pages_uncorrectable: u64,
+    pub uncorrectable_pages: Vec<u64>,
+}
+
+impl EccReport {
+    pub fn add(&mut self, page: u64, status: EccStatus) {
+        match status {
+            EccStatus::Ok => self.pages_good += 1,
+            EccStatus::Corrected => self.pages_corrected += 1,
+            EccStatus::Uncorrectable => {
+                self.pages_uncorrectable += 1;
+                self.uncorrectable_pages.push(page);
+            }
+        }
+    }
+}
+
+const fn parity(byte: u8) -> u8 {
+    (byte.count_ones() & 1) as u8
+}
+
+const fn build_column_parity_table() -> [u8; 256] {
+    let mut table = [0u8; 256];
+    let mut byte = 0;
+    while byte < 256 {
+        let mut mask = 0u8;
+        let mut i = 0;
+        while i < COLUMN_PARITY_MASKS.len() {
+            mask |= parity(byte as u8 & COLUMN_PARITY_MASKS[i]) << i;
+            i += 1;
+        }
+        table[byte] = mask;
+        byte += 1;
+    }
+    table
+}
+
+// Column parity contribution of every possible byte value
+const COLUMN_PARITY_TABLE: [u8; 256] = build_column_parity_table();
+
+// ECC of one 128-byte chunk: [column parity, line parity 0, line parity 1]
+pub fn ecc_calculate(chunk: &[u8]) -> [u8; 3] {
+    let mut column_parity = 0x77u8;
+    let mut line_parity_0 = 0x7Fu8;
+    let mut line_parity_1 = 0x7Fu8;
+
+    for (i, &byte) in chunk.iter().enumerate() {
+        column_parity ^= COLUMN_PARITY_TABLE[byte as usize];
+        if parity(byte) != 0 {
+            line_parity_0 ^= !(i as u8);
+            line_parity_1 ^= i as u8;
+        }
+    }
+
+    [column_parity, line_parity_0 & 0x7F, line_parity_1]
+}
+
+// Check one chunk against its stored ECC, fixing a single flipped bit in either
+pub fn ecc_check(chunk: &mut [u8], ecc: &mut [u8]) -> EccStatus {
+    let computed = ecc_calculate(chunk);
+    let cp_diff = (computed[0] ^ ecc[0]) & 0x77;
+    let lp0_diff = (computed[1] ^ ecc[1]) & 0x7F;
+    let lp1_diff = (computed[2] ^ ecc[2]) & 0x7F;
+
+    // Unused parity bits are not part of the code
+    if cp_diff == 0 && lp0_diff == 0 && lp1_diff == 0 {
+        return EccStatus::Ok;
+    }
+
+    let lp_comp = lp0_diff ^ lp1_diff;
+    let cp_comp = (cp_diff >> 4) ^ (cp_diff & 0x07);
+
+    if lp_comp == 0x7F && cp_comp == 0x07 {
+        // Single bit error in the data: line parity 1 names the byte, column parity the bit
+        chunk[lp1_diff as usize] ^= 1 << (cp_diff >> 4);
+        return EccStatus::Corrected;
+    }
+
+    if lp_comp.count_ones() + cp_comp.count_ones() == 1 {
+        // Single bit error in the ECC itself
+        ecc[..ECC_BYTES_PER_CHUNK].copy_from_slice(&computed);
+        return EccStatus::Corrected;
+    }
+
+    EccStatus::Uncorrectable
+}
+
+// Spare area for a page: the ECC of each chunk followed by zero padding
+pub fn ecc_calculate_page(page: &[u8], spare_size: usize) -> Vec<u8> {
+    let mut spare = Vec::with_capacity(spare_size);
+    for chunk in page.chunks(ECC_CHUNK_SIZE) {
+        spare.extend_from_slice(&ecc_calculate(chunk));
+    }
+    spare.resize(spare_size, 0);
+    spare
+}
+
+// Check and correct a page in place using its spare area.
+// Erased pages (all 0xFF, spare included) carry no ECC and count as good.
+pub fn ecc_check_page(page: &mut [u8], spare: &mut [u8]) -> EccStatus {
+    if page.iter().chain(spare.iter()).all(|&b| b == 0xFF) {
+        return EccStatus::Ok;
+    }
+
+    let mut status = EccStatus::Ok;
+    for (chunk, ecc) in page
+        .chunks_mut(ECC_CHUNK_SIZE)
+        .zip(spare.chunks_mut(ECC_BYTES_PER_CHUNK))
+    {
+        if ecc.len() < ECC_BYTES_PER_CHUNK {
+            return EccStatus::Uncorrectable;
+        }
+        match ecc_check(chunk, ecc) {
+            EccStatus::Ok => {}
+            EccStatus::Corrected => status = EccStatus::Corrected,
+            EccStatus::Uncorrectable => return EccStatus::Uncorrectable,
+        }
+    }
+    status
+}
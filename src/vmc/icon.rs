@@ -0,0 +1,255 @@
+// PS2 3D save icons (.ico). A 20-byte header is followed by the vertex list (three
+// vertices per triangle), the animation block and the 128x128 RGB555 texture.
+//
+// header:  magic 0x00010000, shape count, texture type, reserved, vertex count
+// vertex:  one position [i16; 4] per shape, normal [i16; 4], uv [i16; 2], colour RGBA
+// anim:    magic 0x01, frame length, speed f32, play offset, frame count, then per
+//          frame: shape, key count, 2 reserved, (time f32, value f32) per key
+// texture: raw pixels, or (bit 3 of the type) a u32 size followed by RLE words
+//
+// Coordinates, normals and uvs are 4.12 fixed point.
+
+use crate::vmc::psu;
+use byteorder::{LittleEndian, ReadBytesExt};
+use std::io::{self, Cursor, Read};
+
+pub const ICON_TEXTURE_SIZE: usize = 128;
+const ICON_MAGIC: u32 = 0x0001_0000;
+const ICON_ANIMATION_MAGIC: u32 = 0x01;
+const ICON_TEXTURE_PIXELS: usize = ICON_TEXTURE_SIZE * ICON_TEXTURE_SIZE;
+const TEXTURE_PRESENT: u32 = 0x04;
+const TEXTURE_COMPRESSED: u32 = 0x08;
+const FIXED_ONE: f32 = 4096.0;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct IconVertex {
+    pub positions: Vec<[f32; 3]>, // one per animation shape
+    pub normal: [f32; 3],
+    pub uv: [f32; 2],
+    pub color: [u8; 4],
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct IconKey {
+    pub time: f32,
+    pub value: f32,
+}
+
+// Weight curve of one animation shape over the animation's frames
+#[derive(Debug, Clone, PartialEq)]
+pub struct IconFrame {
+    pub shape: u32,
+    pub keys: Vec<IconKey>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct IconAnimation {
+    pub frame_length: u32,
+    pub speed: f32,
+    pub play_offset: u32,
+    pub frames: Vec<IconFrame>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Icon {
+    pub shape_count: u32,
+    pub texture_type: u32,
+    pub vertices: Vec<IconVertex>,
+    pub animation: IconAnimation,
+    pub texture: Option<Vec<u16>>, // 128x128 RGB555, bit 15 is alpha
+}
+
+impl IconFrame {
+    // Weight of the frame's shape at `time`, interpolated linearly between keys
+    pub fn weight_at(&self, time: f32) -> f32 {
+        let (Some(first), Some(last)) = (self.keys.first(), self.keys.last()) else {
+            return 0.0;
+        };
+        if time <= first.time {
+            return first.value;
+        }
+        self.keys
+            .windows(2)
+            .find(|pair| time <= pair[1].time)
+            .map_or(last.value, |pair| {
+                let span = pair[1].time - pair[0].time;
+                if span <= 0.0 {
+                    return pair[1].value;
+                }
+                let t = (time - pair[0].time) / span;
+                pair[0].value + (pair[1].value - pair[0].value) * t
+            })
+    }
+}
+
+impl Icon {
+    // Positions of every vertex in one animation shape
+    pub fn shape_positions(&self, shape: usize) -> Vec<[f32; 3]> {
+        self.vertices.iter().map(|v| v.positions[shape]).collect()
+    }
+
+    // Vertex positions `time` frames into the animation: the shapes blended by the
+    // weights of their animation frames. Icons without usable keys show shape 0.
+    pub fn positions_at(&self, time: f32) -> Vec<[f32; 3]> {
+        let time = match self.animation.frame_length {
+            0 => 0.0,
+            length => time.rem_euclid(length as f32),
+        };
+        let weights: Vec<(usize, f32)> = self
+            .animation
+            .frames
+            .iter()
+            .filter(|f| f.shape < self.shape_count)
+            .map(|f| (f.shape as usize, f.weight_at(time)))
+            .filter(|&(_, w)| w != 0.0)
+            .collect();
+        let total: f32 = weights.iter().map(|&(_, w)| w).sum();
+        if weights.is_empty() || total.abs() < f32::EPSILON {
+            return self.shape_positions(0);
+        }
+
+        self.vertices
+            .iter()
+            .map(|v| {
+                let mut p = [0f32; 3];
+                for &(shape, weight) in &weights {
+                    for (x, s) in p.iter_mut().zip(v.positions[shape]) {
+                        *x += s * weight / total;
+                    }
+                }
+                p
+            })
+            .collect()
+    }
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+fn read_fixed_vector<R: Read>(reader: &mut R) -> io::Result<[f32; 3]> {
+    let mut v = [0f32; 4];
+    for x in &mut v {
+        *x = reader.read_i16::<LittleEndian>()? as f32 / FIXED_ONE;
+    }
+    Ok([v[0], v[1], v[2]])
+}
+
+fn read_animation<R: Read>(reader: &mut R) -> io::Result<IconAnimation> {
+    if reader.read_u32::<LittleEndian>()? != ICON_ANIMATION_MAGIC {
+        return Err(invalid("Missing animation header in icon"));
+    }
+    let frame_length = reader.read_u32::<LittleEndian>()?;
+    let speed = reader.read_f32::<LittleEndian>()?;
+    let play_offset = reader.read_u32::<LittleEndian>()?;
+    let frame_count = reader.read_u32::<LittleEndian>()?;
+
+    let mut frames = Vec::new();
+    for _ in 0..frame_count {
+        let shape = reader.read_u32::<LittleEndian>()?;
+        let key_count = reader.read_u32::<LittleEndian>()?;
+        reader.read_u32::<LittleEndian>()?;
+        reader.read_u32::<LittleEndian>()?;
+        let mut keys = Vec::new();
+        for _ in 0..key_count {
+            keys.push(IconKey {
+                time: reader.read_f32::<LittleEndian>()?,
+                value: reader.read_f32::<LittleEndian>()?,
+            });
+        }
+        frames.push(IconFrame { shape, keys });
+    }
+
+    Ok(IconAnimation {
+        frame_length,
+        speed,
+        play_offset,
+        frames,
+    })
+}
+
+// A set bit 15 starts a run of (0x10000 - code) literal pixels; otherwise the next
+// pixel is repeated `code` times
+fn decompress_texture(data: &[u8]) -> io::Result<Vec<u16>> {
+    let mut cursor = Cursor::new(data);
+    let mut pixels = Vec::with_capacity(ICON_TEXTURE_PIXELS);
+    while pixels.len() < ICON_TEXTURE_PIXELS {
+        let code = cursor.read_u16::<LittleEndian>()?;
+        if code & 0x8000 != 0 {
+            for _ in 0..0x10000 - code as u32 {
+                pixels.push(cursor.read_u16::<LittleEndian>()?);
+            }
+        } else {
+            let pixel = cursor.read_u16::<LittleEndian>()?;
+            pixels.extend(std::iter::repeat_n(pixel, code as usize));
+        }
+    }
+    pixels.truncate(ICON_TEXTURE_PIXELS);
+    Ok(pixels)
+}
+
+fn read_texture<R: Read>(reader: &mut R, texture_type: u32) -> io::Result<Option<Vec<u16>>> {
+    if texture_type & TEXTURE_PRESENT == 0 {
+        return Ok(None);
+    }
+    if texture_type & TEXTURE_COMPRESSED != 0 {
+        let size = reader.read_u32::<LittleEndian>()?;
+        let data = psu::read_exact_vec(reader, size as u64)?;
+        return decompress_texture(&data)
+            .map(Some)
+            .map_err(|_| invalid("Compressed icon texture is truncated"));
+    }
+
+    let mut pixels = vec![0u16; ICON_TEXTURE_PIXELS];
+    reader.read_u16_into::<LittleEndian>(&mut pixels)?;
+    Ok(Some(pixels))
+}
+
+pub fn read_icon(data: &[u8]) -> io::Result<Icon> {
+    let mut cursor = Cursor::new(data);
+    if cursor.read_u32::<LittleEndian>()? != ICON_MAGIC {
+        return Err(invalid("Not a PS2 icon file"));
+    }
+    let shape_count = cursor.read_u32::<LittleEndian>()?;
+    let texture_type = cursor.read_u32::<LittleEndian>()?;
+    cursor.read_u32::<LittleEndian>()?;
+    let vertex_count = cursor.read_u32::<LittleEndian>()?;
+    if shape_count == 0 || vertex_count % 3 != 0 {
+        return Err(invalid("Bad shape or vertex count in icon header"));
+    }
+
+    let vertex_size = shape_count as u64 * 8 + 16;
+    let vertex_bytes = (vertex_count as u64)
+        .checked_mul(vertex_size)
+        .ok_or_else(|| invalid("Bad shape or vertex count in icon header"))?;
+    if vertex_bytes > data.len() as u64 {
+        return Err(invalid("Icon vertex list is truncated"));
+    }
+    let mut vertices = Vec::with_capacity(vertex_count as usize);
+    for _ in 0..vertex_count {
+        let positions = (0..shape_count)
+            .map(|_| read_fixed_vector(&mut cursor))
+            .collect::<io::Result<Vec<_>>>()?;
+        let normal = read_fixed_vector(&mut cursor)?;
+        let u = cursor.read_i16::<LittleEndian>()? as f32 / FIXED_ONE;
+        let v = cursor.read_i16::<LittleEndian>()? as f32 / FIXED_ONE;
+        let mut color = [0u8; 4];
+        cursor.read_exact(&mut color)?;
+        vertices.push(IconVertex {
+            positions,
+            normal,
+            uv: [u, v],
+            color,
+        });
+    }
+
+    let animation = read_animation(&mut cursor)?;
+    let texture = read_texture(&mut cursor, texture_type)?;
+    Ok(Icon {
+        shape_count,
+        texture_type,
+        vertices,
+        animation,
+        texture,
+    })
+}
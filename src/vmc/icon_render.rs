@@ -0,0 +1,266 @@
+// Software rasteriser for PS2 save icons. Triangles are drawn with an orthographic
+// front view, a depth buffer and nearest-neighbour texturing, the texture being
+// modulated by the vertex colour the way the GS does (0x80 = 1.0).
+
+use crate::vmc::icon::{ICON_TEXTURE_SIZE, Icon};
+use std::io::{self, Write};
+
+// Fraction of the image the icon's bounding box may fill
+const ICON_FILL: f32 = 0.9;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RgbaImage {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<u8>,
+}
+
+impl RgbaImage {
+    pub fn new(width: u32, height: u32) -> Self {
+        RgbaImage {
+            width,
+            height,
+            pixels: vec![0; width as usize * height as usize * 4],
+        }
+    }
+
+    pub fn pixel(&self, x: u32, y: u32) -> [u8; 4] {
+        let i = (y as usize * self.width as usize + x as usize) * 4;
+        self.pixels[i..i + 4].try_into().unwrap()
+    }
+}
+
+fn rgb555_to_rgb(pixel: u16) -> [f32; 3] {
+    let channel = |shift: u16| ((pixel >> shift) & 0x1F) as f32 * 255.0 / 31.0;
+    [channel(0), channel(5), channel(10)]
+}
+
+// Scale and centre that fit every shape of the icon into a `size` x `size` image at
+// any rotation, so all frames of an animation share the same framing
+fn fit(icon: &Icon, size: u32) -> (f32, [f32; 3]) {
+    let mut min = [f32::MAX; 3];
+    let mut max = [f32::MIN; 3];
+    for p in icon.vertices.iter().flat_map(|v| &v.positions) {
+        for axis in 0..3 {
+            min[axis] = min[axis].min(p[axis]);
+            max[axis] = max[axis].max(p[axis]);
+        }
+    }
+    let center = [0, 1, 2].map(|axis| (min[axis] + max[axis]) / 2.0);
+    let radius = icon
+        .vertices
+        .iter()
+        .flat_map(|v| &v.positions)
+        .map(|p| (p[0] - center[0]).hypot(p[2] - center[2]))
+        .fold(0.0, f32::max);
+    let extent = (2.0 * radius).max(max[1] - min[1]);
+    if !extent.is_finite() || extent <= 0.0 {
+        return (1.0, [0.0; 3]);
+    }
+    (size as f32 * ICON_FILL / extent, center)
+}
+
+// Draw the icon with the given vertex positions, turned `rotation` radians around
+// the vertical axis. Screen y grows downwards like the icon's own y axis; smaller z
+// is closer to the viewer.
+pub fn rasterize(icon: &Icon, positions: &[[f32; 3]], rotation: f32, size: u32) -> RgbaImage {
+    let mut image = RgbaImage::new(size, size);
+    let mut depth = vec![f32::INFINITY; size as usize * size as usize];
+    let (scale, center) = fit(icon, size);
+    let half = size as f32 / 2.0;
+    let (sin, cos) = rotation.sin_cos();
+
+    let screen: Vec<[f32; 3]> = positions
+        .iter()
+        .map(|p| {
+            let [x, y, z] = [0, 1, 2].map(|axis| p[axis] - center[axis]);
+            [
+                half + (x * cos - z * sin) * scale,
+                half + y * scale,
+                x * sin + z * cos,
+            ]
+        })
+        .collect();
+
+    for (triangle, corners) in screen.chunks_exact(3).zip(icon.vertices.chunks_exact(3)) {
+        let [a, b, c] = [triangle[0], triangle[1], triangle[2]];
+        let area = (b[0] - a[0]) * (c[1] - a[1]) - (b[1] - a[1]) * (c[0] - a[0]);
+        if area.abs() < f32::EPSILON {
+            continue;
+        }
+
+        let min_x = a[0].min(b[0]).min(c[0]).floor().max(0.0) as u32;
+        let max_x = (a[0].max(b[0]).max(c[0]).ceil() as u32).min(size);
+        let min_y = a[1].min(b[1]).min(c[1]).floor().max(0.0) as u32;
+        let max_y = (a[1].max(b[1]).max(c[1]).ceil() as u32).min(size);
+
+        for y in min_y..max_y {
+            for x in min_x..max_x {
+                let (px, py) = (x as f32 + 0.5, y as f32 + 0.5);
+                let edge = |p: [f32; 3], q: [f32; 3]| {
+                    ((q[0] - p[0]) * (py - p[1]) - (q[1] - p[1]) * (px - p[0])) / area
+                };
+                let weights = [edge(b, c), edge(c, a), edge(a, b)];
+                if weights.iter().any(|&w| w < 0.0) {
+                    continue;
+                }
+
+                let z = weights[0] * a[2] + weights[1] * b[2] + weights[2] * c[2];
+                let index = y as usize * size as usize + x as usize;
+                if z >= depth[index] {
+                    continue;
+                }
+                depth[index] = z;
+
+                let blend = |f: &dyn Fn(usize) -> f32| {
+                    weights[0] * f(0) + weights[1] * f(1) + weights[2] * f(2)
+                };
+                let texel = match &icon.texture {
+                    Some(texture) => {
+                        let wrap = |t: f32| {
+                            ((t * ICON_TEXTURE_SIZE as f32).floor() as i64)
+                                .rem_euclid(ICON_TEXTURE_SIZE as i64)
+                                as usize
+                        };
+                        let u = wrap(blend(&|i| corners[i].uv[0]));
+                        let v = wrap(blend(&|i| corners[i].uv[1]));
+                        rgb555_to_rgb(texture[v * ICON_TEXTURE_SIZE + u])
+                    }
+                    None => [255.0; 3],
+                };
+
+                let out = &mut image.pixels[index * 4..index * 4 + 4];
+                for channel in 0..3 {
+                    let modulate = blend(&|i| corners[i].color[channel] as f32) / 128.0;
+                    out[channel] = (texel[channel] * modulate).clamp(0.0, 255.0) as u8;
+                }
+                out[3] = 255;
+            }
+        }
+    }
+    image
+}
+
+// Render one animation shape of the icon
+pub fn render_icon(icon: &Icon, shape: usize, size: u32) -> io::Result<RgbaImage> {
+    if shape >= icon.shape_count as usize {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "Icon has {} animation shapes, frame {shape} does not exist",
+                icon.shape_count
+            ),
+        ));
+    }
+    Ok(rasterize(icon, &icon.shape_positions(shape), 0.0, size))
+}
+
+// How an animated icon is sampled: `frames` images spread over one loop of the
+// icon's animation, optionally turning once around like the PS2 browser does
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TurntableOptions {
+    pub frames: u32,
+    pub size: u32,
+    pub delay_ms: u16,
+    pub rotate: bool,
+}
+
+impl Default for TurntableOptions {
+    fn default() -> Self {
+        TurntableOptions {
+            frames: 48,
+            size: 128,
+            delay_ms: 50,
+            rotate: true,
+        }
+    }
+}
+
+pub fn render_turntable(icon: &Icon, options: &TurntableOptions) -> Vec<RgbaImage> {
+    let frames = options.frames.max(1);
+    let length = icon.animation.frame_length as f32;
+    (0..frames)
+        .map(|i| {
+            let progress = i as f32 / frames as f32;
+            let rotation = if options.rotate {
+                progress * std::f32::consts::TAU
+            } else {
+                0.0
+            };
+            let positions = icon.positions_at(progress * length);
+            rasterize(icon, &positions, rotation, options.size)
+        })
+        .collect()
+}
+
+fn gif_dimension(value: u32) -> io::Result<u16> {
+    u16::try_from(value).map_err(|_| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("{value} pixels is too large for a GIF"),
+        )
+    })
+}
+
+// Looping GIF; colours are quantised per frame and fully transparent pixels stay transparent
+pub fn write_gif<W: Write>(frames: &[RgbaImage], delay_ms: u16, writer: W) -> io::Result<()> {
+    let Some(first) = frames.first() else {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "No frames to write",
+        ));
+    };
+    let (width, height) = (gif_dimension(first.width)?, gif_dimension(first.height)?);
+    let mut encoder = gif::Encoder::new(writer, width, height, &[]).map_err(io::Error::other)?;
+    encoder
+        .set_repeat(gif::Repeat::Infinite)
+        .map_err(io::Error::other)?;
+    for image in frames {
+        let mut pixels = image.pixels.clone();
+        let mut frame = gif::Frame::from_rgba_speed(width, height, &mut pixels, 10);
+        frame.delay = delay_ms / 10;
+        frame.dispose = gif::DisposalMethod::Background;
+        encoder.write_frame(&frame).map_err(io::Error::other)?;
+    }
+    Ok(())
+}
+
+// Looping animated PNG with full 8-bit alpha
+pub fn write_apng<W: Write>(frames: &[RgbaImage], delay_ms: u16, writer: W) -> io::Result<()> {
+    let Some(first) = frames.first() else {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "No frames to write",
+        ));
+    };
+    let mut encoder = png::Encoder::new(writer, first.width, first.height);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder
+        .set_animated(frames.len() as u32, 0)
+        .map_err(io::Error::other)?;
+    encoder
+        .set_frame_delay(delay_ms, 1000)
+        .map_err(io::Error::other)?;
+    encoder
+        .set_dispose_op(png::DisposeOp::Background)
+        .map_err(io::Error::other)?;
+    let mut png_writer = encoder.write_header().map_err(io::Error::other)?;
+    for image in frames {
+        png_writer
+            .write_image_data(&image.pixels)
+            .map_err(io::Error::other)?;
+    }
+    png_writer.finish().map_err(io::Error::other)
+}
+
+pub fn write_png<W: Write>(image: &RgbaImage, writer: W) -> io::Result<()> {
+    let mut encoder = png::Encoder::new(writer, image.width, image.height);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    let mut png_writer = encoder.write_header().map_err(io::Error::other)?;
+    png_writer
+        .write_image_data(&image.pixels)
+        .map_err(io::Error::other)?;
+    png_writer.finish().map_err(io::Error::other)
+}
@@ -0,0 +1,535 @@
+// LZARI compression (Haruhiko Okumura, 1989) as used by Action Replay MAX saves:
+// LZSS over a 4 KB ring buffer with literals, match lengths and match positions
+// coded by an adaptive arithmetic coder. The bit stream carries no length header;
+// the uncompressed size is stored separately by the container.
+
+use std::io;
+
+const RING_SIZE: usize = 4096;
+const MAX_MATCH: usize = 60;
+// Matches of this length or shorter are sent as literals
+const THRESHOLD: usize = 2;
+const NIL: usize = RING_SIZE;
+const CHAR_COUNT: usize = 256 - THRESHOLD + MAX_MATCH;
+
+const PRECISION: u32 = 15;
+const Q1: u64 = 1 << PRECISION;
+const Q2: u64 = 2 * Q1;
+const Q3: u64 = 3 * Q1;
+const Q4: u64 = 4 * Q1;
+const MAX_CUM: u32 = (Q1 - 1) as u32;
+
+// Adaptive frequency model shared by the encoder and decoder
+struct Model {
+    char_to_sym: Vec<usize>,
+    sym_to_char: Vec<usize>,
+    sym_freq: Vec<u32>,
+    sym_cum: Vec<u32>,
+    position_cum: Vec<u32>,
+}
+
+impl Model {
+    fn new() -> Self {
+        let mut model = Model {
+            char_to_sym: vec![0; CHAR_COUNT],
+            sym_to_char: vec![0; CHAR_COUNT + 1],
+            sym_freq: vec![0; CHAR_COUNT + 1],
+            sym_cum: vec![0; CHAR_COUNT + 1],
+            position_cum: vec![0; RING_SIZE + 1],
+        };
+        for sym in (1..=CHAR_COUNT).rev() {
+            let ch = sym - 1;
+            model.char_to_sym[ch] = sym;
+            model.sym_to_char[sym] = ch;
+            model.sym_freq[sym] = 1;
+            model.sym_cum[sym - 1] = model.sym_cum[sym] + 1;
+        }
+        // Sentinel, never equal to sym_freq[1]
+        model.sym_freq[0] = 0;
+        for i in (1..=RING_SIZE).rev() {
+            model.position_cum[i - 1] = model.position_cum[i] + 10000 / (i as u32 + 200);
+        }
+        model
+    }
+
+    fn update(&mut self, sym: usize) {
+        if self.sym_cum[0] >= MAX_CUM {
+            let mut cum = 0;
+            for i in (1..=CHAR_COUNT).rev() {
+                self.sym_cum[i] = cum;
+                self.sym_freq[i] = (self.sym_freq[i] + 1) >> 1;
+                cum += self.sym_freq[i];
+            }
+            self.sym_cum[0] = cum;
+        }
+
+        let mut i = sym;
+        while self.sym_freq[i] == self.sym_freq[i - 1] {
+            i -= 1;
+        }
+        if i < sym {
+            let ch_i = self.sym_to_char[i];
+            let ch_sym = self.sym_to_char[sym];
+            self.sym_to_char[i] = ch_sym;
+            self.sym_to_char[sym] = ch_i;
+            self.char_to_sym[ch_i] = sym;
+            self.char_to_sym[ch_sym] = i;
+        }
+        self.sym_freq[i] += 1;
+        for cum in &mut self.sym_cum[..i] {
+            *cum += 1;
+        }
+    }
+
+    // Symbol whose cumulative range contains x
+    fn search_sym(&self, x: u32) -> usize {
+        let (mut i, mut j) = (1, CHAR_COUNT);
+        while i < j {
+            let k = (i + j) / 2;
+            if self.sym_cum[k] > x {
+                i = k + 1;
+            } else {
+                j = k;
+            }
+        }
+        i
+    }
+
+    fn search_position(&self, x: u32) -> usize {
+        let (mut i, mut j) = (1, RING_SIZE);
+        while i < j {
+            let k = (i + j) / 2;
+            if self.position_cum[k] > x {
+                i = k + 1;
+            } else {
+                j = k;
+            }
+        }
+        i - 1
+    }
+}
+
+fn initial_ring() -> Vec<u8> {
+    let mut ring = vec![0u8; RING_SIZE + MAX_MATCH - 1];
+    ring[..RING_SIZE - MAX_MATCH].fill(b' ');
+    ring
+}
+
+struct BitReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+    mask: u8,
+}
+
+impl BitReader<'_> {
+    // Reads past the end yield zero bits; the coder looks a few bits ahead
+    fn bit(&mut self) -> u64 {
+        if self.mask == 0 {
+            self.mask = 0x80;
+            self.pos += 1;
+        }
+        let byte = self.data.get(self.pos - 1).copied().unwrap_or(0);
+        let bit = (byte & self.mask != 0) as u64;
+        self.mask >>= 1;
+        bit
+    }
+}
+
+struct Decoder<'a> {
+    bits: BitReader<'a>,
+    model: Model,
+    low: u64,
+    high: u64,
+    value: u64,
+}
+
+impl Decoder<'_> {
+    fn narrow(&mut self, low_cum: u64, high_cum: u64, total: u64) {
+        let range = self.high - self.low;
+        self.high = self.low + range * high_cum / total;
+        self.low += range * low_cum / total;
+        loop {
+            if self.low >= Q2 {
+                self.value = self.value.saturating_sub(Q2);
+                self.low -= Q2;
+                self.high -= Q2;
+            } else if self.low >= Q1 && self.high <= Q3 {
+                self.value = self.value.saturating_sub(Q1);
+                self.low -= Q1;
+                self.high -= Q1;
+            } else if self.high > Q2 {
+                break;
+            }
+            self.low *= 2;
+            self.high *= 2;
+            self.value = 2 * self.value + self.bits.bit();
+        }
+    }
+
+    fn target(&self, total: u32) -> u32 {
+        (((self.value.saturating_sub(self.low) + 1) * total as u64 - 1) / (self.high - self.low))
+            as u32
+    }
+
+    fn decode_char(&mut self) -> usize {
+        let total = self.model.sym_cum[0];
+        let sym = self.model.search_sym(self.target(total));
+        let (low_cum, high_cum) = (self.model.sym_cum[sym], self.model.sym_cum[sym - 1]);
+        self.narrow(low_cum as u64, high_cum as u64, total as u64);
+        let ch = self.model.sym_to_char[sym];
+        self.model.update(sym);
+        ch
+    }
+
+    fn decode_position(&mut self) -> usize {
+        let total = self.model.position_cum[0];
+        let position = self.model.search_position(self.target(total));
+        let (low_cum, high_cum) = (
+            self.model.position_cum[position + 1],
+            self.model.position_cum[position],
+        );
+        self.narrow(low_cum as u64, high_cum as u64, total as u64);
+        position
+    }
+}
+
+// Decompress `data` into exactly `out_len` bytes. `out_len` usually comes from an
+// archive header, so the output grows as it is decoded instead of being reserved.
+pub fn decompress(data: &[u8], out_len: usize) -> io::Result<Vec<u8>> {
+    let mut out = Vec::new();
+    if out_len == 0 {
+        return Ok(out);
+    }
+
+    let mut decoder = Decoder {
+        bits: BitReader {
+            data,
+            pos: 0,
+            mask: 0,
+        },
+        model: Model::new(),
+        low: 0,
+        high: Q4,
+        value: 0,
+    };
+    for _ in 0..PRECISION + 2 {
+        decoder.value = 2 * decoder.value + decoder.bits.bit();
+    }
+
+    let mut ring = initial_ring();
+    let mut r = RING_SIZE - MAX_MATCH;
+    while out.len() < out_len {
+        // Running far past the input means the stream is truncated or garbage
+        if decoder.bits.pos > data.len() + 8 {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "LZARI stream ended early",
+            ));
+        }
+        if decoder.value < decoder.low || decoder.value >= decoder.high {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "Corrupt LZARI stream",
+            ));
+        }
+
+        let c = decoder.decode_char();
+        if c < 256 {
+            out.push(c as u8);
+            ring[r] = c as u8;
+            r = (r + 1) & (RING_SIZE - 1);
+        } else {
+            let start = (r + RING_SIZE - decoder.decode_position() - 1) & (RING_SIZE - 1);
+            let length = c - 255 + THRESHOLD;
+            for k in 0..length {
+                let byte = ring[(start + k) & (RING_SIZE - 1)];
+                out.push(byte);
+                ring[r] = byte;
+                r = (r + 1) & (RING_SIZE - 1);
+            }
+        }
+    }
+    out.truncate(out_len);
+    Ok(out)
+}
+
+struct BitWriter {
+    out: Vec<u8>,
+    byte: u8,
+    mask: u8,
+}
+
+impl BitWriter {
+    fn put(&mut self, bit: bool) {
+        if bit {
+            self.byte |= self.mask;
+        }
+        self.mask >>= 1;
+        if self.mask == 0 {
+            self.out.push(self.byte);
+            self.byte = 0;
+            self.mask = 0x80;
+        }
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        for _ in 0..7 {
+            self.put(false);
+        }
+        self.out
+    }
+}
+
+struct Encoder {
+    bits: BitWriter,
+    model: Model,
+    low: u64,
+    high: u64,
+    // Pending opposite bits for intervals straddling the midpoint
+    shifts: u32,
+}
+
+impl Encoder {
+    fn output(&mut self, bit: bool) {
+        self.bits.put(bit);
+        for _ in 0..self.shifts {
+            self.bits.put(!bit);
+        }
+        self.shifts = 0;
+    }
+
+    fn narrow(&mut self, low_cum: u64, high_cum: u64, total: u64) {
+        let range = self.high - self.low;
+        self.high = self.low + range * high_cum / total;
+        self.low += range * low_cum / total;
+        loop {
+            if self.high <= Q2 {
+                self.output(false);
+            } else if self.low >= Q2 {
+                self.output(true);
+                self.low -= Q2;
+                self.high -= Q2;
+            } else if self.low >= Q1 && self.high <= Q3 {
+                self.shifts += 1;
+                self.low -= Q1;
+                self.high -= Q1;
+            } else {
+                break;
+            }
+            self.low *= 2;
+            self.high *= 2;
+        }
+    }
+
+    fn encode_char(&mut self, ch: usize) {
+        let sym = self.model.char_to_sym[ch];
+        let total = self.model.sym_cum[0] as u64;
+        let (low_cum, high_cum) = (self.model.sym_cum[sym], self.model.sym_cum[sym - 1]);
+        self.narrow(low_cum as u64, high_cum as u64, total);
+        self.model.update(sym);
+    }
+
+    fn encode_position(&mut self, position: usize) {
+        let total = self.model.position_cum[0] as u64;
+        let (low_cum, high_cum) = (
+            self.model.position_cum[position + 1],
+            self.model.position_cum[position],
+        );
+        self.narrow(low_cum as u64, high_cum as u64, total);
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        self.shifts += 1;
+        self.output(self.low >= Q1);
+        self.bits.finish()
+    }
+}
+
+// Binary search trees over the ring buffer used to find the longest match
+struct MatchTree {
+    left: Vec<usize>,
+    right: Vec<usize>,
+    parent: Vec<usize>,
+    match_position: usize,
+    match_length: usize,
+}
+
+impl MatchTree {
+    fn new() -> Self {
+        // Nodes RING_SIZE + 1 ..= RING_SIZE + 256 are the roots, one per first byte
+        MatchTree {
+            left: vec![NIL; RING_SIZE + 257],
+            right: vec![NIL; RING_SIZE + 257],
+            parent: vec![NIL; RING_SIZE + 1],
+            match_position: 0,
+            match_length: 0,
+        }
+    }
+
+    fn insert(&mut self, ring: &[u8], r: usize) {
+        let mut cmp: i32 = 1;
+        let mut p = RING_SIZE + 1 + ring[r] as usize;
+        self.right[r] = NIL;
+        self.left[r] = NIL;
+        self.match_length = 0;
+
+        loop {
+            if cmp >= 0 {
+                if self.right[p] != NIL {
+                    p = self.right[p];
+                } else {
+                    self.right[p] = r;
+                    self.parent[r] = p;
+                    return;
+                }
+            } else if self.left[p] != NIL {
+                p = self.left[p];
+            } else {
+                self.left[p] = r;
+                self.parent[r] = p;
+                return;
+            }
+
+            let mut i = 1;
+            while i < MAX_MATCH {
+                cmp = ring[r + i] as i32 - ring[p + i] as i32;
+                if cmp != 0 {
+                    break;
+                }
+                i += 1;
+            }
+            if i > THRESHOLD {
+                let distance = (r.wrapping_sub(p)) & (RING_SIZE - 1);
+                if i > self.match_length {
+                    self.match_position = distance;
+                    self.match_length = i;
+                    if i >= MAX_MATCH {
+                        break;
+                    }
+                } else if i == self.match_length && distance < self.match_position {
+                    self.match_position = distance;
+                }
+            }
+        }
+
+        // Full-length match: r replaces p in the tree
+        self.parent[r] = self.parent[p];
+        self.left[r] = self.left[p];
+        self.right[r] = self.right[p];
+        self.parent[self.left[p]] = r;
+        self.parent[self.right[p]] = r;
+        let up = self.parent[p];
+        if self.right[up] == p {
+            self.right[up] = r;
+        } else {
+            self.left[up] = r;
+        }
+        self.parent[p] = NIL;
+    }
+
+    fn delete(&mut self, p: usize) {
+        if self.parent[p] == NIL {
+            return;
+        }
+        let q = if self.right[p] == NIL {
+            self.left[p]
+        } else if self.left[p] == NIL {
+            self.right[p]
+        } else {
+            let mut q = self.left[p];
+            if self.right[q] != NIL {
+                while self.right[q] != NIL {
+                    q = self.right[q];
+                }
+                let q_parent = self.parent[q];
+                self.right[q_parent] = self.left[q];
+                self.parent[self.left[q]] = q_parent;
+                self.left[q] = self.left[p];
+                self.parent[self.left[p]] = q;
+            }
+            self.right[q] = self.right[p];
+            self.parent[self.right[p]] = q;
+            q
+        };
+        self.parent[q] = self.parent[p];
+        let up = self.parent[p];
+        if self.right[up] == p {
+            self.right[up] = q;
+        } else {
+            self.left[up] = q;
+        }
+        self.parent[p] = NIL;
+    }
+}
+
+pub fn compress(data: &[u8]) -> Vec<u8> {
+    if data.is_empty() {
+        return Vec::new();
+    }
+
+    let mut encoder = Encoder {
+        bits: BitWriter {
+            out: Vec::new(),
+            byte: 0,
+            mask: 0x80,
+        },
+        model: Model::new(),
+        low: 0,
+        high: Q4,
+        shifts: 0,
+    };
+    let mut tree = MatchTree::new();
+    let mut ring = initial_ring();
+    let mut input = data.iter().copied();
+
+    let mut s = 0;
+    let mut r = RING_SIZE - MAX_MATCH;
+    let mut len = 0;
+    for byte in input.by_ref().take(MAX_MATCH) {
+        ring[r + len] = byte;
+        len += 1;
+    }
+    for i in 1..=MAX_MATCH {
+        tree.insert(&ring, r - i);
+    }
+    tree.insert(&ring, r);
+
+    while len > 0 {
+        tree.match_length = tree.match_length.min(len);
+        if tree.match_length <= THRESHOLD {
+            tree.match_length = 1;
+            encoder.encode_char(ring[r] as usize);
+        } else {
+            encoder.encode_char(255 - THRESHOLD + tree.match_length);
+            encoder.encode_position(tree.match_position - 1);
+        }
+
+        let last_match_length = tree.match_length;
+        let mut i = 0;
+        while i < last_match_length {
+            let Some(byte) = input.next() else { break };
+            tree.delete(s);
+            ring[s] = byte;
+            // Mirror the start of the ring so matches can run past its end
+            if s < MAX_MATCH - 1 {
+                ring[s + RING_SIZE] = byte;
+            }
+            s = (s + 1) & (RING_SIZE - 1);
+            r = (r + 1) & (RING_SIZE - 1);
+            tree.insert(&ring, r);
+            i += 1;
+        }
+        while i < last_match_length {
+            tree.delete(s);
+            s = (s + 1) & (RING_SIZE - 1);
+            r = (r + 1) & (RING_SIZE - 1);
+            len -= 1;
+            if len > 0 {
+                tree.insert(&ring, r);
+            }
+            i += 1;
+        }
+    }
+
+    encoder.finish()
+}
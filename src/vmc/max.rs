@@ -0,0 +1,141 @@
+// Action Replay MAX / PowerSave archives (.max). A 0x5C-byte header is followed by one
+// LZARI stream holding every file as [length u32][name 32 bytes][data], each record
+// padded so the next one starts 8 bytes before a 16-byte boundary.
+//
+// 0x00 magic "Ps2PowerSave"   0x0C CRC-32 of header (CRC field zeroed) + compressed data
+// 0x10 directory name [32]    0x30 title shown by the MAX browser [32]
+// 0x50 compressed size + 4    0x54 number of files        0x58 uncompressed size
+
+use crate::model::save_model::{SaveDir, SaveFile};
+use crate::model::vmc_core_model::{DIR_MODE, FILE_MODE, Ps2Time, RawFSEntry};
+use crate::vmc::{lzari, psu, sjis};
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use std::io::{self, Cursor, Read, Write};
+
+pub const MAX_MAGIC: &[u8; 12] = b"Ps2PowerSave";
+const MAX_HEADER_SIZE: usize = 0x5C;
+const MAX_NAME_SIZE: usize = 32;
+const MAX_RECORD_HEADER_SIZE: usize = 4 + MAX_NAME_SIZE;
+
+// A decoded .max file: the save itself plus the title stored in the header
+#[derive(Debug, Clone)]
+pub struct MaxSave {
+    pub save: SaveDir,
+    pub title: String,
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+fn name_field(bytes: &[u8]) -> String {
+    sjis::decode(bytes)
+}
+
+fn put_name_field(out: &mut Vec<u8>, name: &str) {
+    out.extend_from_slice(&sjis::encode_field(name, MAX_NAME_SIZE));
+}
+
+// Records start 8 bytes before a 16-byte boundary
+fn record_padding(offset: usize) -> usize {
+    (offset + 8).next_multiple_of(16) - 8 - offset
+}
+
+pub fn read_max<R: Read>(reader: &mut R) -> io::Result<MaxSave> {
+    let mut header = [0u8; MAX_HEADER_SIZE];
+    reader.read_exact(&mut header)?;
+    if &header[..12] != MAX_MAGIC {
+        return Err(invalid("Not a .max file: missing Ps2PowerSave magic"));
+    }
+
+    let mut cursor = Cursor::new(&header[12..]);
+    let stored_crc = cursor.read_u32::<LittleEndian>()?;
+    let dir_name = name_field(&header[0x10..0x30]);
+    let title = name_field(&header[0x30..0x50]);
+    cursor.set_position(0x50 - 12);
+    let compressed_size = cursor.read_u32::<LittleEndian>()?;
+    let file_count = cursor.read_u32::<LittleEndian>()?;
+    let length = cursor.read_u32::<LittleEndian>()?;
+
+    let mut compressed = Vec::new();
+    if compressed_size == length {
+        // Some tools store the uncompressed size in both fields
+        reader.read_to_end(&mut compressed)?;
+    } else {
+        let size = compressed_size
+            .checked_sub(4)
+            .ok_or_else(|| invalid("Invalid compressed size in .max header"))?;
+        compressed = psu::read_exact_vec(reader, size as u64)?;
+    }
+
+    header[0x0C..0x10].fill(0);
+    let mut crc = crc32fast::Hasher::new();
+    crc.update(&header);
+    crc.update(&compressed);
+    if crc.finalize() != stored_crc {
+        return Err(invalid("CRC mismatch in .max file"));
+    }
+
+    let payload = lzari::decompress(&compressed, length as usize)?;
+    let now = Ps2Time::now();
+    let mut files = Vec::new();
+    let mut offset = 0;
+    for _ in 0..file_count {
+        let record = payload
+            .get(offset..offset + MAX_RECORD_HEADER_SIZE)
+            .ok_or_else(|| invalid("Truncated .max payload"))?;
+        let size = u32::from_le_bytes(record[..4].try_into().unwrap()) as usize;
+        let name = name_field(&record[4..]);
+        offset += MAX_RECORD_HEADER_SIZE;
+
+        let data = payload
+            .get(offset..offset + size)
+            .ok_or_else(|| invalid("Truncated .max payload"))?
+            .to_vec();
+        offset += size;
+        offset += record_padding(offset);
+
+        files.push(SaveFile {
+            entry: RawFSEntry::new(&name, FILE_MODE, size as u32, 0, now),
+            data,
+        });
+    }
+
+    Ok(MaxSave {
+        save: SaveDir {
+            entry: RawFSEntry::new(&dir_name, DIR_MODE, file_count + 2, 0, now),
+            files,
+        },
+        title,
+    })
+}
+
+// Write `save` as a .max file. The format keeps no mode bits or timestamps.
+pub fn write_max<W: Write>(save: &SaveDir, title: &str, writer: &mut W) -> io::Result<()> {
+    let mut payload = Vec::new();
+    for file in &save.files {
+        payload.write_u32::<LittleEndian>(file.data.len() as u32)?;
+        put_name_field(&mut payload, &file.name());
+        payload.extend_from_slice(&file.data);
+        payload.resize(payload.len() + record_padding(payload.len()), 0);
+    }
+    let compressed = lzari::compress(&payload);
+
+    let mut header = Vec::with_capacity(MAX_HEADER_SIZE);
+    header.extend_from_slice(MAX_MAGIC);
+    header.write_u32::<LittleEndian>(0)?;
+    put_name_field(&mut header, &save.name());
+    put_name_field(&mut header, title);
+    header.write_u32::<LittleEndian>(compressed.len() as u32 + 4)?;
+    header.write_u32::<LittleEndian>(save.files.len() as u32)?;
+    header.write_u32::<LittleEndian>(payload.len() as u32)?;
+
+    let mut crc = crc32fast::Hasher::new();
+    crc.update(&header);
+    crc.update(&compressed);
+    header[0x0C..0x10].copy_from_slice(&crc.finalize().to_le_bytes());
+
+    writer.write_all(&header)?;
+    writer.write_all(&compressed)?;
+    writer.flush()
+}
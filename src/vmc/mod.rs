@@ -1,2 +1,12 @@
+pub mod cbs;
+pub mod ecc;
+pub mod icon;
+pub mod icon_render;
+pub mod lzari;
+pub mod max;
+pub mod psu;
+pub mod psv;
 pub mod search_info;
+pub mod sjis;
+pub mod sps;
 pub mod vmc_core;
@@ -0,0 +1,101 @@
+// EMS / uLaunchELF single-save archives (.psu). The file is a sequence of 512-byte
+// directory entries in the card's own layout: the save directory, its "." and ".."
+// entries, then every file's entry followed by its data padded to a 1 KB boundary.
+
+use crate::model::save_model::{SaveDir, SaveFile};
+use crate::model::vmc_core_model::{DIR_MODE, RawFSEntry, parse_fs_entry_from_bytes};
+use std::io::{self, Read, Write};
+
+const PSU_ENTRY_SIZE: usize = 512;
+const PSU_CLUSTER_SIZE: u64 = 1024;
+
+fn read_entry<R: Read>(reader: &mut R) -> io::Result<RawFSEntry> {
+    let mut buf = [0u8; PSU_ENTRY_SIZE];
+    reader.read_exact(&mut buf)?;
+    parse_fs_entry_from_bytes(&buf)
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "Invalid .psu entry"))
+}
+
+// Read `len` bytes whose count comes from the archive itself. The buffer only grows
+// with data actually read, so a corrupt length cannot force a huge allocation.
+pub(crate) fn read_exact_vec<R: Read>(reader: &mut R, len: u64) -> io::Result<Vec<u8>> {
+    let mut data = Vec::new();
+    reader.by_ref().take(len).read_to_end(&mut data)?;
+    if (data.len() as u64) < len {
+        return Err(io::Error::new(
+            io::ErrorKind::UnexpectedEof,
+            format!("Archive ends after {} of {len} bytes", data.len()),
+        ));
+    }
+    Ok(data)
+}
+
+fn padding(length: u64) -> u64 {
+    length.next_multiple_of(PSU_CLUSTER_SIZE) - length
+}
+
+pub fn read_psu<R: Read>(reader: &mut R) -> io::Result<SaveDir> {
+    let entry = read_entry(reader)?;
+    if !entry.exists() || !entry.is_dir() || entry.length < 2 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "Not a .psu file: the first entry is not a directory",
+        ));
+    }
+
+    // "." and ".." carry nothing worth keeping
+    read_entry(reader)?;
+    read_entry(reader)?;
+
+    let mut files = Vec::new();
+    for _ in 2..entry.length {
+        let file_entry = read_entry(reader)?;
+        if file_entry.is_dir() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "'{}': nested directories are not supported in .psu files",
+                    file_entry.name_str()
+                ),
+            ));
+        }
+
+        let data = read_exact_vec(reader, file_entry.length as u64)?;
+        // Some tools leave out the padding after the last file
+        io::copy(
+            &mut reader.by_ref().take(padding(file_entry.length as u64)),
+            &mut io::sink(),
+        )?;
+        files.push(SaveFile {
+            entry: file_entry,
+            data,
+        });
+    }
+
+    Ok(SaveDir { entry, files })
+}
+
+pub fn write_psu<W: Write>(save: &SaveDir, writer: &mut W) -> io::Result<()> {
+    let mut header = save.entry;
+    header.length = save.files.len() as u32 + 2;
+    header.cluster = 0;
+    header.dir_entry = 0;
+    writer.write_all(&header.to_bytes())?;
+
+    for name in [".", ".."] {
+        let dot = RawFSEntry::new(name, DIR_MODE, 0, 0, header.created());
+        writer.write_all(&dot.to_bytes())?;
+    }
+
+    for file in &save.files {
+        let mut entry = file.entry;
+        entry.length = file.data.len() as u32;
+        entry.cluster = 0;
+        entry.dir_entry = 0;
+        writer.write_all(&entry.to_bytes())?;
+        writer.write_all(&file.data)?;
+        let pad = padding(file.data.len() as u64) as usize;
+        writer.write_all(&vec![0u8; pad])?;
+    }
+    writer.flush()
+}
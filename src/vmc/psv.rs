@@ -0,0 +1,252 @@
+// PS3 "PS2 Classics" save exports (.PSV). The file starts with a signed header, then a
+// PS2-specific header, the save directory's entry, one entry per file and the file data.
+//
+// 0x00 magic "\0VSP"   0x08 key seed [20]   0x1C HMAC-SHA1 signature [20]
+// 0x38 size of the type header (0x2C)       0x3C save type (2 = PS2)
+// 0x40 total data size, then position/size pairs for icon.sys and the three icons
+// 0x64 number of files  0x68 directory entry, followed by the file entries
+//
+// The signature is an HMAC-SHA1 over the whole file (signature field zeroed). Its key is
+// derived from the seed with the AES key and IV published by the PS3 save tools.
+
+use crate::model::save_model::{SaveDir, SaveFile};
+use crate::model::vmc_core_model::{Ps2Time, RawFSEntry};
+use crate::vmc::sjis;
+use aes::Aes128;
+use aes::cipher::{BlockDecrypt, KeyInit, generic_array::GenericArray};
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use hmac::{Hmac, Mac};
+use sha1::{Digest, Sha1};
+use std::io::{self, Cursor, Read, Write};
+
+pub const PSV_MAGIC: &[u8; 4] = b"\0VSP";
+const PSV_SEED_OFFSET: usize = 0x08;
+const PSV_SIGNATURE_OFFSET: usize = 0x1C;
+const PSV_SIGNATURE_SIZE: usize = 20;
+const PSV_TYPE_PS2: u32 = 2;
+const PSV_PS2_HEADER_SIZE: u32 = 0x2C;
+const PSV_DIR_INFO_OFFSET: usize = 0x68;
+const PSV_DIR_INFO_SIZE: usize = 0x38;
+const PSV_FILE_INFO_SIZE: usize = 0x3C;
+const PSV_NAME_SIZE: usize = 32;
+const PSV_DATA_ALIGN: usize = 16;
+
+const PSV_PS2_KEY: [u8; 16] = [
+    0xFA, 0x72, 0xCE, 0xEF, 0x59, 0xB4, 0xD2, 0x98, 0x9F, 0x11, 0x19, 0x13, 0x28, 0x7F, 0x51, 0xC7,
+];
+const PSV_IV: [u8; 16] = [
+    0xB3, 0x0F, 0xFE, 0xED, 0xB7, 0xDC, 0x5E, 0xB7, 0x13, 0x3D, 0xA6, 0x0D, 0x1B, 0x6B, 0x2C, 0xDC,
+];
+// LAID/PAID of the PS2 emulator, mixed into the AES key
+const PSV_PS2_LAID_PAID: [u8; 16] = [
+    0x10, 0x70, 0x00, 0x00, 0x02, 0x00, 0x00, 0x01, 0x10, 0x70, 0x00, 0x03, 0xFF, 0x00, 0x00, 0x01,
+];
+
+// icon.sys offsets of the normal, copy and delete icon file names
+const ICON_SYS_ICON_NAME_OFFSETS: [usize; 3] = [0x104, 0x144, 0x184];
+const ICON_SYS_NAME_SIZE: usize = 64;
+
+// A decoded .PSV file. `read_psv` only reports the signature; everything that puts the
+// save somewhere (card import, unpacking) goes through `read_signed_psv` and refuses it.
+#[derive(Debug, Clone)]
+pub struct PsvSave {
+    pub save: SaveDir,
+    pub signature_valid: bool,
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+fn name_field(bytes: &[u8]) -> String {
+    sjis::decode(bytes)
+}
+
+fn put_name_field(out: &mut Vec<u8>, name: &str) {
+    out.extend_from_slice(&sjis::encode_field(name, PSV_NAME_SIZE));
+}
+
+fn hmac_key(seed: &[u8]) -> [u8; PSV_SIGNATURE_SIZE] {
+    let mut aes_key = PSV_PS2_LAID_PAID;
+    for (k, x) in aes_key.iter_mut().zip(PSV_PS2_KEY) {
+        *k ^= x;
+    }
+    let cipher = Aes128::new(GenericArray::from_slice(&aes_key));
+
+    // CBC decryption of the zero-padded seed; only the first 20 bytes are kept
+    let mut salt = [0u8; 64];
+    salt[..PSV_SIGNATURE_SIZE].copy_from_slice(&seed[..PSV_SIGNATURE_SIZE]);
+    let mut previous = PSV_IV;
+    for block in salt.chunks_mut(16) {
+        let ciphertext: [u8; 16] = block.try_into().unwrap();
+        cipher.decrypt_block(GenericArray::from_mut_slice(block));
+        for (b, p) in block.iter_mut().zip(previous) {
+            *b ^= p;
+        }
+        previous = ciphertext;
+    }
+
+    salt[..PSV_SIGNATURE_SIZE].try_into().unwrap()
+}
+
+// Signature of a complete .PSV image; the signature field itself is treated as zero
+pub fn psv_signature(image: &[u8]) -> [u8; PSV_SIGNATURE_SIZE] {
+    let seed = &image[PSV_SEED_OFFSET..PSV_SEED_OFFSET + PSV_SIGNATURE_SIZE];
+    let mut mac = <Hmac<Sha1> as Mac>::new_from_slice(&hmac_key(seed)).unwrap();
+    mac.update(&image[..PSV_SIGNATURE_OFFSET]);
+    mac.update(&[0u8; PSV_SIGNATURE_SIZE]);
+    mac.update(&image[PSV_SIGNATURE_OFFSET + PSV_SIGNATURE_SIZE..]);
+    mac.finalize().into_bytes().into()
+}
+
+fn read_info(info: &[u8], with_position: bool) -> io::Result<(RawFSEntry, u32)> {
+    let mut cursor = Cursor::new(&info[16..]);
+    let length = cursor.read_u32::<LittleEndian>()?;
+    let mode = cursor.read_u32::<LittleEndian>()? as u16;
+    let name = name_field(&info[24..24 + PSV_NAME_SIZE]);
+    let position = if with_position {
+        u32::from_le_bytes(info[56..60].try_into().unwrap())
+    } else {
+        0
+    };
+
+    let mut entry = RawFSEntry::new(
+        &name,
+        mode,
+        length,
+        0,
+        Ps2Time::from_bytes(info[..8].try_into().unwrap()),
+    );
+    entry.set_modified(Ps2Time::from_bytes(info[8..16].try_into().unwrap()));
+    Ok((entry, position))
+}
+
+// Decode a .PSV file, refusing it when the signature does not match its contents
+pub fn read_signed_psv<R: Read>(reader: &mut R) -> io::Result<SaveDir> {
+    let psv_save = read_psv(reader)?;
+    if !psv_save.signature_valid {
+        return Err(invalid(
+            "Signature of .PSV file does not match its contents",
+        ));
+    }
+    Ok(psv_save.save)
+}
+
+pub fn read_psv<R: Read>(reader: &mut R) -> io::Result<PsvSave> {
+    let mut image = Vec::new();
+    reader.read_to_end(&mut image)?;
+    if image.len() < PSV_DIR_INFO_OFFSET + PSV_DIR_INFO_SIZE || &image[..4] != PSV_MAGIC {
+        return Err(invalid("Not a .PSV file"));
+    }
+
+    let mut cursor = Cursor::new(&image[0x3C..]);
+    if cursor.read_u32::<LittleEndian>()? != PSV_TYPE_PS2 {
+        return Err(invalid("Only PS2 .PSV saves are supported"));
+    }
+    let file_count = u32::from_le_bytes(image[0x64..0x68].try_into().unwrap()) as usize;
+
+    let dir_info = &image[PSV_DIR_INFO_OFFSET..PSV_DIR_INFO_OFFSET + PSV_DIR_INFO_SIZE];
+    let (dir_entry, _) = read_info(dir_info, false)?;
+
+    let table_start = PSV_DIR_INFO_OFFSET + PSV_DIR_INFO_SIZE;
+    let mut files = Vec::with_capacity(file_count);
+    for i in 0..file_count {
+        let start = table_start + i * PSV_FILE_INFO_SIZE;
+        let info = image
+            .get(start..start + PSV_FILE_INFO_SIZE)
+            .ok_or_else(|| invalid("Truncated .PSV file table"))?;
+        let (entry, position) = read_info(info, true)?;
+        let data = image
+            .get(position as usize..position as usize + entry.length as usize)
+            .ok_or_else(|| invalid("File data lies outside the .PSV file"))?
+            .to_vec();
+        files.push(SaveFile { entry, data });
+    }
+
+    let stored = &image[PSV_SIGNATURE_OFFSET..PSV_SIGNATURE_OFFSET + PSV_SIGNATURE_SIZE];
+    Ok(PsvSave {
+        signature_valid: psv_signature(&image) == stored,
+        save: SaveDir {
+            entry: dir_entry,
+            files,
+        },
+    })
+}
+
+fn write_info(out: &mut Vec<u8>, entry: &RawFSEntry, length: u32) -> io::Result<()> {
+    out.extend_from_slice(&entry.created().to_bytes());
+    out.extend_from_slice(&entry.modified().to_bytes());
+    out.write_u32::<LittleEndian>(length)?;
+    out.write_u32::<LittleEndian>(entry.mode as u32)?;
+    put_name_field(out, &entry.name_str());
+    Ok(())
+}
+
+// Icon file names listed in icon.sys, in normal/copy/delete order
+fn icon_names(save: &SaveDir) -> [String; 3] {
+    let icon_sys = save.file("icon.sys").map(|f| f.data.as_slice());
+    ICON_SYS_ICON_NAME_OFFSETS.map(|offset| {
+        icon_sys
+            .and_then(|data| data.get(offset..offset + ICON_SYS_NAME_SIZE))
+            .map(name_field)
+            .unwrap_or_default()
+    })
+}
+
+// Write `save` as a signed PS2 .PSV file
+pub fn write_psv<W: Write>(save: &SaveDir, writer: &mut W) -> io::Result<()> {
+    let table_end = PSV_DIR_INFO_OFFSET + PSV_DIR_INFO_SIZE + save.files.len() * PSV_FILE_INFO_SIZE;
+    let mut positions = Vec::with_capacity(save.files.len());
+    let mut data_end = table_end;
+    for file in &save.files {
+        data_end = data_end.next_multiple_of(PSV_DATA_ALIGN);
+        positions.push(data_end as u32);
+        data_end += file.data.len();
+    }
+    let locate = |name: &str| -> (u32, u32) {
+        save.files
+            .iter()
+            .position(|f| !name.is_empty() && f.name() == name)
+            .map_or((0, 0), |i| (positions[i], save.files[i].data.len() as u32))
+    };
+
+    let mut image = Vec::with_capacity(data_end);
+    image.extend_from_slice(PSV_MAGIC);
+    image.write_u32::<LittleEndian>(0)?;
+    // Any seed works; deriving it from the content keeps the output reproducible
+    let mut seed_hash = Sha1::new();
+    seed_hash.update(save.name().as_bytes());
+    for file in &save.files {
+        seed_hash.update(&file.data);
+    }
+    image.extend_from_slice(&seed_hash.finalize());
+    image.resize(0x38, 0);
+    image.write_u32::<LittleEndian>(PSV_PS2_HEADER_SIZE)?;
+    image.write_u32::<LittleEndian>(PSV_TYPE_PS2)?;
+
+    let total: usize = save.files.iter().map(|f| f.data.len()).sum();
+    image.write_u32::<LittleEndian>(total as u32)?;
+    let [normal, copy, delete] = icon_names(save);
+    for name in ["icon.sys", &normal, &copy, &delete] {
+        let (position, size) = locate(name);
+        image.write_u32::<LittleEndian>(position)?;
+        image.write_u32::<LittleEndian>(size)?;
+    }
+    image.write_u32::<LittleEndian>(save.files.len() as u32)?;
+
+    write_info(&mut image, &save.entry, save.files.len() as u32 + 2)?;
+    for (file, &position) in save.files.iter().zip(&positions) {
+        write_info(&mut image, &file.entry, file.data.len() as u32)?;
+        image.write_u32::<LittleEndian>(position)?;
+    }
+    for (file, &position) in save.files.iter().zip(&positions) {
+        image.resize(position as usize, 0);
+        image.extend_from_slice(&file.data);
+    }
+
+    let signature = psv_signature(&image);
+    image[PSV_SIGNATURE_OFFSET..PSV_SIGNATURE_OFFSET + PSV_SIGNATURE_SIZE]
+        .copy_from_slice(&signature);
+    writer.write_all(&image)?;
+    writer.flush()
+}
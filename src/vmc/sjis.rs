@@ -0,0 +1,82 @@
+// Shift-JIS text as used by directory names, archive headers and icon.sys titles.
+// Names are often typed in full-width ASCII (U+FF01..U+FF5E), which the browser shows
+// like normal text; normalize_full_width maps those back to plain ASCII for display.
+
+use encoding_rs::SHIFT_JIS;
+use std::io;
+
+const FULL_WIDTH_FIRST: u32 = 0xFF01;
+const FULL_WIDTH_LAST: u32 = 0xFF5E;
+const FULL_WIDTH_OFFSET: u32 = 0xFF01 - 0x21;
+const IDEOGRAPHIC_SPACE: char = '\u{3000}';
+
+// Decode a NUL-terminated Shift-JIS field. Invalid sequences become U+FFFD, so a
+// damaged name still decodes to something instead of being dropped.
+pub fn decode(bytes: &[u8]) -> String {
+    let end = bytes.iter().position(|&b| b == 0).unwrap_or(bytes.len());
+    let (text, _) = SHIFT_JIS.decode_without_bom_handling(&bytes[..end]);
+    text.into_owned()
+}
+
+// Map full-width ASCII and the ideographic space to their ASCII equivalents
+pub fn normalize_full_width(text: &str) -> String {
+    text.chars()
+        .map(|c| match c as u32 {
+            FULL_WIDTH_FIRST..=FULL_WIDTH_LAST => {
+                char::from_u32(c as u32 - FULL_WIDTH_OFFSET).unwrap_or(c)
+            }
+            _ if c == IDEOGRAPHIC_SPACE => ' ',
+            _ => c,
+        })
+        .collect()
+}
+
+// The reverse of normalize_full_width, for text the PS2 browser only shows correctly
+// in double-byte form (icon.sys titles)
+pub fn to_full_width(text: &str) -> String {
+    text.chars()
+        .map(|c| match c {
+            ' ' => IDEOGRAPHIC_SPACE,
+            '!'..='~' => char::from_u32(c as u32 + FULL_WIDTH_OFFSET).unwrap_or(c),
+            _ => c,
+        })
+        .collect()
+}
+
+// Decode a field and normalize it in one go, for titles shown to the user
+pub fn decode_normalized(bytes: &[u8]) -> String {
+    normalize_full_width(&decode(bytes))
+}
+
+fn encode_char(c: char) -> Option<Vec<u8>> {
+    let mut buf = [0u8; 4];
+    let (bytes, _, had_errors) = SHIFT_JIS.encode(c.encode_utf8(&mut buf));
+    (!had_errors).then(|| bytes.into_owned())
+}
+
+// Encode text for the card, failing on characters Shift-JIS cannot represent
+pub fn encode(text: &str) -> io::Result<Vec<u8>> {
+    let (bytes, _, had_errors) = SHIFT_JIS.encode(text);
+    if had_errors {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("'{text}' contains characters that cannot be written in Shift-JIS"),
+        ));
+    }
+    Ok(bytes.into_owned())
+}
+
+// Encode text into a fixed-size, NUL-terminated field. Characters without a Shift-JIS
+// form become '?', and the text is cut before a double-byte character that would not fit.
+pub fn encode_field(text: &str, size: usize) -> Vec<u8> {
+    let mut field = Vec::with_capacity(size);
+    for c in text.chars() {
+        let bytes = encode_char(c).unwrap_or_else(|| b"?".to_vec());
+        if field.len() + bytes.len() >= size {
+            break;
+        }
+        field.extend_from_slice(&bytes);
+    }
+    field.resize(size, 0);
+    field
+}
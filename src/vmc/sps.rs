@@ -0,0 +1,111 @@
+// SharkPort and X-Port save archives (.sps / .xps). Both tools write the same layout:
+// length-prefixed strings ("SharkPortSave", save type, title, date, comment), then a
+// header for the save directory and one header plus data per file, and a trailing
+// checksum that is not verified here. Mode fields are stored byte-swapped.
+
+use crate::model::save_model::{SaveDir, SaveFile};
+use crate::model::vmc_core_model::{Ps2Time, RawFSEntry};
+use crate::vmc::{psu, sjis};
+use byteorder::{BigEndian, LittleEndian, ReadBytesExt};
+use std::io::{self, Cursor, Read};
+
+const SPS_MAGIC: &[u8] = b"SharkPortSave";
+const SPS_ENTRY_HEADER_SIZE: usize = 98;
+const SPS_NAME_SIZE: usize = 64;
+const SPS_DIRECTORY_BIT: u16 = 0x0020;
+// Refuse absurd string lengths instead of allocating them
+const SPS_MAX_STRING_LEN: u32 = 0x10000;
+
+// A decoded .sps/.xps file: the save plus the descriptive strings from its header
+#[derive(Debug, Clone)]
+pub struct SpsSave {
+    pub save: SaveDir,
+    pub title: String,
+    pub date: String,
+    pub comment: String,
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+fn read_long_string<R: Read>(reader: &mut R) -> io::Result<Vec<u8>> {
+    let len = reader.read_u32::<LittleEndian>()?;
+    if len > SPS_MAX_STRING_LEN {
+        return Err(invalid("String in .sps header is too long"));
+    }
+    let mut buf = vec![0u8; len as usize];
+    reader.read_exact(&mut buf)?;
+    Ok(buf)
+}
+
+fn name_field(bytes: &[u8]) -> String {
+    sjis::decode(bytes)
+}
+
+// Directory and file headers share one layout:
+// length u16, name [64], size u32, 8 unused, mode u16 (big-endian), 2 unused,
+// created [8], modified [8], then (length - 98) bytes that are skipped
+fn read_entry<R: Read>(reader: &mut R) -> io::Result<RawFSEntry> {
+    let mut buf = [0u8; SPS_ENTRY_HEADER_SIZE];
+    reader.read_exact(&mut buf)?;
+    let mut cursor = Cursor::new(&buf[..]);
+    let header_len = cursor.read_u16::<LittleEndian>()? as usize;
+    if header_len < SPS_ENTRY_HEADER_SIZE {
+        return Err(invalid("Entry header in .sps file is too short"));
+    }
+    io::copy(
+        &mut reader
+            .by_ref()
+            .take((header_len - SPS_ENTRY_HEADER_SIZE) as u64),
+        &mut io::sink(),
+    )?;
+
+    let name = name_field(&buf[2..2 + SPS_NAME_SIZE]);
+    cursor.set_position(2 + SPS_NAME_SIZE as u64);
+    let length = cursor.read_u32::<LittleEndian>()?;
+    cursor.set_position(cursor.position() + 8);
+    let mode = cursor.read_u16::<BigEndian>()?;
+    let created = Ps2Time::from_bytes(buf[82..90].try_into().unwrap());
+    let modified = Ps2Time::from_bytes(buf[90..98].try_into().unwrap());
+
+    let mut entry = RawFSEntry::new(&name, mode, length, 0, created);
+    entry.set_modified(modified);
+    Ok(entry)
+}
+
+pub fn read_sps<R: Read>(reader: &mut R) -> io::Result<SpsSave> {
+    if read_long_string(reader)? != SPS_MAGIC {
+        return Err(invalid("Not a SharkPort/X-Port save file"));
+    }
+    let _save_type = reader.read_u32::<LittleEndian>()?;
+    let title = sjis::decode(&read_long_string(reader)?);
+    let date = sjis::decode(&read_long_string(reader)?);
+    let comment = sjis::decode(&read_long_string(reader)?);
+    let _total_len = reader.read_u32::<LittleEndian>()?;
+
+    let dir_entry = read_entry(reader)?;
+    if dir_entry.mode & SPS_DIRECTORY_BIT == 0 || dir_entry.length < 2 {
+        return Err(invalid("Bad directory entry in .sps file"));
+    }
+
+    let mut files = Vec::new();
+    for _ in 2..dir_entry.length {
+        let entry = read_entry(reader)?;
+        if entry.mode & SPS_DIRECTORY_BIT != 0 {
+            return Err(invalid("Subdirectories in .sps files are not supported"));
+        }
+        let data = psu::read_exact_vec(reader, entry.length as u64)?;
+        files.push(SaveFile { entry, data });
+    }
+
+    Ok(SpsSave {
+        save: SaveDir {
+            entry: dir_entry,
+            files,
+        },
+        title,
+        date,
+        comment,
+    })
+}
@@ -1,12 +1,18 @@
+use crate::model::save_model::SaveDir;
+use crate::model::vmc_backup::{BlockRecovery, PendingBlockCopy};
+use crate::model::vmc_check::{CheckReport, RepairReport};
 use crate::model::vmc_core_model::{FSEntry, Vmc};
+use crate::model::vmc_undelete::{DeletedEntry, Recoverability};
+use crate::vmc::ecc::EccReport;
+use crate::vmc::icon_render::{self, TurntableOptions};
 use crate::vmc::search_info::search_info_from_id;
-use std::io::Seek;
+use crate::vmc::{cbs, max, psu, psv, sjis, sps};
 use std::{
     collections::HashSet,
     env,
     fs::{self, File},
-    io::{self, Read, Write},
-    path::Path,
+    io::{self, Read},
+    path::{Component, Path, PathBuf},
 };
 
 pub fn validate_mc_file(path: &str) -> io::Result<bool> {
@@ -16,6 +22,27 @@ pub fn validate_mc_file(path: &str) -> io::Result<bool> {
     Ok(&buffer == b"Sony PS2 Memory Card Format ")
 }
 
+// Check the ECC of every page of a raw dump (528-byte pages)
+pub fn verify_ecc(path: &str) -> io::Result<EccReport> {
+    Vmc::new(path)?.verify_ecc()
+}
+
+// Convert between a raw dump with ECC and a plain .ps2/.mc2 image, whichever
+// `input` is not. Returns true when the output carries ECC.
+pub fn convert_card(input: &str, output: &str) -> io::Result<bool> {
+    if Path::new(input) == Path::new(output) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "Input and output must be different files",
+        ));
+    }
+    let mut vmc = Vmc::new(input)?;
+    let with_ecc = !vmc.has_ecc();
+    let mut out = io::BufWriter::new(File::create(output)?);
+    vmc.export_image(&mut out, with_ecc)?;
+    Ok(with_ecc)
+}
+
 #[derive(Debug)]
 pub struct ExtractedId {
     pub id: String,
@@ -112,7 +139,35 @@ pub fn get_game_title(save_name: &str) -> String {
     }
 }
 
+// The title a save shows in the PS2 browser, falling back to the game database when the
+// directory has no readable icon.sys
+fn save_title(vmc: &mut Vmc, entry: &FSEntry) -> String {
+    let title = if entry.is_directory {
+        vmc.icon_sys(&entry.name)
+            .map(|i| sjis::normalize_full_width(&i.title()))
+            .unwrap_or_default()
+    } else {
+        String::new()
+    };
+    if !title.is_empty() {
+        return title;
+    }
+    get_game_title(&entry.name)
+}
+
 pub fn print_directory_entries(entries: Vec<FSEntry>) {
+    let titled = entries
+        .into_iter()
+        .map(|entry| {
+            let title = get_game_title(&entry.name);
+            (entry, title)
+        })
+        .collect();
+    print_titled_directory_entries(titled);
+}
+
+// Same listing with titles the caller already looked up, e.g. from each save's icon.sys
+pub fn print_titled_directory_entries(entries: Vec<(FSEntry, String)>) {
     println!(
         "Save Name                        Type       Size Created          Modified         Game Title"
     );
@@ -122,13 +177,11 @@ pub fn print_directory_entries(entries: Vec<FSEntry>) {
 
     let mut unique_games = HashSet::new();
 
-    for entry in &entries {
+    for (entry, game_title) in &entries {
         if entry.name == "." || entry.name == ".." {
             continue;
         }
 
-        let game_title = get_game_title(&entry.name);
-
         println!(
             "{:<32} {:<10} {:<4} {:04}/{:02}/{:02}-{:02}:{:02}:{:02} {:04}/{:02}/{:02}-{:02}:{:02}:{:02} {}",
             entry.name,
@@ -156,6 +209,24 @@ pub fn print_directory_entries(entries: Vec<FSEntry>) {
     println!("\nTotal Games: {}", unique_games.len());
 }
 
+// Join a name read from a card or a save archive onto a host directory. Names that could
+// leave `dir` (separators, "..", absolute paths, drive prefixes) or that the host
+// cannot store (NUL) are refused rather than sanitized.
+fn host_path(dir: &Path, name: &str) -> io::Result<PathBuf> {
+    let mut components = Path::new(name).components();
+    let single_normal = matches!(
+        (components.next(), components.next()),
+        (Some(Component::Normal(_)), None)
+    );
+    if !single_normal || name.contains(['/', '\\', '\0']) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("Unsafe entry name for the host: '{}'", name.escape_debug()),
+        ));
+    }
+    Ok(dir.join(name))
+}
+
 // New function to extract save directories from VMC
 pub fn extract_save_directories(vmc: &mut Vmc, output_dir: &str) -> io::Result<()> {
     println!("🔄 Extracting save directories...");
@@ -163,27 +234,31 @@ pub fn extract_save_directories(vmc: &mut Vmc, output_dir: &str) -> io::Result<(
     // Create output directory if it doesn't exist
     fs::create_dir_all(output_dir)?;
 
-    let entries = vmc.list_root_directory()?;
+    let entries = vmc.read_dir("")?;
     let mut extracted_count = 0;
-
-    for entry in entries {
-        if entry.name == "." || entry.name == ".." {
-            continue;
-        }
-
-        if entry.is_directory {
-            let save_dir = Path::new(output_dir).join(&entry.name);
-
-            println!("📁 Extracting directory: {}", entry.name);
-
-            match extract_directory_contents(vmc, entry.cluster, &save_dir) {
-                Ok(files_count) => {
-                    println!("   ✅ Extracted {files_count} files");
-                    extracted_count += 1;
-                }
-                Err(e) => {
-                    eprintln!("   ❌ Failed to extract {}: {}", entry.name, e);
-                }
+    // Directory clusters already extracted. A damaged card can link a directory back to
+    // one of its ancestors, which must not be entered again.
+    let mut visited = HashSet::from([vmc.superblock.rootdir_cluster]);
+
+    for entry in entries.iter().filter(|e| e.is_directory) {
+        println!("📁 Extracting directory: {}", entry.name);
+
+        let result = host_path(Path::new(output_dir), &entry.name).and_then(|save_dir| {
+            if !visited.insert(entry.cluster) {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "its cluster is shared with a directory already extracted",
+                ));
+            }
+            extract_directory_contents(vmc, &entry.name, &save_dir, &mut visited)
+        });
+        match result {
+            Ok(files_count) => {
+                println!("   ✅ Extracted {files_count} files");
+                extracted_count += 1;
+            }
+            Err(e) => {
+                eprintln!("   ❌ Failed to extract {}: {}", entry.name, e);
             }
         }
     }
@@ -192,213 +267,610 @@ pub fn extract_save_directories(vmc: &mut Vmc, output_dir: &str) -> io::Result<(
     Ok(())
 }
 
-// Helper function to extract directory contents
+// Helper function to extract directory contents, following the directory's own entry
+// count rather than every slot of its clusters
 fn extract_directory_contents(
     vmc: &mut Vmc,
-    start_cluster: u32,
+    dir_path: &str,
     output_dir: &Path,
+    visited: &mut HashSet<u32>,
 ) -> io::Result<usize> {
     fs::create_dir_all(output_dir)?;
 
-    // Read directory entries from the cluster chain
-    let cluster_chain = vmc.build_cluster_chain(start_cluster);
-    let vmc_entry_size = 512;
-    let entries_per_cluster = vmc.superblock.cluster_size as usize / vmc_entry_size;
-
     let mut file_count = 0;
-    let mut entry_count = 0;
+    for entry in vmc.read_dir(dir_path)? {
+        let path = format!("{dir_path}/{}", entry.name);
+        let target = match host_path(output_dir, &entry.name) {
+            Ok(target) => target,
+            Err(e) => {
+                eprintln!("   ❌ Skipping {path}: {e}");
+                continue;
+            }
+        };
+
+        if entry.is_directory {
+            if !visited.insert(entry.cluster) {
+                println!("   ⚠️  Directory {path} loops back to an extracted directory");
+                continue;
+            }
+            println!("   📁 Entering directory: {path}");
+            file_count += extract_directory_contents(vmc, &path, &target, visited)?;
+            continue;
+        }
 
-    println!("   🔗 Cluster chain: {cluster_chain:?}");
+        println!("   💾 Extracting file: {path}");
+        match extract_file_data(vmc, &path) {
+            Ok(file_data) => {
+                fs::write(&target, &file_data)?;
+                println!(
+                    "   ✅ Successfully extracted: {} ({} bytes)",
+                    entry.name,
+                    file_data.len()
+                );
+                file_count += 1;
+            }
+            Err(e) => {
+                eprintln!("   ❌ Failed to extract file {}: {}", entry.name, e);
+            }
+        }
+    }
 
-    // Let's examine the first cluster more carefully
-    if let Some(&first_cluster) = cluster_chain.first() {
-        let first_cluster_offset = (vmc.superblock.alloc_offset + first_cluster) as u64
-            * vmc.superblock.cluster_size as u64;
+    Ok(file_count)
+}
 
-        vmc.file
-            .seek(std::io::SeekFrom::Start(first_cluster_offset))?;
-        let mut header_buf = vec![0u8; vmc_entry_size];
-        vmc.file.read_exact(&mut header_buf)?;
+// Helper function to extract file data
+fn extract_file_data(vmc: &mut Vmc, path: &str) -> io::Result<Vec<u8>> {
+    let mut file = vmc.open(path)?;
+    println!("     📏 File size: {} bytes", file.len());
 
-        // Debug: print first few bytes of header
-        println!("   🔍 Header bytes: {:02X?}", &header_buf[..32]);
+    let mut file_data = Vec::new();
+    file.read_to_end(&mut file_data)?;
 
-        // Try to parse header differently - maybe it's not a standard FS entry
-        let header_length = if let Some(header) =
-            crate::model::vmc_core_model::parse_fs_entry_from_bytes(&header_buf)
-        {
-            println!("   📊 Header parsed - length field: {}", header.length);
-            header.length as usize
-        } else {
-            println!("   ⚠️  Header parsing failed, using fallback");
-            // Fallback: scan all entries in all clusters
-            entries_per_cluster * cluster_chain.len()
-        };
+    println!("     📊 Total bytes read: {}", file_data.len());
+    Ok(file_data)
+}
 
-        println!("   📊 Expected entries in directory: {header_length}");
+// Copy a host file into a save directory on the card
+pub fn put_file(
+    vmc: &mut Vmc,
+    save_dir: &str,
+    host_file: &str,
+    name: Option<&str>,
+) -> io::Result<()> {
+    let data = fs::read(host_file)?;
+    let file_name = match name {
+        Some(name) => name.to_string(),
+        None => Path::new(host_file)
+            .file_name()
+            .map(|n| n.to_string_lossy().into_owned())
+            .unwrap_or_default(),
+    };
+
+    vmc.write_file(save_dir, &file_name, &data)?;
+    println!(
+        "✅ Wrote {} ({} bytes) to {save_dir}",
+        file_name,
+        data.len()
+    );
+    Ok(())
+}
+
+fn archive_extension(path: &str) -> String {
+    Path::new(path)
+        .extension()
+        .map(|e| e.to_string_lossy().to_ascii_lowercase())
+        .unwrap_or_default()
+}
+
+// Title for archive headers that show one: the save's icon.sys title, or the directory
+// name when it has none
+fn archive_title(vmc: &mut Vmc, save_dir: &str) -> String {
+    vmc.icon_sys(save_dir)
+        .map(|i| sjis::normalize_full_width(&i.title()))
+        .ok()
+        .filter(|title| !title.is_empty())
+        .unwrap_or_else(|| save_dir.to_string())
+}
+
+// Pack one save directory into a single-save archive on the host.
+// The format follows the output extension: .max, .cbs, .psv, anything else is written as .psu.
+pub fn export_save_file(vmc: &mut Vmc, save_dir: &str, output: &str) -> io::Result<()> {
+    let mut out = io::BufWriter::new(File::create(output)?);
+    match archive_extension(output).as_str() {
+        "max" => {
+            let save = vmc.export_save(save_dir)?;
+            max::write_max(&save, &archive_title(vmc, save_dir), &mut out)?;
+        }
+        "cbs" => {
+            let save = vmc.export_save(save_dir)?;
+            cbs::write_cbs(&save, &archive_title(vmc, save_dir), &mut out)?;
+        }
+        "psv" => vmc.export_psv(save_dir, &mut out)?,
+        _ => vmc.export_psu(save_dir, &mut out)?,
     }
+    println!("✅ {save_dir} diekspor ke {output}");
+    Ok(())
+}
 
-    // Process all clusters, starting from the first one
-    for &cluster in &cluster_chain {
-        let cluster_offset =
-            (vmc.superblock.alloc_offset + cluster) as u64 * vmc.superblock.cluster_size as u64;
+// Decode a single-save archive, picking the format from the extension
+// (.max, .cbs, .sps/.xps, .psv, anything else is read as .psu). Returns the save and its
+// title, which is empty for formats without one.
+pub fn read_save_file(input: &str) -> io::Result<(SaveDir, String)> {
+    let mut reader = io::BufReader::new(File::open(input)?);
+    match archive_extension(input).as_str() {
+        "max" => max::read_max(&mut reader).map(|m| (m.save, m.title)),
+        "cbs" => cbs::read_cbs(&mut reader).map(|c| (c.save, c.title)),
+        "sps" | "xps" => sps::read_sps(&mut reader).map(|s| (s.save, s.title)),
+        "psv" => psv::read_signed_psv(&mut reader).map(|save| (save, String::new())),
+        _ => psu::read_psu(&mut reader).map(|save| (save, String::new())),
+    }
+}
 
-        vmc.file.seek(std::io::SeekFrom::Start(cluster_offset))?;
+// Restore a single-save archive from the host as a new save directory
+pub fn import_save_file(vmc: &mut Vmc, input: &str) -> io::Result<String> {
+    let (save, title) = read_save_file(input)?;
+    if !title.is_empty() {
+        println!("🎮 {title}");
+    }
+    let name = vmc.import_save(&save)?;
+    println!("✅ {input} diimpor sebagai {name}");
+    Ok(name)
+}
 
-        let mut cluster_buf = vec![0u8; vmc.superblock.cluster_size as usize];
-        vmc.file.read_exact(&mut cluster_buf)?;
+// Unpack a single-save archive into <output_dir>/<save name>/ without touching any card
+pub fn unpack_save_file(input: &str, output_dir: &str) -> io::Result<usize> {
+    let (save, title) = read_save_file(input)?;
+    if !title.is_empty() {
+        println!("🎮 {title}");
+    }
+    // Names come straight from the archive; check them all before writing anything
+    let save_dir = host_path(Path::new(output_dir), &save.name())?;
+    let targets = save
+        .files
+        .iter()
+        .map(|file| host_path(&save_dir, &file.name()))
+        .collect::<io::Result<Vec<_>>>()?;
+    fs::create_dir_all(&save_dir)?;
+    for (file, target) in save.files.iter().zip(&targets) {
+        fs::write(target, &file.data)?;
+        println!("   ✅ {} ({} bytes)", file.name(), file.data.len());
+    }
+    println!(
+        "📁 {} file diekstrak ke {}",
+        save.files.len(),
+        save_dir.display()
+    );
+    Ok(save.files.len())
+}
 
-        for i in 0..entries_per_cluster {
-            let entry_start = i * vmc_entry_size;
-            if entry_start + vmc_entry_size > cluster_buf.len() {
-                break;
-            }
+// Value following a `--flag` argument, if present
+fn flag_value<'a>(args: &'a [String], flag: &str) -> Option<&'a str> {
+    args.iter()
+        .position(|a| a == flag)
+        .and_then(|i| args.get(i + 1))
+        .map(String::as_str)
+}
 
-            let entry_bytes = &cluster_buf[entry_start..entry_start + vmc_entry_size];
+// Render one animation shape of a save's icon to a PNG file
+pub fn render_save_icon(
+    vmc: &mut Vmc,
+    save_dir: &str,
+    output: &str,
+    frame: usize,
+    size: u32,
+) -> io::Result<()> {
+    let icon = vmc.icon(save_dir)?;
+    let image = icon_render::render_icon(&icon, frame, size)?;
+    icon_render::write_png(&image, io::BufWriter::new(File::create(output)?))?;
+    println!("✅ Ikon {save_dir} dirender ke {output}");
+    Ok(())
+}
 
-            // Check if entry is all zeros (empty entry)
-            if entry_bytes.iter().all(|&b| b == 0) {
-                continue;
-            }
+// Render a save's icon animation as a turntable: a .gif output gives a GIF, anything
+// else an animated PNG
+pub fn render_save_icon_animation(
+    vmc: &mut Vmc,
+    save_dir: &str,
+    output: &str,
+    options: &TurntableOptions,
+) -> io::Result<()> {
+    let icon = vmc.icon(save_dir)?;
+    let frames = icon_render::render_turntable(&icon, options);
+    let out = io::BufWriter::new(File::create(output)?);
+    if archive_extension(output) == "gif" {
+        icon_render::write_gif(&frames, options.delay_ms, out)?;
+    } else {
+        icon_render::write_apng(&frames, options.delay_ms, out)?;
+    }
+    println!(
+        "✅ Animasi ikon {save_dir} ({} frame) dirender ke {output}",
+        frames.len()
+    );
+    Ok(())
+}
 
-            // Debug: print first few bytes of each entry
-            if entry_count < 10 {
-                // Only print first 10 for brevity
-                println!(
-                    "   🔍 Entry {} bytes: {:02X?}",
-                    entry_count,
-                    &entry_bytes[..32]
-                );
-            }
+// Comma-separated numbers such as "128,64,0" or "0.5,-1,0"
+fn parse_components<T: std::str::FromStr>(text: &str, count: usize) -> io::Result<Vec<T>> {
+    let values = text
+        .split(',')
+        .map(|v| v.trim().parse::<T>())
+        .collect::<Result<Vec<T>, _>>()
+        .ok()
+        .filter(|v| v.len() == count);
+    values.ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("Expected {count} comma-separated numbers, got '{text}'"),
+        )
+    })
+}
 
-            if let Some(raw_entry) =
-                crate::model::vmc_core_model::parse_fs_entry_from_bytes(entry_bytes)
-            {
-                entry_count += 1;
-
-                if let Some(fs_entry) = FSEntry::from_raw(&raw_entry) {
-                    println!(
-                        "   🔍 Found entry: '{}' (dir: {}, cluster: {}, size: {}, mode: 0x{:04X})",
-                        fs_entry.name,
-                        fs_entry.is_directory,
-                        fs_entry.cluster,
-                        fs_entry.length,
-                        fs_entry.mode
-                    );
-
-                    if fs_entry.name != "." && fs_entry.name != ".." {
-                        if fs_entry.is_directory {
-                            println!("   📁 Skipping directory: {}", fs_entry.name);
-                        } else if fs_entry.cluster > 0 && fs_entry.cluster != 0xFFFFFFFF {
-                            // Extract file data
-                            println!("   💾 Extracting file: {}", fs_entry.name);
-                            match extract_file_data(vmc, fs_entry.cluster, fs_entry.length) {
-                                Ok(file_data) => {
-                                    if !file_data.is_empty() {
-                                        let file_path = output_dir.join(&fs_entry.name);
-                                        let mut output_file = File::create(&file_path)?;
-                                        output_file.write_all(&file_data)?;
-                                        println!(
-                                            "   ✅ Successfully extracted: {} ({} bytes)",
-                                            fs_entry.name,
-                                            file_data.len()
-                                        );
-                                        file_count += 1;
-                                    } else {
-                                        println!("   ⚠️  File {} is empty", fs_entry.name);
-                                    }
-                                }
-                                Err(e) => {
-                                    eprintln!(
-                                        "   ❌ Failed to extract file {}: {}",
-                                        fs_entry.name, e
-                                    );
-                                }
-                            }
-                        } else {
-                            println!(
-                                "   ⚠️  File {} has invalid cluster: {}",
-                                fs_entry.name, fs_entry.cluster
-                            );
-                        }
-                    }
-                } else {
-                    // Raw entry parsed but FSEntry::from_raw returned None
-                    println!(
-                        "   ⚠️  Raw entry parsed but FSEntry::from_raw failed (mode: 0x{:04X})",
-                        raw_entry.mode
-                    );
-                }
-            } else {
-                // Check if there are any non-zero bytes that might indicate data
-                let non_zero_count = entry_bytes.iter().filter(|&&b| b != 0).count();
-                if non_zero_count > 0 && entry_count < 10 {
-                    println!(
-                        "   ⚠️  Entry {entry_count} failed to parse but has {non_zero_count} non-zero bytes",
-                    );
-                }
+// Apply one icon.sys edit and write the file back into the save directory:
+//   set-title <line1> [line2]
+//   set-bg <r,g,b> [<r,g,b> <r,g,b> <r,g,b>] [--alpha n]
+//   set-light <1|2|3> <x,y,z> <r,g,b>   or   set-light ambient <r,g,b>
+pub fn edit_icon_sys(vmc: &mut Vmc, save_dir: &str, edit: &[String]) -> io::Result<()> {
+    let invalid = |message: &str| io::Error::new(io::ErrorKind::InvalidInput, message.to_string());
+    let mut icon_sys = vmc.icon_sys(save_dir)?;
+    let operands: Vec<&str> = edit
+        .iter()
+        .skip(1)
+        .map(String::as_str)
+        .take_while(|a| !a.starts_with("--"))
+        .collect();
+
+    match edit.first().map(String::as_str) {
+        Some("set-title") => {
+            let (Some(first), second) = (operands.first(), operands.get(1)) else {
+                return Err(invalid("set-title needs at least one line of text"));
+            };
+            icon_sys.set_title(first, second.unwrap_or(&""))?;
+        }
+        Some("set-bg") => {
+            let colors = operands
+                .iter()
+                .map(|c| parse_components::<u32>(c, 3))
+                .collect::<io::Result<Vec<_>>>()?;
+            let corners = match colors.len() {
+                1 => vec![colors[0].clone(); 4],
+                4 => colors,
+                _ => return Err(invalid("set-bg takes one colour or one per corner")),
+            };
+            for (corner, color) in icon_sys.bg_colors.iter_mut().zip(corners) {
+                corner[..3].copy_from_slice(&color);
+            }
+            if let Some(alpha) = flag_value(edit, "--alpha") {
+                icon_sys.bg_transparency = alpha
+                    .parse()
+                    .map_err(|_| invalid("--alpha must be a number"))?;
             }
         }
+        Some("set-light") => match operands.as_slice() {
+            ["ambient", color] => {
+                icon_sys.ambient[..3].copy_from_slice(&parse_components::<f32>(color, 3)?);
+            }
+            [light, direction, color] => {
+                let index = match light.parse::<usize>() {
+                    Ok(n @ 1..=3) => n - 1,
+                    _ => return Err(invalid("Light number must be 1, 2 or 3")),
+                };
+                icon_sys.light_dirs[index][..3]
+                    .copy_from_slice(&parse_components::<f32>(direction, 3)?);
+                icon_sys.light_colors[index][..3]
+                    .copy_from_slice(&parse_components::<f32>(color, 3)?);
+            }
+            _ => return Err(invalid("Usage: set-light <1|2|3> <x,y,z> <r,g,b>")),
+        },
+        _ => return Err(invalid("Expected set-title, set-bg or set-light")),
     }
 
-    println!("   📈 Processed {entry_count} entries total");
-    Ok(file_count)
+    vmc.write_icon_sys(save_dir, &icon_sys)?;
+    println!("✅ icon.sys {save_dir} diperbarui: {}", icon_sys.title());
+    Ok(())
 }
 
-// Helper function to extract file data
-fn extract_file_data(vmc: &mut Vmc, start_cluster: u32, file_size: u32) -> io::Result<Vec<u8>> {
-    if start_cluster == 0 || start_cluster == 0xFFFFFFFF {
-        return Ok(Vec::new());
+fn print_check_report(report: &CheckReport) {
+    println!("=== Pemeriksaan Sistem Berkas ===");
+    println!("Direktori        : {}", report.directories);
+    println!("File             : {}", report.files);
+    println!("Cluster terpakai : {}", report.clusters_in_use);
+    if !report.bad_blocks.is_empty() {
+        println!("Blok rusak       : {:?}", report.bad_blocks);
     }
+    for issue in report.issues.iter().filter(|i| i.is_informational()) {
+        println!("⚠️ {issue}");
+    }
+    let problems = report.problems();
+    if problems.is_empty() {
+        println!("✅ Tidak ada masalah ditemukan");
+        return;
+    }
+    println!("❌ {} masalah ditemukan:", problems.len());
+    for issue in problems {
+        println!("   - {issue}");
+    }
+}
 
-    let cluster_chain = vmc.build_cluster_chain(start_cluster);
-    let mut file_data = Vec::with_capacity(file_size as usize);
-    let mut bytes_read = 0u32;
+// First of <card>.bak, <card>.bak1, <card>.bak2... that does not exist yet, so an
+// earlier backup is never overwritten
+fn backup_path(card: &str) -> String {
+    (0..)
+        .map(|n| match n {
+            0 => format!("{card}.bak"),
+            n => format!("{card}.bak{n}"),
+        })
+        .find(|path| !Path::new(path).exists())
+        .unwrap()
+}
 
-    println!("     🔗 Cluster chain: {cluster_chain:?}");
-    println!("     📏 File size: {file_size} bytes");
+// check --repair: back the image up next to the card, then fix it in place
+pub fn repair_card(vmc: &mut Vmc, card: &str) -> io::Result<RepairReport> {
+    let backup = backup_path(card);
+    let mut out = io::BufWriter::new(File::create_new(&backup)?);
+    let report = vmc.repair(&mut out)?;
+    println!("💾 Cadangan kartu ditulis ke {backup}");
+    Ok(report)
+}
 
-    for &cluster in cluster_chain.iter() {
-        if bytes_read >= file_size {
-            break;
+fn print_repair_report(report: &RepairReport) {
+    print_check_report(&report.found);
+    if report.found.is_clean() {
+        return;
+    }
+    println!("=== Perbaikan ===");
+    println!("Rantai dipotong     : {}", report.truncated_chains);
+    println!("Panjang diperbaiki  : {}", report.fixed_lengths);
+    println!("Entri dilepas       : {}", report.detached_entries);
+    println!("Cluster dibebaskan  : {}", report.freed_clusters);
+    for path in &report.recovered {
+        println!("   + {path}");
+    }
+    if report.remaining.is_clean() {
+        println!("✅ Kartu berhasil diperbaiki");
+    } else {
+        let problems = report.remaining.problems();
+        println!("❌ {} masalah tersisa:", problems.len());
+        for issue in problems {
+            println!("   - {issue}");
         }
+    }
+}
 
-        let cluster_offset =
-            (vmc.superblock.alloc_offset + cluster) as u64 * vmc.superblock.cluster_size as u64;
+fn print_deleted_entries(deleted: &[DeletedEntry]) {
+    println!("=== Entri Terhapus ===");
+    if deleted.is_empty() {
+        println!("Tidak ada entri terhapus yang ditemukan.");
+        return;
+    }
+    for entry in deleted {
+        let kind = if entry.is_dir { "direktori" } else { "file" };
+        let chance = match entry.recoverability {
+            Recoverability::Contiguous => "✅ dapat dipulihkan",
+            Recoverability::Fragmented => "⚠️ mungkin sebagian rusak",
+            Recoverability::Overwritten => "❌ sudah tertimpa",
+        };
+        println!(
+            "  {} ({kind}, {} byte, {} cluster) - {chance}",
+            entry.path,
+            entry.length,
+            entry.clusters.len()
+        );
+    }
+    println!("\n💡 Tip: Gunakan 'undelete <path>' untuk memulihkan entri");
+}
 
-        println!("     📍 Reading cluster {cluster} at offset 0x{cluster_offset:X}",);
+// Restore the first deleted entry listed under `path`
+pub fn undelete_entry(vmc: &mut Vmc, path: &str) -> io::Result<()> {
+    let deleted = vmc.deleted_entries()?;
+    let candidates: Vec<&DeletedEntry> = deleted.iter().filter(|e| e.path == path).collect();
+    // Prefer a copy that can still come back when a name was deleted more than once
+    let entry = candidates
+        .iter()
+        .find(|e| e.recoverability != Recoverability::Overwritten)
+        .or(candidates.first())
+        .ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("No deleted entry named '{path}'"),
+            )
+        })?;
+    vmc.undelete(entry)?;
+    if entry.recoverability == Recoverability::Fragmented {
+        println!("⚠️ Rantai cluster ditebak, sebagian data mungkin salah");
+    }
+    println!("✅ {path} dipulihkan");
+    Ok(())
+}
 
-        vmc.file.seek(std::io::SeekFrom::Start(cluster_offset))?;
+fn print_pending_copy(pending: &PendingBlockCopy) {
+    println!(
+        "⚠️ Penulisan ke blok {} terputus sebelum selesai (salinan cadangan tertunda)",
+        pending.block
+    );
+    if pending.target_complete {
+        println!(
+            "   Blok sudah berisi data baru; 'recover forward' atau 'recover back' aman dilakukan"
+        );
+    } else if pending.target_erased {
+        println!("   Blok sudah terhapus; gunakan 'recover forward' untuk memulihkannya");
+    } else {
+        println!("   Blok mungkin rusak sebagian; gunakan 'recover forward' seperti konsol PS2");
+    }
+}
 
-        let bytes_to_read = std::cmp::min(vmc.superblock.cluster_size, file_size - bytes_read);
+pub fn print_usage(program: &str) {
+    eprintln!("Penggunaan: {program} <file_vmc> [command]");
+    eprintln!("  <file_vmc>                          : Path to VMC file");
+    eprintln!(
+        "  <save_file> unpack [output_dir]     : Extract a save archive to the host (default: extracted_saves)"
+    );
+    eprintln!(
+        "  mkcard [size_mb]                    : Create a new, empty memory card (8, 16, 32, 64 MB...)"
+    );
+    eprintln!(
+        "  mkcard [size_mb] --bad-blocks a,b   : Same, listing worn erase blocks to keep out of use"
+    );
+    eprintln!(
+        "  extract [output_dir]                : Extract save directories (default: extracted_saves)"
+    );
+    eprintln!(
+        "  put <save_dir> <host_file> [name]   : Create or overwrite a file in a save directory"
+    );
+    eprintln!("  rm <save_dir> <name>                : Delete a file from a save directory");
+    eprintln!(
+        "  ecc                                 : Verify the ECC of a raw dump (528-byte pages)"
+    );
+    eprintln!(
+        "  check                               : Check the FAT and directory tree for errors"
+    );
+    eprintln!(
+        "  check --repair                      : Fix them, saving a backup to <file_vmc>.bak first"
+    );
+    eprintln!(
+        "  convert <output>                    : Convert a raw dump to a .ps2 image or vice versa"
+    );
+    eprintln!(
+        "  recover <forward|back>              : Finish or undo a write interrupted mid-block"
+    );
+    eprintln!(
+        "  undelete [path]                     : List deleted entries, or restore one of them"
+    );
+    eprintln!("  mkdir <save_dir>                    : Create a new save directory");
+    eprintln!("  rmdir <save_dir>                    : Delete a save directory and its files");
+    eprintln!(
+        "  export <save_dir> [output]          : Export a save directory as .psu/.max/.cbs/.psv (default: <save_dir>.psu)"
+    );
+    eprintln!(
+        "  import <save_file>                  : Import a .psu, .max, .cbs, .sps, .xps or .psv save"
+    );
+    eprintln!(
+        "  icon <save_dir> [--out file.png] [--frame n] [--size px] : Render a save's icon to PNG"
+    );
+    eprintln!(
+        "  icon <save_dir> --animate [--out file.gif|.png] [--frames n] [--delay ms] [--no-rotate]"
+    );
+    eprintln!(
+        "                                      : Render the icon animation as a GIF or APNG turntable"
+    );
+    eprintln!("  iconsys <save_dir> set-title <line1> [line2]");
+    eprintln!("  iconsys <save_dir> set-bg <r,g,b> [<r,g,b> x3] [--alpha n]");
+    eprintln!(
+        "  iconsys <save_dir> set-light <1|2|3|ambient> [x,y,z] <r,g,b> : Edit a save's icon.sys"
+    );
+}
 
-        let mut cluster_data = vec![0u8; bytes_to_read as usize];
-        vmc.file.read_exact(&mut cluster_data)?;
+fn print_vmc_info(vmc: &Vmc) {
+    println!("\n=== Informasi VMC ===");
+    println!("Versi: {}", vmc.superblock.version);
+    if vmc.has_ecc() {
+        println!("Format: Raw dump (halaman 528 byte dengan ECC)");
+    }
+    let bad_blocks = vmc.superblock.bad_blocks();
+    if !bad_blocks.is_empty() {
+        println!("Blok rusak: {bad_blocks:?}");
+    }
+    let total_clusters = vmc.superblock.max_allocatable_clusters;
+    let free_clusters = vmc.count_free_clusters();
+    let used_clusters = total_clusters.saturating_sub(free_clusters);
+    let cluster_size_mb = vmc.superblock.cluster_size as f64 / (1024.0 * 1024.0);
+    println!(
+        "Ukuran Kartu: {:.2} MB",
+        total_clusters as f64 * cluster_size_mb
+    );
+    println!(
+        "Ruang Terpakai: {:.2} MB ({} cluster)",
+        used_clusters as f64 * cluster_size_mb,
+        used_clusters
+    );
+    println!(
+        "Ruang Kosong: {:.2} MB ({} cluster)",
+        free_clusters as f64 * cluster_size_mb,
+        free_clusters
+    );
+    println!("====================\n");
+}
 
-        file_data.extend_from_slice(&cluster_data);
-        bytes_read += bytes_to_read;
+fn list_root(vmc: &mut Vmc) {
+    println!("=== Root Directory ===");
+    match vmc.list_root_directory() {
+        Ok(entries) => {
+            let save_entries: Vec<_> = entries
+                .into_iter()
+                .filter(|e| e.name != "." && e.name != "..")
+                .map(|e| {
+                    let title = save_title(vmc, &e);
+                    (e, title)
+                })
+                .collect();
+
+            if save_entries.is_empty() {
+                println!("Tidak ada save game yang ditemukan.");
+            } else {
+                print_titled_directory_entries(save_entries);
+            }
 
-        println!("     ✅ Read {bytes_to_read} bytes from cluster {cluster}",);
+            println!("\n💡 Tip: Gunakan 'extract <output_dir>' untuk mengekstrak save directories");
+        }
+        Err(e) => eprintln!("Gagal membaca direktori: {e}"),
     }
-
-    println!("     📊 Total bytes read: {}", file_data.len());
-    Ok(file_data)
 }
 
 pub fn argument_handler() {
-    let args: Vec<String> = env::args().collect();
+    let mut args: Vec<String> = env::args().collect();
+    // `icon <card> <save>` is accepted as well as `<card> icon <save>`, likewise iconsys
+    if args.len() > 2 && (args[1] == "icon" || args[1] == "iconsys") {
+        args.swap(1, 2);
+    }
+    let program = args.first().map_or("alfath_vmc", |s| s);
     if args.len() < 2 {
-        eprintln!(
-            "Penggunaan: {} <file_vmc> [extract <output_dir>]",
-            args.first().map_or("alfath_vmc", |s| s)
-        );
-        eprintln!("  <file_vmc>     : Path to VMC file");
-        eprintln!("  extract        : Extract save directories");
-        eprintln!("  <output_dir>   : Output directory for extraction (default: extracted_saves)");
+        print_usage(program);
         return;
     }
 
     let filename = &args[1];
+    let command = args.get(2).map(String::as_str);
+
+    if command == Some("mkcard") {
+        if Path::new(filename).exists() {
+            eprintln!("❌ File sudah ada: {filename}");
+            return;
+        }
+        let size_arg = args.get(3).filter(|a| !a.starts_with("--"));
+        let size_mb = match size_arg.map(|s| s.parse::<u32>()) {
+            None => 8,
+            Some(Ok(size)) => size,
+            Some(Err(_)) => {
+                eprintln!("❌ Ukuran kartu tidak valid: {}", args[3]);
+                return;
+            }
+        };
+        let bad_blocks = match flag_value(&args, "--bad-blocks")
+            .map(|list| list.split(',').map(|b| b.trim().parse::<u32>()).collect())
+        {
+            None => Vec::new(),
+            Some(Ok(blocks)) => blocks,
+            Some(Err(_)) => {
+                eprintln!("❌ Daftar blok rusak tidak valid");
+                return;
+            }
+        };
+        match Vmc::format_with_bad_blocks(filename, size_mb, &bad_blocks) {
+            Ok(vmc) => {
+                println!("✅ Kartu baru dibuat: {filename}");
+                print_vmc_info(&vmc);
+            }
+            Err(e) => eprintln!("❌ Gagal membuat kartu: {e}"),
+        }
+        return;
+    }
+
+    if command == Some("unpack") {
+        let output_dir = args.get(3).map_or("extracted_saves", String::as_str);
+        if let Err(e) = unpack_save_file(filename, output_dir) {
+            eprintln!("❌ Gagal membongkar save {filename}: {e}");
+        }
+        return;
+    }
 
     if !validate_mc_file(filename).unwrap_or(false) {
         eprintln!("❌ File VMC tidak valid: {filename}");
@@ -406,64 +878,202 @@ pub fn argument_handler() {
     }
     println!("✅ File VMC valid: {filename}");
 
-    match Vmc::new(filename) {
-        Ok(mut vmc) => {
-            println!("\n=== Informasi VMC ===");
-            println!("Versi: {}", vmc.superblock.version);
-            let total_clusters = vmc.superblock.max_allocatable_clusters;
-            let free_clusters = vmc.count_free_clusters();
-            let used_clusters = total_clusters.saturating_sub(free_clusters);
-            let cluster_size_mb = vmc.superblock.cluster_size as f64 / (1024.0 * 1024.0);
-            println!(
-                "Ukuran Kartu: {:.2} MB",
-                total_clusters as f64 * cluster_size_mb
-            );
-            println!(
-                "Ruang Terpakai: {:.2} MB ({} cluster)",
-                used_clusters as f64 * cluster_size_mb,
-                used_clusters
-            );
-            println!(
-                "Ruang Kosong: {:.2} MB ({} cluster)",
-                free_clusters as f64 * cluster_size_mb,
-                free_clusters
-            );
-            println!("====================\n");
-
-            // Check if extraction is requested
-            if args.len() >= 3 && args[2] == "extract" {
-                let output_dir = if args.len() >= 4 {
-                    &args[3]
-                } else {
-                    "extracted_saves"
-                };
+    if command == Some("convert") {
+        let Some(output) = args.get(3) else {
+            print_usage(program);
+            return;
+        };
+        match convert_card(filename, output) {
+            Ok(true) => println!("✅ Raw dump dengan ECC ditulis ke {output}"),
+            Ok(false) => println!("✅ Image .ps2 ditulis ke {output}"),
+            Err(e) => eprintln!("❌ Gagal mengonversi kartu: {e}"),
+        }
+        return;
+    }
 
-                if let Err(e) = extract_save_directories(&mut vmc, output_dir) {
-                    eprintln!("❌ Gagal mengekstrak save directories: {e}");
-                }
+    let repair = command == Some("check") && args.iter().any(|a| a == "--repair");
+    let opened = match command {
+        Some("put") | Some("rm") | Some("mkdir") | Some("rmdir") | Some("import")
+        | Some("iconsys") => Vmc::open_writable(filename),
+        Some("check") if repair => Vmc::open_writable(filename),
+        Some("undelete") if args.len() > 3 => Vmc::open_writable(filename),
+        Some("recover") => Vmc::open_writable(filename),
+        _ => Vmc::new(filename),
+    };
+    let mut vmc = match opened {
+        Ok(vmc) => vmc,
+        Err(e) => {
+            eprintln!("Gagal memproses file VMC: {e}");
+            return;
+        }
+    };
+    if let Some(pending) = vmc.pending_block_copy() {
+        print_pending_copy(&pending);
+    }
+
+    match command {
+        Some("put") => {
+            if args.len() < 5 {
+                print_usage(program);
+                return;
+            }
+            let name = args.get(5).map(String::as_str);
+            if let Err(e) = put_file(&mut vmc, &args[3], &args[4], name) {
+                eprintln!("❌ Gagal menulis file: {e}");
+            }
+        }
+        Some("rm") => {
+            if args.len() < 5 {
+                print_usage(program);
+                return;
+            }
+            match vmc.delete_file(&args[3], &args[4]) {
+                Ok(()) => println!("✅ Deleted {}/{}", args[3], args[4]),
+                Err(e) => eprintln!("❌ Gagal menghapus file: {e}"),
+            }
+        }
+        Some("mkdir") | Some("rmdir") => {
+            let Some(save_dir) = args.get(3) else {
+                print_usage(program);
+                return;
+            };
+            let result = if command == Some("mkdir") {
+                vmc.create_dir(save_dir)
             } else {
-                println!("=== Root Directory ===");
-                match vmc.list_root_directory() {
-                    Ok(entries) => {
-                        let save_entries: Vec<_> = entries
-                            .into_iter()
-                            .filter(|e| e.name != "." && e.name != "..")
-                            .collect();
-
-                        if save_entries.is_empty() {
-                            println!("Tidak ada save game yang ditemukan.");
-                        } else {
-                            print_directory_entries(save_entries);
-                        }
-
-                        println!(
-                            "\n💡 Tip: Gunakan 'extract <output_dir>' untuk mengekstrak save directories"
-                        );
-                    }
-                    Err(e) => eprintln!("Gagal membaca direktori: {e}"),
+                vmc.remove_dir(save_dir)
+            };
+            match result {
+                Ok(()) => println!("✅ {}: {save_dir}", args[2]),
+                Err(e) => eprintln!("❌ Gagal memproses direktori {save_dir}: {e}"),
+            }
+        }
+        Some("export") => {
+            let Some(save_dir) = args.get(3) else {
+                print_usage(program);
+                return;
+            };
+            let output = args
+                .get(4)
+                .cloned()
+                .unwrap_or_else(|| format!("{save_dir}.psu"));
+            if let Err(e) = export_save_file(&mut vmc, save_dir, &output) {
+                eprintln!("❌ Gagal mengekspor save {save_dir}: {e}");
+            }
+        }
+        Some("import") => {
+            let Some(input) = args.get(3) else {
+                print_usage(program);
+                return;
+            };
+            if let Err(e) = import_save_file(&mut vmc, input) {
+                eprintln!("❌ Gagal mengimpor save {input}: {e}");
+            }
+        }
+        Some("recover") => {
+            let recovery = match args.get(3).map(String::as_str) {
+                Some("forward") => BlockRecovery::RollForward,
+                Some("back") => BlockRecovery::RollBack,
+                _ => {
+                    print_usage(program);
+                    return;
+                }
+            };
+            match vmc.recover_block_copy(recovery) {
+                Ok(()) => println!("✅ Salinan blok tertunda diselesaikan"),
+                Err(e) => eprintln!("❌ Gagal memulihkan blok: {e}"),
+            }
+        }
+        Some("undelete") => match args.get(3) {
+            Some(path) => {
+                if let Err(e) = undelete_entry(&mut vmc, path) {
+                    eprintln!("❌ Gagal memulihkan {path}: {e}");
                 }
             }
+            None => match vmc.deleted_entries() {
+                Ok(deleted) => print_deleted_entries(&deleted),
+                Err(e) => eprintln!("❌ Gagal memindai entri terhapus: {e}"),
+            },
+        },
+        Some("check") if repair => match repair_card(&mut vmc, filename) {
+            Ok(report) => print_repair_report(&report),
+            Err(e) => eprintln!("❌ Gagal memperbaiki kartu: {e}"),
+        },
+        Some("check") => match vmc.check() {
+            Ok(report) => print_check_report(&report),
+            Err(e) => eprintln!("❌ Gagal memeriksa kartu: {e}"),
+        },
+        Some("ecc") => match vmc.verify_ecc() {
+            Ok(report) => {
+                println!("=== Pemeriksaan ECC ===");
+                println!("Halaman baik       : {}", report.pages_good);
+                println!("Halaman dikoreksi  : {}", report.pages_corrected);
+                println!("Tidak dapat diperbaiki: {}", report.pages_uncorrectable);
+                if !report.uncorrectable_pages.is_empty() {
+                    println!("Halaman rusak: {:?}", report.uncorrectable_pages);
+                }
+            }
+            Err(e) => eprintln!("❌ Gagal memeriksa ECC: {e}"),
+        },
+        Some("icon") => {
+            let Some(save_dir) = args.get(3) else {
+                print_usage(program);
+                return;
+            };
+            let output = flag_value(&args, "--out");
+            let animate = args.iter().any(|a| a == "--animate")
+                || output.is_some_and(|o| archive_extension(o) == "gif");
+            let frame = flag_value(&args, "--frame").map_or(Ok(0), str::parse::<usize>);
+            let size = flag_value(&args, "--size").map_or(Ok(256), str::parse::<u32>);
+            let frames = flag_value(&args, "--frames").map_or(Ok(48), str::parse::<u32>);
+            let delay = flag_value(&args, "--delay").map_or(Ok(50), str::parse::<u16>);
+            let (Ok(frame), Ok(size), Ok(frames), Ok(delay_ms)) = (frame, size, frames, delay)
+            else {
+                eprintln!("❌ Nilai --frame, --size, --frames atau --delay tidak valid");
+                return;
+            };
+
+            let result = if animate {
+                let output = output.map_or_else(|| format!("{save_dir}.gif"), str::to_string);
+                let options = TurntableOptions {
+                    frames,
+                    size,
+                    delay_ms,
+                    rotate: !args.iter().any(|a| a == "--no-rotate"),
+                };
+                render_save_icon_animation(&mut vmc, save_dir, &output, &options)
+            } else {
+                let output = output.map_or_else(|| format!("{save_dir}.png"), str::to_string);
+                render_save_icon(&mut vmc, save_dir, &output, frame, size)
+            };
+            if let Err(e) = result {
+                eprintln!("❌ Gagal merender ikon {save_dir}: {e}");
+            }
+        }
+        Some("iconsys") => {
+            if args.len() < 5 {
+                print_usage(program);
+                return;
+            }
+            if let Err(e) = edit_icon_sys(&mut vmc, &args[3], &args[4..]) {
+                eprintln!("❌ Gagal mengubah icon.sys {}: {e}", args[3]);
+            }
+        }
+        Some("extract") => {
+            print_vmc_info(&vmc);
+            let output_dir = args.get(3).map_or("extracted_saves", String::as_str);
+
+            if let Err(e) = extract_save_directories(&mut vmc, output_dir) {
+                eprintln!("❌ Gagal mengekstrak save directories: {e}");
+            }
         }
-        Err(e) => eprintln!("Gagal memproses file VMC: {e}"),
+        _ => {
+            print_vmc_info(&vmc);
+            list_root(&mut vmc);
+        }
+    }
+
+    let bad_reads = vmc.bad_block_reads();
+    if !bad_reads.is_empty() {
+        println!("⚠️ Data dibaca dari blok rusak {bad_reads:?}; periksa hasilnya");
     }
 }
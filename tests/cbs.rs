@@ -0,0 +1,93 @@
+use alfatch_vmc::model::save_model::{SaveDir, SaveFile};
+use alfatch_vmc::model::vmc_core_model::{DIR_MODE, FILE_MODE, Ps2Time, RawFSEntry, Vmc};
+use alfatch_vmc::vmc::cbs::{read_cbs, write_cbs};
+use alfatch_vmc::vmc::vmc_core::{export_save_file, import_save_file};
+use std::io::{Cursor, ErrorKind};
+use tempfile::tempdir;
+
+fn time(min: u8) -> Ps2Time {
+    Ps2Time {
+        sec: 1,
+        min,
+        hour: 20,
+        day: 14,
+        month: 2,
+        year: 2004,
+    }
+}
+
+fn sample_save() -> SaveDir {
+    let mut entry = RawFSEntry::new("BASLUS-20312", DIR_MODE, 4, 0, time(1));
+    entry.set_modified(time(2));
+    let mut icon = RawFSEntry::new("icon.sys", FILE_MODE, 964, 0, time(3));
+    icon.set_modified(time(4));
+    let data = RawFSEntry::new("BASLUS-20312", FILE_MODE, 3000, 0, time(5));
+    SaveDir {
+        entry,
+        files: vec![
+            SaveFile {
+                entry: icon,
+                data: vec![0x42; 964],
+            },
+            SaveFile {
+                entry: data,
+                data: (0..3000u32).map(|i| (i % 97) as u8).collect(),
+            },
+        ],
+    }
+}
+
+#[test]
+fn test_cbs_round_trip() {
+    let mut cbs = Vec::new();
+    write_cbs(&sample_save(), "Tony Hawk's Underground", &mut cbs).unwrap();
+    assert_eq!(&cbs[..4], b"CFU\0");
+    assert_eq!(&cbs[0x14..0x20], b"BASLUS-20312");
+    // The body is encrypted, so no zlib header is visible
+    assert_ne!(cbs[0x128], 0x78);
+
+    let decoded = read_cbs(&mut Cursor::new(&cbs)).unwrap();
+    assert_eq!(decoded.title, "Tony Hawk's Underground");
+    assert_eq!(decoded.save.name(), "BASLUS-20312");
+    assert_eq!(decoded.save.entry.created(), time(1));
+    assert_eq!(decoded.save.entry.modified(), time(2));
+    let icon = decoded.save.file("icon.sys").unwrap();
+    assert_eq!(icon.data, vec![0x42; 964]);
+    assert_eq!(icon.entry.modified(), time(4));
+    assert_eq!(icon.entry.mode, FILE_MODE);
+}
+
+#[test]
+fn test_cbs_rejects_damaged_body() {
+    let mut cbs = Vec::new();
+    write_cbs(&sample_save(), "", &mut cbs).unwrap();
+
+    cbs[0x130] ^= 0xFF;
+    assert!(read_cbs(&mut Cursor::new(&cbs)).is_err());
+    assert!(read_cbs(&mut Cursor::new(&cbs[..0x100])).is_err());
+
+    // A 4 GB header length must fail on the short file, not allocate
+    cbs[0x08..0x0C].copy_from_slice(&u32::MAX.to_le_bytes());
+    let err = read_cbs(&mut Cursor::new(&cbs)).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::UnexpectedEof);
+}
+
+#[test]
+fn test_import_and_export_cbs_through_card() {
+    let dir = tempdir().unwrap();
+    let card = dir.path().join("card.ps2");
+    let original = dir.path().join("save.cbs");
+    let mut cbs = Vec::new();
+    write_cbs(&sample_save(), "BASLUS-20312", &mut cbs).unwrap();
+    std::fs::write(&original, &cbs).unwrap();
+
+    let mut vmc = Vmc::format(&card).unwrap();
+    let name = import_save_file(&mut vmc, original.to_str().unwrap()).unwrap();
+    assert_eq!(name, "BASLUS-20312");
+    let meta = vmc.metadata("BASLUS-20312/icon.sys").unwrap();
+    assert_eq!(meta.length, 964);
+
+    let exported = dir.path().join("again.cbs");
+    export_save_file(&mut vmc, "BASLUS-20312", exported.to_str().unwrap()).unwrap();
+    assert_eq!(std::fs::read(exported).unwrap(), cbs);
+}
@@ -0,0 +1,59 @@
+// Helpers shared by the check and repair tests: a small card with two saves and raw
+// edits of its image
+#![allow(dead_code)]
+
+use alfatch_vmc::model::vmc_core_model::Vmc;
+use std::io::Cursor;
+
+pub type MemVmc = Vmc<Cursor<Vec<u8>>>;
+
+pub fn card_with_saves() -> MemVmc {
+    let mut vmc = Vmc::format_in_memory(8).unwrap();
+    vmc.create_dir("SAVE-A").unwrap();
+    vmc.write_file("SAVE-A", "data.bin", &[1u8; 3000]).unwrap();
+    vmc.create_dir("SAVE-B").unwrap();
+    vmc.write_file("SAVE-B", "data.bin", &[2u8; 3000]).unwrap();
+    vmc.create_dir("SAVE-B/SUB").unwrap();
+    vmc
+}
+
+pub fn chain_of(vmc: &mut MemVmc, path: &str) -> Vec<u32> {
+    let cluster = vmc.metadata(path).unwrap().cluster;
+    vmc.build_cluster_chain(cluster)
+}
+
+// Apply raw edits to the image and reopen it; returns the card and the damaged image.
+// `edit` gets the image bytes and the card's layout.
+pub fn corrupt(vmc: MemVmc, edit: impl FnOnce(&mut Vec<u8>, &Layout)) -> (MemVmc, Vec<u8>) {
+    let layout = Layout {
+        ifc: vmc.superblock.ifc_ptr_list[0],
+        alloc_offset: vmc.superblock.alloc_offset,
+    };
+    let mut image = vmc.into_inner().into_inner();
+    edit(&mut image, &layout);
+    (
+        Vmc::from_backend(Cursor::new(image.clone())).unwrap(),
+        image,
+    )
+}
+
+pub struct Layout {
+    ifc: u32,
+    alloc_offset: u32,
+}
+
+impl Layout {
+    pub fn set_fat(&self, image: &mut [u8], cluster: u32, value: u32) {
+        let ifc_offset = self.ifc as usize * 1024 + (cluster as usize / 256) * 4;
+        let fat_cluster = u32::from_le_bytes(image[ifc_offset..ifc_offset + 4].try_into().unwrap());
+        let offset = fat_cluster as usize * 1024 + (cluster as usize % 256) * 4;
+        image[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+    }
+
+    // Overwrite the length of directory entry `index` in the directory chain `chain`
+    pub fn set_length(&self, image: &mut [u8], chain: &[u32], index: usize, length: u32) {
+        let cluster = self.alloc_offset + chain[index / 2];
+        let offset = cluster as usize * 1024 + (index % 2) * 512 + 4;
+        image[offset..offset + 4].copy_from_slice(&length.to_le_bytes());
+    }
+}
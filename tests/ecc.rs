@@ -0,0 +1,72 @@
+use alfatch_vmc::vmc::ecc::{
+    EccStatus, ecc_calculate, ecc_calculate_page, ecc_check, ecc_check_page,
+};
+
+fn sample_chunk() -> Vec<u8> {
+    (0..128u32).map(|i| (i * 37 + 11) as u8).collect()
+}
+
+#[test]
+fn test_ecc_of_zero_chunk() {
+    assert_eq!(ecc_calculate(&[0u8; 128]), [0x77, 0x7F, 0x7F]);
+}
+
+#[test]
+fn test_ecc_corrects_single_data_bit() {
+    let original = sample_chunk();
+    let mut ecc = ecc_calculate(&original);
+
+    for (byte, bit) in [(0usize, 0u8), (57, 3), (127, 7)] {
+        let mut damaged = original.clone();
+        damaged[byte] ^= 1 << bit;
+        assert_eq!(ecc_check(&mut damaged, &mut ecc), EccStatus::Corrected);
+        assert_eq!(damaged, original);
+    }
+}
+
+#[test]
+fn test_ecc_corrects_single_ecc_bit() {
+    let mut chunk = sample_chunk();
+    let good = ecc_calculate(&chunk);
+    let mut ecc = good;
+    ecc[2] ^= 0x04;
+
+    assert_eq!(ecc_check(&mut chunk, &mut ecc), EccStatus::Corrected);
+    assert_eq!(ecc, good);
+    assert_eq!(chunk, sample_chunk());
+}
+
+#[test]
+fn test_ecc_detects_double_bit_error() {
+    let mut chunk = sample_chunk();
+    let mut ecc = ecc_calculate(&chunk);
+    chunk[3] ^= 0x01;
+    chunk[90] ^= 0x10;
+
+    assert_eq!(ecc_check(&mut chunk, &mut ecc), EccStatus::Uncorrectable);
+}
+
+#[test]
+fn test_ecc_check_page() {
+    let page: Vec<u8> = (0..512u32).map(|i| (i ^ (i >> 3)) as u8).collect();
+    let mut spare = ecc_calculate_page(&page, 16);
+    assert_eq!(spare.len(), 16);
+    assert_eq!(&spare[12..], [0, 0, 0, 0]);
+
+    let mut damaged = page.clone();
+    assert_eq!(ecc_check_page(&mut damaged, &mut spare), EccStatus::Ok);
+
+    damaged[300] ^= 0x20;
+    assert_eq!(
+        ecc_check_page(&mut damaged, &mut spare),
+        EccStatus::Corrected
+    );
+    assert_eq!(damaged, page);
+
+    let mut erased = vec![0xFFu8; 512];
+    let mut erased_spare = vec![0xFFu8; 16];
+    assert_eq!(
+        ecc_check_page(&mut erased, &mut erased_spare),
+        EccStatus::Ok
+    );
+}
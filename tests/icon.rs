@@ -0,0 +1,172 @@
+use alfatch_vmc::model::vmc_core_model::Vmc;
+use alfatch_vmc::vmc::icon::read_icon;
+use alfatch_vmc::vmc::icon_render::{render_icon, write_png};
+use std::io::{Cursor, ErrorKind};
+
+const RED: u16 = 0x801F;
+const BLUE: u16 = 0xFC00;
+
+fn fixed(v: f32) -> [u8; 2] {
+    ((v * 4096.0) as i16).to_le_bytes()
+}
+
+// A square made of two triangles, textured red on the left and blue on the right.
+// The second shape moves the square to the right.
+fn square_icon(compressed: bool) -> Vec<u8> {
+    let corners = [
+        (-1.0, -1.0, 0.0, 0.0),
+        (1.0, -1.0, 1.0, 0.0),
+        (1.0, 1.0, 1.0, 1.0),
+        (-1.0, -1.0, 0.0, 0.0),
+        (1.0, 1.0, 1.0, 1.0),
+        (-1.0, 1.0, 0.0, 1.0),
+    ];
+    let mut data = Vec::new();
+    for value in [
+        0x0001_0000u32,
+        2,
+        if compressed { 0x0F } else { 0x07 },
+        0x3F80_0000,
+        6,
+    ] {
+        data.extend_from_slice(&value.to_le_bytes());
+    }
+    for (x, y, u, v) in corners {
+        for shift in [0.0, 2.0] {
+            for c in [x + shift, y, 0.0, 1.0] {
+                data.extend_from_slice(&fixed(c));
+            }
+        }
+        for c in [0.0, 0.0, -1.0, 0.0, u, v] {
+            data.extend_from_slice(&fixed(c));
+        }
+        data.extend_from_slice(&[0x80, 0x80, 0x80, 0x80]);
+    }
+
+    // Animation block with one frame and a single key
+    for value in [1u32, 60, 1.0f32.to_bits(), 0, 1, 0, 1, 1, 0] {
+        data.extend_from_slice(&value.to_le_bytes());
+    }
+    data.extend_from_slice(&0.0f32.to_le_bytes());
+    data.extend_from_slice(&1.0f32.to_le_bytes());
+
+    let mut texture = Vec::new();
+    if compressed {
+        // First row as literal pixels, the rest as runs
+        texture.extend_from_slice(&(0x10000u32 - 128).to_le_bytes()[..2]);
+        for x in 0..128 {
+            texture.extend_from_slice(&(if x < 64 { RED } else { BLUE }).to_le_bytes());
+        }
+        for _ in 1..128 {
+            for pixel in [RED, BLUE] {
+                texture.extend_from_slice(&64u16.to_le_bytes());
+                texture.extend_from_slice(&pixel.to_le_bytes());
+            }
+        }
+        data.extend_from_slice(&(texture.len() as u32).to_le_bytes());
+    } else {
+        for _ in 0..128 {
+            for x in 0..128 {
+                texture.extend_from_slice(&(if x < 64 { RED } else { BLUE }).to_le_bytes());
+            }
+        }
+    }
+    data.extend_from_slice(&texture);
+    data
+}
+
+#[test]
+fn test_parse_icon() {
+    for compressed in [false, true] {
+        let icon = read_icon(&square_icon(compressed)).unwrap();
+        assert_eq!(icon.shape_count, 2);
+        assert_eq!(icon.vertices.len(), 6);
+        assert_eq!(
+            icon.vertices[1].positions,
+            [[1.0, -1.0, 0.0], [3.0, -1.0, 0.0]]
+        );
+        assert_eq!(icon.vertices[2].uv, [1.0, 1.0]);
+        assert_eq!(icon.animation.frames[0].keys[0].value, 1.0);
+        let texture = icon.texture.unwrap();
+        assert_eq!(
+            (texture[0], texture[127], texture[128 * 127]),
+            (RED, BLUE, RED)
+        );
+    }
+
+    let mut truncated = square_icon(true);
+    truncated.truncate(truncated.len() - 10);
+    assert_eq!(
+        read_icon(&truncated).unwrap_err().kind(),
+        ErrorKind::UnexpectedEof
+    );
+
+    // A 4 GB texture size is read as far as the icon goes, not allocated
+    let mut huge = square_icon(true);
+    let size_offset = huge.len() - (2 + 128 * 2 + 127 * 8) - 4;
+    huge[size_offset..size_offset + 4].copy_from_slice(&u32::MAX.to_le_bytes());
+    assert_eq!(
+        read_icon(&huge).unwrap_err().kind(),
+        ErrorKind::UnexpectedEof
+    );
+    // Shape and vertex counts whose product overflows are refused, not wrapped
+    let mut overflow = square_icon(false);
+    overflow[4..8].copy_from_slice(&u32::MAX.to_le_bytes());
+    overflow[16..20].copy_from_slice(&(u32::MAX / 3 * 3).to_le_bytes());
+    assert_eq!(
+        read_icon(&overflow).unwrap_err().kind(),
+        ErrorKind::InvalidData
+    );
+    assert_eq!(
+        read_icon(b"PS2D").unwrap_err().kind(),
+        ErrorKind::InvalidData
+    );
+}
+
+#[test]
+fn test_render_shapes() {
+    let icon = read_icon(&square_icon(false)).unwrap();
+
+    // Both shapes share one framing: the first fills the left half of the image
+    let image = render_icon(&icon, 0, 64).unwrap();
+    assert_eq!(image.pixel(10, 32), [255, 0, 0, 255]);
+    assert_eq!(image.pixel(25, 32), [0, 0, 255, 255]);
+    assert_eq!(image.pixel(50, 32), [0, 0, 0, 0]);
+    assert_eq!(image.pixel(10, 1), [0, 0, 0, 0]);
+
+    let image = render_icon(&icon, 1, 64).unwrap();
+    assert_eq!(image.pixel(10, 32), [0, 0, 0, 0]);
+    assert_eq!(image.pixel(40, 32), [255, 0, 0, 255]);
+
+    let err = render_icon(&icon, 2, 64).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::InvalidInput);
+}
+
+#[test]
+fn test_render_icon_from_card_to_png() {
+    let mut icon_sys = vec![0u8; 964];
+    icon_sys[..4].copy_from_slice(b"PS2D");
+    icon_sys[0x104..0x104 + 8].copy_from_slice(b"list.ico");
+
+    let mut vmc = Vmc::format_in_memory(8).unwrap();
+    vmc.create_dir("BASLUS-21050").unwrap();
+    vmc.write_file("BASLUS-21050", "icon.sys", &icon_sys)
+        .unwrap();
+    let err = vmc.icon("BASLUS-21050").unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::NotFound);
+
+    vmc.write_file("BASLUS-21050", "list.ico", &square_icon(true))
+        .unwrap();
+    let icon = vmc.icon("BASLUS-21050").unwrap();
+    let image = render_icon(&icon, 0, 32).unwrap();
+
+    let mut png = Vec::new();
+    write_png(&image, &mut png).unwrap();
+    let decoder = png::Decoder::new(Cursor::new(png));
+    let mut reader = decoder.read_info().unwrap();
+    let mut pixels = vec![0u8; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut pixels).unwrap();
+    assert_eq!((info.width, info.height), (32, 32));
+    assert_eq!(info.color_type, png::ColorType::Rgba);
+    assert_eq!(pixels, image.pixels);
+}
@@ -0,0 +1,135 @@
+use alfatch_vmc::vmc::icon::{Icon, IconAnimation, IconFrame, IconKey, IconVertex};
+use alfatch_vmc::vmc::icon_render::{TurntableOptions, render_turntable, write_apng, write_gif};
+use std::io::Cursor;
+
+fn keys(pairs: &[(f32, f32)]) -> Vec<IconKey> {
+    pairs
+        .iter()
+        .map(|&(time, value)| IconKey { time, value })
+        .collect()
+}
+
+// An untextured white square facing the viewer that morphs from shape 0 to shape 1
+// (moved down by one unit) over the first half of a 60-frame loop
+fn morphing_square() -> Icon {
+    let corners = [
+        (-1.0, -1.0),
+        (1.0, -1.0),
+        (1.0, 1.0),
+        (-1.0, -1.0),
+        (1.0, 1.0),
+        (-1.0, 1.0),
+    ];
+    Icon {
+        shape_count: 2,
+        texture_type: 0x03,
+        vertices: corners
+            .iter()
+            .map(|&(x, y)| IconVertex {
+                positions: vec![[x, y, 0.0], [x, y + 1.0, 0.0]],
+                normal: [0.0, 0.0, -1.0],
+                uv: [0.0, 0.0],
+                color: [0x80; 4],
+            })
+            .collect(),
+        animation: IconAnimation {
+            frame_length: 60,
+            speed: 1.0,
+            play_offset: 0,
+            frames: vec![
+                IconFrame {
+                    shape: 0,
+                    keys: keys(&[(0.0, 1.0), (30.0, 0.0)]),
+                },
+                IconFrame {
+                    shape: 1,
+                    keys: keys(&[(0.0, 0.0), (30.0, 1.0)]),
+                },
+            ],
+        },
+        texture: None,
+    }
+}
+
+#[test]
+fn test_shapes_blend_over_time() {
+    let icon = morphing_square();
+    assert_eq!(icon.animation.frames[1].weight_at(-5.0), 0.0);
+    assert_eq!(icon.animation.frames[1].weight_at(15.0), 0.5);
+    assert_eq!(icon.animation.frames[1].weight_at(45.0), 1.0);
+
+    assert_eq!(icon.positions_at(0.0)[0], [-1.0, -1.0, 0.0]);
+    assert_eq!(icon.positions_at(15.0)[0], [-1.0, -0.5, 0.0]);
+    assert_eq!(icon.positions_at(40.0)[0], [-1.0, 0.0, 0.0]);
+    // Time wraps around the loop
+    assert_eq!(icon.positions_at(75.0)[0], [-1.0, -0.5, 0.0]);
+
+    let mut still = morphing_square();
+    still.animation.frames.clear();
+    assert_eq!(still.positions_at(40.0), still.shape_positions(0));
+}
+
+#[test]
+fn test_turntable_frames() {
+    let icon = morphing_square();
+    let options = TurntableOptions {
+        frames: 4,
+        size: 32,
+        ..TurntableOptions::default()
+    };
+    let frames = render_turntable(&icon, &options);
+    assert_eq!(frames.len(), 4);
+    let covered = |i: usize| frames[i].pixels.chunks(4).filter(|p| p[3] != 0).count();
+
+    // Facing the viewer, then edge-on after a quarter turn
+    assert!(covered(0) > 100);
+    assert_eq!(covered(1), 0);
+    assert!(covered(2) > 100);
+
+    // Without rotation only the morph moves the square: down at the half loop
+    let frames = render_turntable(
+        &icon,
+        &TurntableOptions {
+            rotate: false,
+            ..options
+        },
+    );
+    assert_eq!(frames[0].pixel(16, 2), [255, 255, 255, 255]);
+    assert_eq!(frames[2].pixel(16, 2), [0, 0, 0, 0]);
+    assert_eq!(frames[2].pixel(16, 29), [255, 255, 255, 255]);
+}
+
+#[test]
+fn test_write_gif_and_apng() {
+    let options = TurntableOptions {
+        frames: 6,
+        size: 24,
+        delay_ms: 40,
+        rotate: true,
+    };
+    let frames = render_turntable(&morphing_square(), &options);
+
+    let mut gif_data = Vec::new();
+    write_gif(&frames, options.delay_ms, &mut gif_data).unwrap();
+    let mut decoder = gif::DecodeOptions::new();
+    decoder.set_color_output(gif::ColorOutput::RGBA);
+    let mut decoder = decoder.read_info(Cursor::new(gif_data)).unwrap();
+    let mut count = 0;
+    while let Some(frame) = decoder.read_next_frame().unwrap() {
+        assert_eq!(frame.delay, 4);
+        assert_eq!((frame.width, frame.height), (24, 24));
+        count += 1;
+    }
+    assert_eq!(count, 6);
+
+    let mut png_data = Vec::new();
+    write_apng(&frames, options.delay_ms, &mut png_data).unwrap();
+    let reader = png::Decoder::new(Cursor::new(png_data))
+        .read_info()
+        .unwrap();
+    let animation = reader.info().animation_control.unwrap();
+    assert_eq!(animation.num_frames, 6);
+    assert_eq!(animation.num_plays, 0);
+
+    assert!(write_gif(&[], 40, Vec::new()).is_err());
+}
@@ -0,0 +1,157 @@
+use alfatch_vmc::model::vmc_core_model::{IconSys, Vmc};
+use alfatch_vmc::vmc::vmc_core::edit_icon_sys;
+use std::io::{Cursor, ErrorKind};
+
+fn icon_sys_bytes(title: &[u8], line_break: u16) -> Vec<u8> {
+    let mut data = vec![0u8; 964];
+    data[..4].copy_from_slice(b"PS2D");
+    data[6..8].copy_from_slice(&line_break.to_le_bytes());
+    data[0x0C..0x10].copy_from_slice(&0x40u32.to_le_bytes());
+    data[0x10..0x14].copy_from_slice(&0x80u32.to_le_bytes());
+    data[0x50..0x54].copy_from_slice(&0.5f32.to_le_bytes());
+    data[0xB0..0xB4].copy_from_slice(&0.25f32.to_le_bytes());
+    data[0xC0..0xC0 + title.len()].copy_from_slice(title);
+    data[0x104..0x104 + 8].copy_from_slice(b"list.ico");
+    data[0x144..0x144 + 8].copy_from_slice(b"copy.ico");
+    data[0x184..0x184 + 7].copy_from_slice(b"del.ico");
+    data
+}
+
+#[test]
+fn test_parse_icon_sys() {
+    let title = b"PES 2014Master League";
+    let icon_sys = IconSys::from_bytes(&icon_sys_bytes(title, 8)).unwrap();
+
+    assert_eq!(
+        icon_sys.title_lines(),
+        ("PES 2014".to_string(), "Master League".to_string())
+    );
+    assert_eq!(icon_sys.title(), "PES 2014 Master League");
+    assert_eq!(icon_sys.bg_transparency, 0x40);
+    assert_eq!(icon_sys.bg_colors[0][0], 0x80);
+    assert_eq!(icon_sys.light_dirs[0][0], 0.5);
+    assert_eq!(icon_sys.ambient[0], 0.25);
+    assert_eq!(icon_sys.icon_file, "list.ico");
+    assert_eq!(icon_sys.copy_icon_file, "copy.ico");
+    assert_eq!(icon_sys.delete_icon_file, "del.ico");
+}
+
+#[test]
+fn test_shift_jis_title() {
+    // "ＰＥＳ" / "セーブ" in Shift-JIS, second line starting at byte 6
+    let title = [
+        0x82, 0x6F, 0x82, 0x64, 0x82, 0x72, 0x83, 0x5A, 0x81, 0x5B, 0x83, 0x75,
+    ];
+    let icon_sys = IconSys::from_bytes(&icon_sys_bytes(&title, 6)).unwrap();
+    assert_eq!(
+        icon_sys.title_lines(),
+        ("ＰＥＳ".to_string(), "セーブ".to_string())
+    );
+}
+
+#[test]
+fn test_icon_sys_from_card() {
+    let mut vmc = Vmc::format_in_memory(8).unwrap();
+    vmc.create_dir("BESLES-55673SAVEDATA").unwrap();
+    let err = vmc.icon_sys("BESLES-55673SAVEDATA").unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::NotFound);
+
+    vmc.write_file("BESLES-55673SAVEDATA", "icon.sys", &[0u8; 964])
+        .unwrap();
+    let err = vmc.icon_sys("BESLES-55673SAVEDATA").unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::InvalidData);
+
+    let data = icon_sys_bytes(b"PES 2014 - Master League", 9);
+    vmc.write_file("BESLES-55673SAVEDATA", "icon.sys", &data)
+        .unwrap();
+    let icon_sys = vmc.icon_sys("BESLES-55673SAVEDATA").unwrap();
+    assert_eq!(icon_sys.title(), "PES 2014 - Master League");
+
+    // A 4 GB length in the entry is read as far as the chain goes, not reserved
+    let save_cluster = vmc.metadata("BESLES-55673SAVEDATA").unwrap().cluster;
+    let entry_cluster = vmc.superblock.alloc_offset + vmc.build_cluster_chain(save_cluster)[1];
+    let mut image = vmc.into_inner().into_inner();
+    let offset = entry_cluster as usize * 1024 + 4;
+    image[offset..offset + 4].copy_from_slice(&u32::MAX.to_le_bytes());
+    let mut vmc = Vmc::from_backend(Cursor::new(image)).unwrap();
+    let err = vmc.icon_sys("BESLES-55673SAVEDATA").unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::UnexpectedEof);
+    let err = vmc
+        .read_file("BESLES-55673SAVEDATA", "icon.sys")
+        .unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::UnexpectedEof);
+}
+
+#[test]
+fn test_set_title_and_round_trip() {
+    let data = icon_sys_bytes(b"PES 2014Master League", 8);
+    let mut icon_sys = IconSys::from_bytes(&data).unwrap();
+    assert_eq!(icon_sys.to_bytes(), data);
+
+    icon_sys.set_title("OPL", "Config ~1").unwrap();
+    // ASCII is stored full-width, two bytes per character
+    assert_eq!(icon_sys.line_break, 6);
+    assert_eq!(&icon_sys.title[..6], &[0x82, 0x6E, 0x82, 0x6F, 0x82, 0x6B]);
+    assert_eq!(
+        icon_sys.title_lines(),
+        ("ＯＰＬ".to_string(), "Ｃｏｎｆｉｇ　～１".to_string())
+    );
+
+    let reparsed = IconSys::from_bytes(&icon_sys.to_bytes()).unwrap();
+    assert_eq!(reparsed, icon_sys);
+
+    let err = icon_sys.set_title(&"x".repeat(30), "four").unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::InvalidInput);
+    assert_eq!(reparsed, icon_sys);
+}
+
+#[test]
+fn test_edit_icon_sys_on_card() {
+    let dir = tempfile::tempdir().unwrap();
+    let mut vmc = Vmc::format(dir.path().join("card.ps2")).unwrap();
+    vmc.create_dir("OPL").unwrap();
+    vmc.write_file("OPL", "icon.sys", &icon_sys_bytes(b"OPL", 0))
+        .unwrap();
+    let args = |list: &[&str]| list.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+
+    edit_icon_sys(&mut vmc, "OPL", &args(&["set-title", "Open PS2", "Loader"])).unwrap();
+    edit_icon_sys(
+        &mut vmc,
+        "OPL",
+        &args(&[
+            "set-bg", "0,0,64", "0,0,64", "64,0,0", "64,0,0", "--alpha", "0",
+        ]),
+    )
+    .unwrap();
+    edit_icon_sys(
+        &mut vmc,
+        "OPL",
+        &args(&["set-light", "2", "0,-1,0.5", "1,1,1"]),
+    )
+    .unwrap();
+    edit_icon_sys(
+        &mut vmc,
+        "OPL",
+        &args(&["set-light", "ambient", "0.2,0.2,0.2"]),
+    )
+    .unwrap();
+
+    let icon_sys = vmc.icon_sys("OPL").unwrap();
+    assert_eq!(icon_sys.title(), "Ｏｐｅｎ　ＰＳ２ Ｌｏａｄｅｒ");
+    assert_eq!(icon_sys.bg_transparency, 0);
+    assert_eq!(icon_sys.bg_colors[0][..3], [0, 0, 64]);
+    assert_eq!(icon_sys.bg_colors[3][..3], [64, 0, 0]);
+    assert_eq!(icon_sys.light_dirs[1][..3], [0.0, -1.0, 0.5]);
+    assert_eq!(icon_sys.light_colors[1][..3], [1.0, 1.0, 1.0]);
+    assert_eq!(icon_sys.ambient[..3], [0.2, 0.2, 0.2]);
+    assert_eq!(icon_sys.icon_file, "list.ico");
+
+    for bad in [
+        &["set-bg", "1,2"][..],
+        &["set-light", "4", "0,0,0", "1,1,1"],
+        &["frobnicate"],
+    ] {
+        let err = edit_icon_sys(&mut vmc, "OPL", &args(bad)).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidInput);
+    }
+}
@@ -0,0 +1,133 @@
+use alfatch_vmc::model::save_model::{SaveDir, SaveFile};
+use alfatch_vmc::model::vmc_core_model::{DIR_MODE, FILE_MODE, Ps2Time, RawFSEntry, Vmc};
+use alfatch_vmc::vmc::lzari;
+use alfatch_vmc::vmc::max::{read_max, write_max};
+use alfatch_vmc::vmc::vmc_core::export_save_file;
+use std::io::{Cursor, ErrorKind};
+
+fn pseudo_random(len: usize, seed: u32) -> Vec<u8> {
+    let mut state = seed;
+    (0..len)
+        .map(|_| {
+            state = state.wrapping_mul(1_103_515_245).wrapping_add(12345);
+            (state >> 16) as u8
+        })
+        .collect()
+}
+
+fn sample_save() -> SaveDir {
+    let now = Ps2Time::now();
+    let file = |name: &str, data: Vec<u8>| SaveFile {
+        entry: RawFSEntry::new(name, FILE_MODE, data.len() as u32, 0, now),
+        data,
+    };
+    SaveDir {
+        entry: RawFSEntry::new("BESLES-55673SAVEDATA", DIR_MODE, 5, 0, now),
+        files: vec![
+            file("icon.sys", vec![0x20; 964]),
+            file("list.ico", pseudo_random(5000, 7)),
+            file("BESLES-55673", b"league ".repeat(900)),
+        ],
+    }
+}
+
+#[test]
+fn test_lzari_round_trip() {
+    let mut mixed = b"PES 2014 Master League ".repeat(300);
+    mixed.extend(pseudo_random(9000, 1));
+    mixed.extend(vec![0u8; 20000]);
+
+    for data in [Vec::new(), b"a".to_vec(), b"    ".to_vec(), mixed] {
+        let compressed = lzari::compress(&data);
+        assert_eq!(lzari::decompress(&compressed, data.len()).unwrap(), data);
+    }
+
+    let zeros = vec![0u8; 20000];
+    assert!(lzari::compress(&zeros).len() < 1000);
+}
+
+#[test]
+fn test_max_round_trip_and_import() {
+    let save = sample_save();
+    let mut max = Vec::new();
+    write_max(&save, "PES 2014 Master League", &mut max).unwrap();
+    assert_eq!(&max[..12], b"Ps2PowerSave");
+
+    let decoded = read_max(&mut Cursor::new(&max)).unwrap();
+    assert_eq!(decoded.title, "PES 2014 Master League");
+    assert_eq!(decoded.save.name(), "BESLES-55673SAVEDATA");
+    for (a, b) in decoded.save.files.iter().zip(&save.files) {
+        assert_eq!(a.name(), b.name());
+        assert_eq!(a.data, b.data);
+    }
+
+    let mut vmc = Vmc::format_in_memory(8).unwrap();
+    vmc.import_save(&decoded.save).unwrap();
+    assert_eq!(
+        vmc.read_file("BESLES-55673SAVEDATA", "list.ico").unwrap(),
+        pseudo_random(5000, 7)
+    );
+}
+
+#[test]
+fn test_max_rejects_corruption() {
+    let mut max = Vec::new();
+    write_max(&sample_save(), "", &mut max).unwrap();
+
+    let mut bad_crc = max.clone();
+    let last = bad_crc.len() - 1;
+    bad_crc[last] ^= 0x01;
+    let err = read_max(&mut Cursor::new(&bad_crc)).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::InvalidData);
+
+    let mut bad_magic = max.clone();
+    bad_magic[0] = b'X';
+    assert!(read_max(&mut Cursor::new(&bad_magic)).is_err());
+
+    let err = read_max(&mut Cursor::new(&max[..max.len() - 10])).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::UnexpectedEof);
+
+    // A compressed size of 4 GB is read as far as the file goes, not allocated
+    let mut huge = max.clone();
+    huge[0x50..0x54].copy_from_slice(&u32::MAX.to_le_bytes());
+    let err = read_max(&mut Cursor::new(&huge)).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::UnexpectedEof);
+}
+
+// The stream was written by Haruhiko Okumura's reference LZARI.C encoder rather than by
+// this crate, with the .max header put around it by hand
+#[test]
+fn test_decode_reference_lzari_stream() {
+    let max = read_max(&mut Cursor::new(include_bytes!("data/okumura_lzari.max"))).unwrap();
+    assert_eq!(max.title, "PES 2014");
+    assert_eq!(max.save.name(), "BESLES-55673SAVEDATA");
+    assert_eq!(max.save.files.len(), 2);
+
+    let icon_sys = &max.save.file("icon.sys").unwrap().data;
+    assert_eq!(icon_sys.len(), 136);
+    assert_eq!(&icon_sys[..4], b"PS2D");
+
+    let mut expected = b"Master League week 12, cup final. ".repeat(12);
+    expected.extend(0..64u8);
+    assert_eq!(max.save.file("BESLES-55673").unwrap().data, expected);
+}
+
+#[test]
+fn test_export_uses_icon_sys_title() {
+    let dir = tempfile::tempdir().unwrap();
+    let mut vmc = Vmc::format(dir.path().join("card.ps2")).unwrap();
+    vmc.create_dir("BESLES-55673SAVEDATA").unwrap();
+    let mut icon_sys = vec![0u8; 964];
+    icon_sys[..4].copy_from_slice(b"PS2D");
+    icon_sys[0xC0..0xC8].copy_from_slice(b"PES 2014");
+    vmc.write_file("BESLES-55673SAVEDATA", "icon.sys", &icon_sys)
+        .unwrap();
+    vmc.create_dir("NOICON").unwrap();
+
+    for (save_dir, title) in [("BESLES-55673SAVEDATA", "PES 2014"), ("NOICON", "NOICON")] {
+        let output = dir.path().join(format!("{save_dir}.max"));
+        export_save_file(&mut vmc, save_dir, output.to_str().unwrap()).unwrap();
+        let max = read_max(&mut std::fs::File::open(&output).unwrap()).unwrap();
+        assert_eq!(max.title, title);
+    }
+}
@@ -0,0 +1,130 @@
+use alfatch_vmc::model::save_model::{SaveDir, SaveFile};
+use alfatch_vmc::model::vmc_core_model::{
+    DIR_MODE, FILE_MODE, Ps2Time, RawFSEntry, Vmc, parse_fs_entry_from_bytes,
+};
+use alfatch_vmc::vmc::psu::{read_psu, write_psu};
+use std::io::{Cursor, ErrorKind};
+
+fn time(day: u8) -> Ps2Time {
+    Ps2Time {
+        sec: 5,
+        min: 30,
+        hour: 12,
+        day,
+        month: 3,
+        year: 2006,
+    }
+}
+
+fn sample_save() -> SaveDir {
+    let mut icon = RawFSEntry::new("icon.sys", FILE_MODE, 964, 0, time(1));
+    icon.set_modified(time(2));
+    // Copy-protected file, as some games use
+    let data = RawFSEntry::new("BASLUS-21050", FILE_MODE | 0x0800, 1500, 0, time(3));
+    SaveDir {
+        entry: RawFSEntry::new("BASLUS-21050", DIR_MODE, 4, 0, time(4)),
+        files: vec![
+            SaveFile {
+                entry: icon,
+                data: vec![0x11; 964],
+            },
+            SaveFile {
+                entry: data,
+                data: (0..1500u32).map(|i| i as u8).collect(),
+            },
+        ],
+    }
+}
+
+#[test]
+fn test_psu_layout() {
+    let mut psu = Vec::new();
+    write_psu(&sample_save(), &mut psu).unwrap();
+
+    // Directory, ".", "..", then two files padded to 1 KB each
+    assert_eq!(psu.len(), 3 * 512 + 512 + 1024 + 512 + 2048);
+    let header = parse_fs_entry_from_bytes(&psu[..512]).unwrap();
+    assert_eq!(header.name_str(), "BASLUS-21050");
+    assert_eq!(header.length, 4);
+    assert_eq!(
+        parse_fs_entry_from_bytes(&psu[512..]).unwrap().name_str(),
+        "."
+    );
+    assert_eq!(
+        parse_fs_entry_from_bytes(&psu[1024..]).unwrap().name_str(),
+        ".."
+    );
+    assert_eq!(&psu[2048..2048 + 964], &[0x11; 964][..]);
+
+    let save = read_psu(&mut Cursor::new(&psu)).unwrap();
+    assert_eq!(save.files.len(), 2);
+    assert_eq!(save.file("BASLUS-21050").unwrap().data.len(), 1500);
+}
+
+#[test]
+fn test_import_restores_metadata_and_export_round_trips() {
+    let mut original = Vec::new();
+    write_psu(&sample_save(), &mut original).unwrap();
+
+    let mut vmc = Vmc::format_in_memory(8).unwrap();
+    let name = vmc.import_psu(&mut Cursor::new(&original)).unwrap();
+    assert_eq!(name, "BASLUS-21050");
+
+    assert_eq!(
+        vmc.read_file("BASLUS-21050", "icon.sys").unwrap(),
+        vec![0x11; 964]
+    );
+    let meta = vmc.metadata("BASLUS-21050/BASLUS-21050").unwrap();
+    assert_eq!(meta.mode, FILE_MODE | 0x0800);
+    let save = vmc.export_save("BASLUS-21050").unwrap();
+    assert_eq!(save.entry.created(), time(4));
+    assert_eq!(save.file("icon.sys").unwrap().entry.modified(), time(2));
+
+    let mut exported = Vec::new();
+    vmc.export_psu("BASLUS-21050", &mut exported).unwrap();
+    assert_eq!(exported, original);
+}
+
+#[test]
+fn test_import_errors() {
+    let mut psu = Vec::new();
+    write_psu(&sample_save(), &mut psu).unwrap();
+
+    let mut vmc = Vmc::format_in_memory(8).unwrap();
+    vmc.import_psu(&mut Cursor::new(&psu)).unwrap();
+    let err = vmc.import_psu(&mut Cursor::new(&psu)).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::AlreadyExists);
+
+    // A truncated archive must not leave a partial save behind
+    let mut vmc = Vmc::format_in_memory(8).unwrap();
+    let err = vmc.import_psu(&mut Cursor::new(&psu[..2500])).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::UnexpectedEof);
+    assert!(vmc.read_dir("").unwrap().is_empty());
+
+    // A file entry first is not a .psu
+    let err = read_psu(&mut Cursor::new(&psu[2048 - 512..])).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::InvalidData);
+}
+
+#[test]
+fn test_oversized_file_length_is_rejected() {
+    let mut psu = Vec::new();
+    write_psu(&sample_save(), &mut psu).unwrap();
+    // icon.sys claims 4 GB in an archive of a few KB
+    psu[3 * 512 + 4..3 * 512 + 8].copy_from_slice(&u32::MAX.to_le_bytes());
+
+    let err = read_psu(&mut Cursor::new(&psu)).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::UnexpectedEof);
+
+    // The same length on a card entry stops export at the end of the chain
+    let mut vmc = Vmc::format_in_memory(8).unwrap();
+    vmc.import_save(&sample_save()).unwrap();
+    let save_cluster = vmc.metadata("BASLUS-21050").unwrap().cluster;
+    let entry_cluster = vmc.superblock.alloc_offset + vmc.build_cluster_chain(save_cluster)[1];
+    let mut image = vmc.into_inner().into_inner();
+    let offset = entry_cluster as usize * 1024 + 4;
+    image[offset..offset + 4].copy_from_slice(&u32::MAX.to_le_bytes());
+    let mut vmc = Vmc::from_backend(Cursor::new(image)).unwrap();
+    let err = vmc.export_save("BASLUS-21050").unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::UnexpectedEof);
+}
@@ -0,0 +1,138 @@
+use alfatch_vmc::model::save_model::{SaveDir, SaveFile};
+use alfatch_vmc::model::vmc_core_model::{DIR_MODE, FILE_MODE, Ps2Time, RawFSEntry, Vmc};
+use alfatch_vmc::vmc::psv::{psv_signature, read_psv, write_psv};
+use alfatch_vmc::vmc::vmc_core::{import_save_file, unpack_save_file};
+use std::io::{Cursor, ErrorKind};
+
+fn icon_sys() -> Vec<u8> {
+    let mut data = vec![0u8; 964];
+    data[..4].copy_from_slice(b"PS2D");
+    data[0x104..0x104 + 8].copy_from_slice(b"list.ico");
+    data[0x144..0x144 + 8].copy_from_slice(b"copy.ico");
+    data[0x184..0x184 + 8].copy_from_slice(b"list.ico");
+    data
+}
+
+fn sample_save() -> SaveDir {
+    let now = Ps2Time::now();
+    let file = |name: &str, data: Vec<u8>| SaveFile {
+        entry: RawFSEntry::new(name, FILE_MODE, data.len() as u32, 0, now),
+        data,
+    };
+    SaveDir {
+        entry: RawFSEntry::new("BASLUS-21050", DIR_MODE, 6, 0, now),
+        files: vec![
+            file("icon.sys", icon_sys()),
+            file("list.ico", vec![3u8; 3001]),
+            file("copy.ico", vec![4u8; 17]),
+            file("BASLUS-21050", b"franchise ".repeat(500)),
+        ],
+    }
+}
+
+#[test]
+fn test_psv_round_trip_is_signed() {
+    let save = sample_save();
+    let mut psv = Vec::new();
+    write_psv(&save, &mut psv).unwrap();
+    assert_eq!(&psv[..4], b"\0VSP");
+    assert_eq!(&psv[0x1C..0x30], &psv_signature(&psv));
+
+    // Icon positions in the PS2 header point at the listed files
+    let icon_position = u32::from_le_bytes(psv[0x4C..0x50].try_into().unwrap()) as usize;
+    let icon_size = u32::from_le_bytes(psv[0x50..0x54].try_into().unwrap()) as usize;
+    assert_eq!(
+        &psv[icon_position..icon_position + icon_size],
+        &[3u8; 3001][..]
+    );
+
+    let decoded = read_psv(&mut Cursor::new(&psv)).unwrap();
+    assert!(decoded.signature_valid);
+    assert_eq!(decoded.save.name(), "BASLUS-21050");
+    assert_eq!(decoded.save.entry.mode, DIR_MODE);
+    assert_eq!(decoded.save.files.len(), save.files.len());
+    for (a, b) in decoded.save.files.iter().zip(&save.files) {
+        assert_eq!(a.name(), b.name());
+        assert_eq!(a.entry.mode, b.entry.mode);
+        assert_eq!(a.entry.modified(), b.entry.modified());
+        assert_eq!(a.data, b.data);
+    }
+
+    // The output only depends on the save, so exporting twice gives the same file
+    let mut again = Vec::new();
+    write_psv(&save, &mut again).unwrap();
+    assert_eq!(psv, again);
+}
+
+#[test]
+fn test_tampered_psv_fails_signature() {
+    let mut psv = Vec::new();
+    write_psv(&sample_save(), &mut psv).unwrap();
+    let last = psv.len() - 1;
+    psv[last] ^= 0xFF;
+
+    let decoded = read_psv(&mut Cursor::new(&psv)).unwrap();
+    assert!(!decoded.signature_valid);
+
+    let mut vmc = Vmc::format_in_memory(8).unwrap();
+    let err = vmc.import_psv(&mut Cursor::new(&psv)).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::InvalidData);
+    assert!(vmc.read_dir("BASLUS-21050").is_err());
+
+    // The CLI import and unpack refuse it the same way
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("tampered.psv");
+    std::fs::write(&path, &psv).unwrap();
+    let mut card = Vmc::format(dir.path().join("card.ps2")).unwrap();
+    let err = import_save_file(&mut card, path.to_str().unwrap()).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::InvalidData);
+    assert!(card.read_dir("BASLUS-21050").is_err());
+    let out = dir.path().join("out");
+    let err = unpack_save_file(path.to_str().unwrap(), out.to_str().unwrap()).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::InvalidData);
+    assert!(!out.exists());
+
+    psv[0] = b'X';
+    let err = read_psv(&mut Cursor::new(&psv)).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::InvalidData);
+}
+
+// Expected value computed outside this crate, with Python's `cryptography` package
+// running the same key derivation from the published key, IV and LAID/PAID. It pins the
+// AES/HMAC plumbing; it has not been checked against a console-signed file.
+#[test]
+fn test_signature_known_answer() {
+    let mut image = b"\0VSP".to_vec();
+    image.extend([0u8; 4]);
+    image.extend(1..=20u8);
+    image.extend(0xAA..0xAA + 20u8);
+    image.extend((0..200u32).map(|i| (i * 7) as u8));
+
+    let expected = [
+        0x75, 0xa6, 0x5f, 0x67, 0xe4, 0x1a, 0xb7, 0xc6, 0xad, 0x19, 0x29, 0x36, 0xb3, 0x9a, 0x86,
+        0x43, 0xa8, 0x39, 0x97, 0x7e,
+    ];
+    assert_eq!(psv_signature(&image), expected);
+}
+
+#[test]
+fn test_psv_card_round_trip() {
+    let mut vmc = Vmc::format_in_memory(8).unwrap();
+    let mut psv = Vec::new();
+    write_psv(&sample_save(), &mut psv).unwrap();
+    assert_eq!(
+        vmc.import_psv(&mut Cursor::new(&psv)).unwrap(),
+        "BASLUS-21050"
+    );
+    assert_eq!(
+        vmc.read_file("BASLUS-21050", "list.ico").unwrap(),
+        vec![3u8; 3001]
+    );
+
+    let mut exported = Vec::new();
+    vmc.export_psv("BASLUS-21050", &mut exported).unwrap();
+    let decoded = read_psv(&mut Cursor::new(&exported)).unwrap();
+    assert!(decoded.signature_valid);
+    let names: Vec<String> = decoded.save.files.iter().map(|f| f.name()).collect();
+    assert_eq!(names, ["icon.sys", "list.ico", "copy.ico", "BASLUS-21050"]);
+}
@@ -0,0 +1,62 @@
+use alfatch_vmc::model::vmc_core_model::Vmc;
+use alfatch_vmc::vmc::sjis;
+use std::io::ErrorKind;
+
+#[test]
+fn test_decode_and_normalize() {
+    // "ＢＡＳＬＵＳ　セーブ" followed by the NUL terminator and garbage
+    let bytes = [
+        0x82, 0x61, 0x82, 0x60, 0x82, 0x72, 0x82, 0x6B, 0x82, 0x74, 0x82, 0x72, 0x81, 0x40, 0x83,
+        0x5A, 0x81, 0x5B, 0x83, 0x75, 0x00, 0xFF,
+    ];
+    assert_eq!(sjis::decode(&bytes), "ＢＡＳＬＵＳ　セーブ");
+    assert_eq!(sjis::decode_normalized(&bytes), "BASLUS セーブ");
+    assert_eq!(sjis::decode(b"BASLUS-21050\0\0"), "BASLUS-21050");
+
+    // A lone lead byte still decodes instead of dropping the whole name
+    assert_eq!(sjis::decode(&[b'A', 0x82]), "A\u{FFFD}");
+}
+
+#[test]
+fn test_encode() {
+    assert_eq!(
+        sjis::encode("ＰＳ２セーブ").unwrap(),
+        [
+            0x82, 0x6F, 0x82, 0x72, 0x82, 0x51, 0x83, 0x5A, 0x81, 0x5B, 0x83, 0x75
+        ]
+    );
+    let err = sjis::encode("save 😀").unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::InvalidInput);
+
+    // Fields keep their NUL terminator and never split a double-byte character
+    assert_eq!(sjis::encode_field("ABセ", 4), [b'A', b'B', 0, 0]);
+    assert_eq!(sjis::encode_field("A😀", 4), [b'A', b'?', 0, 0]);
+}
+
+#[test]
+fn test_shift_jis_names_on_card() {
+    let mut vmc = Vmc::format_in_memory(8).unwrap();
+    vmc.create_dir("ＢＡＳＬＵＳセーブ").unwrap();
+    vmc.write_file("ＢＡＳＬＵＳセーブ", "データ.bin", &[5u8; 100])
+        .unwrap();
+
+    let names: Vec<String> = vmc
+        .list_root_directory()
+        .unwrap()
+        .into_iter()
+        .map(|e| e.name)
+        .collect();
+    assert!(names.contains(&"ＢＡＳＬＵＳセーブ".to_string()));
+    assert_eq!(
+        vmc.read_file("ＢＡＳＬＵＳセーブ", "データ.bin").unwrap(),
+        vec![5u8; 100]
+    );
+
+    // 16 full-width characters take 32 bytes, more than a name can hold
+    let err = vmc
+        .create_dir("ＡＢＣＤＥＦＧＨＩＪＫＬＭＮＯＰ")
+        .unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::InvalidInput);
+    let err = vmc.create_dir("😀").unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::InvalidInput);
+}
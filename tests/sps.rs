@@ -0,0 +1,151 @@
+use alfatch_vmc::model::vmc_core_model::{Ps2Time, Vmc};
+use alfatch_vmc::vmc::sps::read_sps;
+use alfatch_vmc::vmc::vmc_core::{import_save_file, unpack_save_file};
+use std::io::{Cursor, ErrorKind};
+use tempfile::tempdir;
+
+const CREATED: Ps2Time = Ps2Time {
+    sec: 10,
+    min: 20,
+    hour: 8,
+    day: 3,
+    month: 11,
+    year: 2003,
+};
+
+fn long_string(out: &mut Vec<u8>, s: &[u8]) {
+    out.extend_from_slice(&(s.len() as u32).to_le_bytes());
+    out.extend_from_slice(s);
+}
+
+// Header with a few extra bytes past the 98-byte minimum, as X-Port writes them
+fn entry(out: &mut Vec<u8>, name: &str, length: u32, mode: u16) {
+    out.extend_from_slice(&102u16.to_le_bytes());
+    let mut name_buf = [0u8; 64];
+    name_buf[..name.len()].copy_from_slice(name.as_bytes());
+    out.extend_from_slice(&name_buf);
+    out.extend_from_slice(&length.to_le_bytes());
+    out.extend_from_slice(&[0; 8]);
+    out.extend_from_slice(&mode.to_be_bytes());
+    out.extend_from_slice(&[0; 2]);
+    out.extend_from_slice(&CREATED.to_bytes());
+    out.extend_from_slice(&CREATED.to_bytes());
+    out.extend_from_slice(&[0; 4]);
+}
+
+fn sample_sps() -> Vec<u8> {
+    let mut sps = Vec::new();
+    long_string(&mut sps, b"SharkPortSave");
+    sps.extend_from_slice(&0x0002_0000u32.to_le_bytes());
+    long_string(&mut sps, b"Ratchet & Clank");
+    long_string(&mut sps, b"2003-11-03");
+    long_string(&mut sps, b"100% complete");
+    sps.extend_from_slice(&0u32.to_le_bytes());
+    entry(&mut sps, "BASCUS-97199", 4, 0x8427);
+    entry(&mut sps, "icon.sys", 964, 0x8497);
+    sps.extend_from_slice(&[0x33; 964]);
+    entry(&mut sps, "BASCUS-97199", 2000, 0x8497);
+    sps.extend((0..2000u32).map(|i| i as u8));
+    // Checksum
+    sps.extend_from_slice(&[0xAB; 4]);
+    sps
+}
+
+#[test]
+fn test_read_sps() {
+    let sps = read_sps(&mut Cursor::new(sample_sps())).unwrap();
+    assert_eq!(sps.title, "Ratchet & Clank");
+    assert_eq!(sps.comment, "100% complete");
+    assert_eq!(sps.save.name(), "BASCUS-97199");
+    assert_eq!(sps.save.entry.mode, 0x8427);
+    assert_eq!(sps.save.entry.created(), CREATED);
+
+    let names: Vec<String> = sps.save.files.iter().map(|f| f.name()).collect();
+    assert_eq!(names, ["icon.sys", "BASCUS-97199"]);
+    assert_eq!(sps.save.files[0].entry.mode, 0x8497);
+    assert_eq!(sps.save.files[1].data.len(), 2000);
+}
+
+#[test]
+fn test_read_sps_errors() {
+    let sps = sample_sps();
+    let err = read_sps(&mut Cursor::new(&sps[..sps.len() - 500])).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::UnexpectedEof);
+
+    let mut bad = sps.clone();
+    bad[4] = b'X';
+    let err = read_sps(&mut Cursor::new(bad)).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::InvalidData);
+}
+
+#[test]
+fn test_import_and_unpack_xps() {
+    let dir = tempdir().unwrap();
+    let save_file = dir.path().join("ratchet.xps");
+    std::fs::write(&save_file, sample_sps()).unwrap();
+    let save_file = save_file.to_str().unwrap();
+
+    let mut vmc = Vmc::format(dir.path().join("card.ps2")).unwrap();
+    assert_eq!(
+        import_save_file(&mut vmc, save_file).unwrap(),
+        "BASCUS-97199"
+    );
+    assert_eq!(
+        vmc.read_file("BASCUS-97199", "icon.sys").unwrap(),
+        vec![0x33; 964]
+    );
+
+    let out = dir.path().join("out");
+    assert_eq!(
+        unpack_save_file(save_file, out.to_str().unwrap()).unwrap(),
+        2
+    );
+    let data = std::fs::read(out.join("BASCUS-97199").join("BASCUS-97199")).unwrap();
+    assert_eq!(data.len(), 2000);
+}
+
+#[test]
+fn test_unpack_refuses_names_leaving_the_output_dir() {
+    let dir = tempdir().unwrap();
+    let out = dir.path().join("out/a/b");
+    let evil_file = |dir_name: &str, file_name: &str| {
+        let mut sps = Vec::new();
+        long_string(&mut sps, b"SharkPortSave");
+        sps.extend_from_slice(&0x0002_0000u32.to_le_bytes());
+        for s in [&b"evil"[..], b"", b""] {
+            long_string(&mut sps, s);
+        }
+        sps.extend_from_slice(&0u32.to_le_bytes());
+        entry(&mut sps, dir_name, 3, 0x8427);
+        entry(&mut sps, file_name, 4, 0x8497);
+        sps.extend_from_slice(b"pwnd");
+        let path = dir.path().join("evil.sps");
+        std::fs::write(&path, sps).unwrap();
+        path
+    };
+
+    for (dir_name, file_name) in [
+        ("SAVE", "../../../escaped.txt"),
+        ("SAVE", "/tmp/escaped.txt"),
+        ("SAVE", "..\\escaped.txt"),
+        ("SAVE", ".."),
+        ("..", "escaped.txt"),
+    ] {
+        let input = evil_file(dir_name, file_name);
+        let err = unpack_save_file(input.to_str().unwrap(), out.to_str().unwrap()).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidData, "{dir_name}/{file_name}");
+    }
+    assert!(!dir.path().join("out/escaped.txt").exists());
+    assert!(!out.join("escaped.txt").exists());
+    assert!(!out.exists());
+}
+
+#[test]
+fn test_oversized_file_length_is_rejected() {
+    let mut sps = sample_sps();
+    // The size field of icon.sys's header, 2 + 64 bytes into it
+    let icon_header = sps.len() - 4 - 2000 - 102 - 964 - 102;
+    sps[icon_header + 66..icon_header + 70].copy_from_slice(&u32::MAX.to_le_bytes());
+    let err = read_sps(&mut Cursor::new(sps)).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::UnexpectedEof);
+}
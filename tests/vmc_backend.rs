@@ -0,0 +1,49 @@
+use alfatch_vmc::model::vmc_core_model::Vmc;
+use std::io::{Cursor, Read};
+
+#[test]
+fn test_card_round_trips_through_memory() {
+    let mut vmc = Vmc::format_in_memory(8).unwrap();
+    vmc.create_dir("BASLUS-21050").unwrap();
+    vmc.write_file("BASLUS-21050", "icon.sys", &[3u8; 964])
+        .unwrap();
+
+    let image = vmc.into_inner().into_inner();
+    assert_eq!(image.len(), 8 * 1024 * 1024);
+
+    // A borrowed, read-only buffer is enough for reading
+    let mut vmc = Vmc::from_backend(Cursor::new(&image[..])).unwrap();
+    let names: Vec<String> = vmc.walk().map(|e| e.unwrap().path).collect();
+    assert_eq!(names, ["BASLUS-21050", "BASLUS-21050/icon.sys"]);
+
+    let mut data = Vec::new();
+    vmc.open("BASLUS-21050/icon.sys")
+        .unwrap()
+        .read_to_end(&mut data)
+        .unwrap();
+    assert_eq!(data, vec![3u8; 964]);
+}
+
+#[test]
+fn test_raw_dump_in_memory() {
+    let mut vmc = Vmc::format_in_memory(16).unwrap();
+    vmc.create_dir("BESLES-55673").unwrap();
+
+    let mut raw = Vec::new();
+    vmc.export_image(&mut raw, true).unwrap();
+    assert_eq!(raw.len(), 2 * 8_650_752);
+
+    let mut vmc = Vmc::from_backend(Cursor::new(raw)).unwrap();
+    assert!(vmc.has_ecc());
+    assert_eq!(vmc.superblock.clusters_per_card, 16384);
+    vmc.write_file("BESLES-55673", "data.bin", b"hello")
+        .unwrap();
+    assert_eq!(vmc.read_file("BESLES-55673", "data.bin").unwrap(), b"hello");
+    assert_eq!(vmc.verify_ecc().unwrap().pages_corrected, 0);
+}
+
+#[test]
+fn test_rejects_bad_buffers() {
+    assert!(Vmc::from_backend(Cursor::new(vec![0u8; 4096])).is_err());
+    assert!(Vmc::format_in_memory(12).is_err());
+}
@@ -0,0 +1,190 @@
+use alfatch_vmc::model::vmc_backup::{BlockRecovery, SyncData};
+use alfatch_vmc::model::vmc_core_model::Vmc;
+use std::io::{self, Cursor, ErrorKind, Read, Seek, SeekFrom, Write};
+
+const BLOCK_SIZE: usize = 16 * 512;
+
+// A backend that "loses power" during its n-th write, either before touching the
+// flash or right after erasing the pages it was about to program. Writes and syncs
+// are logged in order.
+struct CrashingCursor {
+    inner: Cursor<Vec<u8>>,
+    writes: usize,
+    fail_at: usize,
+    erased: bool,
+    log: Vec<&'static str>,
+}
+
+impl Read for CrashingCursor {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.inner.read(buf)
+    }
+}
+
+impl Seek for CrashingCursor {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.inner.seek(pos)
+    }
+}
+
+impl Write for CrashingCursor {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.writes += 1;
+        self.log.push("write");
+        if self.writes == self.fail_at {
+            if self.erased {
+                self.inner.write_all(&vec![0xFF; buf.len()])?;
+            }
+            return Err(io::Error::other("power lost"));
+        }
+        self.inner.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl SyncData for CrashingCursor {
+    fn sync_data(&mut self) -> io::Result<()> {
+        self.log.push("sync");
+        Ok(())
+    }
+}
+
+// Card with one save, then a write of data.bin that crashes as described
+fn crashed_card(fail_at: usize, erased: bool) -> Vmc<Cursor<Vec<u8>>> {
+    let mut vmc = Vmc::format_in_memory(8).unwrap();
+    vmc.create_dir("SAVE").unwrap();
+    let backend = CrashingCursor {
+        inner: vmc.into_inner(),
+        writes: 0,
+        fail_at,
+        erased,
+        log: Vec::new(),
+    };
+    let mut vmc = Vmc::from_backend(backend).unwrap();
+    assert!(vmc.write_file("SAVE", "data.bin", &[5u8; 3000]).is_err());
+    Vmc::from_backend(vmc.into_inner().inner).unwrap()
+}
+
+#[test]
+fn test_completed_writes_leave_no_pending_copy() {
+    let mut vmc = Vmc::format_in_memory(8).unwrap();
+    vmc.create_dir("SAVE").unwrap();
+    vmc.write_file("SAVE", "data.bin", &[5u8; 3000]).unwrap();
+    let backup2 = vmc.superblock.backup_block2 as usize;
+
+    let image = vmc.into_inner().into_inner();
+    let record = &image[backup2 * BLOCK_SIZE..(backup2 + 1) * BLOCK_SIZE];
+    assert!(record.iter().all(|&b| b == 0xFF));
+    let vmc = Vmc::from_backend(Cursor::new(image)).unwrap();
+    assert_eq!(vmc.pending_block_copy(), None);
+
+    // Every step of a block copy is synced before the next one is written
+    let mut vmc = Vmc::format_in_memory(8).unwrap();
+    vmc.create_dir("SAVE").unwrap();
+    let backend = CrashingCursor {
+        inner: vmc.into_inner(),
+        writes: 0,
+        fail_at: 0,
+        erased: false,
+        log: Vec::new(),
+    };
+    let mut vmc = Vmc::from_backend(backend).unwrap();
+    vmc.write_file("SAVE", "data.bin", &[5u8; 3000]).unwrap();
+    let log = vmc.into_inner().log;
+    assert!(!log.is_empty() && log.len().is_multiple_of(8));
+    assert!(log.chunks(2).all(|step| step == ["write", "sync"]));
+}
+
+#[test]
+fn test_roll_forward_erased_block() {
+    // Writes: backup block 1, copy record, then the target is erased but never written
+    let mut vmc = crashed_card(3, true);
+    let pending = vmc.pending_block_copy().unwrap();
+    assert!(pending.target_erased);
+    assert!(!pending.target_complete);
+
+    let err = vmc.recover_block_copy(BlockRecovery::RollBack).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::InvalidInput);
+
+    vmc.recover_block_copy(BlockRecovery::RollForward).unwrap();
+    assert_eq!(vmc.pending_block_copy(), None);
+    assert!(vmc.check().unwrap().is_clean());
+    vmc.write_file("SAVE", "data.bin", &[6u8; 3000]).unwrap();
+    assert_eq!(vmc.read_file("SAVE", "data.bin").unwrap(), vec![6u8; 3000]);
+}
+
+#[test]
+fn test_roll_back_needs_a_complete_target() {
+    // The target was about to be written when the power went. Nothing proves it is
+    // untouched, so it can only be rolled forward.
+    let mut vmc = crashed_card(3, false);
+    let pending = vmc.pending_block_copy().unwrap();
+    assert!(!pending.target_erased);
+    assert!(!pending.target_complete);
+
+    // Nothing else may be written until the copy is settled
+    let err = vmc.write_file("SAVE", "other.bin", b"x").unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::InvalidInput);
+
+    let err = vmc.recover_block_copy(BlockRecovery::RollBack).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::InvalidInput);
+    vmc.recover_block_copy(BlockRecovery::RollForward).unwrap();
+    assert!(vmc.check().unwrap().is_clean());
+    assert!(vmc.read_file("SAVE", "data.bin").is_err());
+
+    let image = vmc.into_inner().into_inner();
+    let vmc = Vmc::from_backend(Cursor::new(image)).unwrap();
+    assert_eq!(vmc.pending_block_copy(), None);
+}
+
+#[test]
+fn test_record_left_after_complete_write() {
+    // The target is complete, only erasing the record was cut short
+    let mut vmc = crashed_card(4, false);
+    let pending = vmc.pending_block_copy().unwrap();
+    assert!(pending.target_complete);
+    vmc.recover_block_copy(BlockRecovery::RollForward).unwrap();
+    assert!(vmc.check().unwrap().is_clean());
+
+    // With the target complete, dropping the record loses nothing either
+    let mut vmc = crashed_card(4, false);
+    vmc.recover_block_copy(BlockRecovery::RollBack).unwrap();
+    assert_eq!(vmc.pending_block_copy(), None);
+    assert!(vmc.check().unwrap().is_clean());
+}
+
+#[test]
+fn test_console_record_and_zeroed_block() {
+    let mut vmc = Vmc::format_in_memory(8).unwrap();
+    vmc.create_dir("SAVE").unwrap();
+    vmc.write_file("SAVE", "data.bin", &[5u8; 1000]).unwrap();
+    let cluster = vmc.superblock.alloc_offset + vmc.metadata("SAVE/data.bin").unwrap().cluster;
+    let block = cluster as usize / 8;
+    let backup1 = vmc.superblock.backup_block1 as usize;
+    let backup2 = vmc.superblock.backup_block2 as usize;
+    let mut image = vmc.into_inner().into_inner();
+
+    // What the PS2's driver leaves behind: the new block in backup_block1 and the
+    // target with bit 31 set in the first word of backup_block2
+    let mut new_block = image[block * BLOCK_SIZE..(block + 1) * BLOCK_SIZE].to_vec();
+    let start = (cluster as usize % 8) * 1024;
+    new_block[start..start + 1000].fill(7);
+    image[backup1 * BLOCK_SIZE..(backup1 + 1) * BLOCK_SIZE].copy_from_slice(&new_block);
+    image[backup2 * BLOCK_SIZE..backup2 * BLOCK_SIZE + 4]
+        .copy_from_slice(&(block as u32 | 0x8000_0000).to_le_bytes());
+
+    let mut vmc = Vmc::from_backend(Cursor::new(image.clone())).unwrap();
+    let pending = vmc.pending_block_copy().unwrap();
+    assert_eq!(pending.block, block as u32);
+    assert!(!pending.target_erased && !pending.target_complete);
+    vmc.recover_block_copy(BlockRecovery::RollForward).unwrap();
+    assert_eq!(vmc.read_file("SAVE", "data.bin").unwrap(), vec![7u8; 1000]);
+
+    // A zeroed backup_block2 has no flag bit, so nothing is pending
+    image[backup2 * BLOCK_SIZE..(backup2 + 1) * BLOCK_SIZE].fill(0);
+    let vmc = Vmc::from_backend(Cursor::new(image)).unwrap();
+    assert_eq!(vmc.pending_block_copy(), None);
+}
@@ -0,0 +1,86 @@
+use alfatch_vmc::model::vmc_check::CheckIssue;
+use alfatch_vmc::model::vmc_core_model::Vmc;
+use std::io::{Cursor, ErrorKind};
+
+#[test]
+fn test_format_lists_bad_blocks() {
+    let vmc = Vmc::format_in_memory_with_bad_blocks(8, &[100, 6]).unwrap();
+    assert_eq!(vmc.superblock.bad_blocks(), [6, 100]);
+    let clean = Vmc::format_in_memory(8).unwrap();
+    assert_eq!(
+        vmc.count_free_clusters(),
+        clean.count_free_clusters() - 2 * 8
+    );
+
+    // The list survives a reopen
+    let image = vmc.into_inner().into_inner();
+    let vmc = Vmc::from_backend(Cursor::new(image)).unwrap();
+    assert_eq!(vmc.superblock.bad_blocks(), [6, 100]);
+    assert_eq!(vmc.superblock.bad_block_list[2], 0xFFFF_FFFF);
+}
+
+#[test]
+fn test_format_rejects_blocks_outside_allocatable_area() {
+    // Block 5 holds the FAT and the root directory, 1022 and 1023 are the backup blocks
+    for block in [0, 5, 1022, 1023, 5000] {
+        let err = Vmc::format_in_memory_with_bad_blocks(8, &[block])
+            .err()
+            .unwrap();
+        assert_eq!(err.kind(), ErrorKind::InvalidInput, "block {block}");
+    }
+    let too_many: Vec<u32> = (10..43).collect();
+    assert!(Vmc::format_in_memory_with_bad_blocks(8, &too_many).is_err());
+}
+
+#[test]
+fn test_allocator_skips_bad_blocks() {
+    let mut vmc = Vmc::format_in_memory_with_bad_blocks(8, &[6, 7]).unwrap();
+    vmc.create_dir("SAVE").unwrap();
+    vmc.write_file("SAVE", "data.bin", &[4u8; 40 * 1024])
+        .unwrap();
+
+    let cluster = vmc.metadata("SAVE/data.bin").unwrap().cluster;
+    let chain = vmc.build_cluster_chain(cluster);
+    assert_eq!(chain.len(), 40);
+    assert!(chain.iter().all(|&c| !vmc.is_bad_cluster(c)));
+    assert!(vmc.check().unwrap().is_clean());
+    assert_eq!(
+        vmc.read_file("SAVE", "data.bin").unwrap(),
+        vec![4u8; 40 * 1024]
+    );
+    assert!(vmc.bad_block_reads().is_empty());
+}
+
+#[test]
+fn test_check_and_reads_flag_data_in_bad_blocks() {
+    let mut vmc = Vmc::format_in_memory(8).unwrap();
+    vmc.create_dir("SAVE").unwrap();
+    vmc.write_file("SAVE", "data.bin", &[4u8; 3000]).unwrap();
+    let cluster = vmc.metadata("SAVE/data.bin").unwrap().cluster;
+    let block = (vmc.superblock.alloc_offset + cluster) / 8;
+
+    // The block wore out after the save was written
+    vmc.superblock.bad_block_list[0] = block;
+    let report = vmc.check().unwrap();
+    assert_eq!(report.bad_blocks, [block]);
+    let issue = CheckIssue::DataInBadBlock {
+        path: "SAVE/data.bin".to_string(),
+        block,
+    };
+    assert!(report.issues.contains(&issue));
+
+    // It is informational: the card still counts as clean and repair leaves the data
+    assert!(issue.is_informational());
+    assert!(report.is_clean());
+    let repair = vmc.repair(&mut Vec::new()).unwrap();
+    assert!(repair.remaining.is_clean());
+    assert!(repair.remaining.issues.contains(&issue));
+    assert_eq!(vmc.read_file("SAVE", "data.bin").unwrap(), vec![4u8; 3000]);
+
+    // Reads through a fresh handle flag the block as well
+    let mut vmc = Vmc::from_backend(vmc.into_inner()).unwrap();
+    vmc.superblock.bad_block_list[0] = block;
+    assert!(vmc.bad_block_reads().is_empty());
+    vmc.read_file("SAVE", "data.bin").unwrap();
+    assert_eq!(vmc.bad_block_reads(), [block]);
+}
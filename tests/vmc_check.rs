@@ -0,0 +1,98 @@
+mod common;
+
+use alfatch_vmc::model::vmc_check::CheckIssue;
+use common::{card_with_saves, chain_of, corrupt};
+
+#[test]
+fn test_clean_card() {
+    let mut vmc = card_with_saves();
+    let report = vmc.check().unwrap();
+    assert!(report.is_clean(), "{:?}", report.issues);
+    assert_eq!(report.directories, 4);
+    assert_eq!(report.files, 2);
+    let max_clusters = vmc.superblock.max_allocatable_clusters;
+    assert_eq!(
+        report.clusters_in_use,
+        max_clusters - vmc.count_free_clusters()
+    );
+}
+
+#[test]
+fn test_loop_cross_link_and_orphans() {
+    let mut vmc = card_with_saves();
+    let a = chain_of(&mut vmc, "SAVE-A/data.bin");
+    let b = chain_of(&mut vmc, "SAVE-B/data.bin");
+    assert_eq!((a.len(), b.len()), (3, 3));
+
+    let (mut vmc, _) = corrupt(vmc, |image, layout| {
+        // A's last cluster points back to its first; B continues into A after one cluster
+        layout.set_fat(image, a[2], 0x8000_0000 | a[0]);
+        layout.set_fat(image, b[0], 0x8000_0000 | a[1]);
+    });
+    let issues = vmc.check().unwrap().issues;
+    assert!(issues.contains(&CheckIssue::ChainLoop {
+        path: "SAVE-A/data.bin".to_string(),
+        cluster: a[0],
+    }));
+    assert!(issues.contains(&CheckIssue::CrossLinked {
+        cluster: a[1],
+        first: "SAVE-A/data.bin".to_string(),
+        second: "SAVE-B/data.bin".to_string(),
+    }));
+    assert!(issues.contains(&CheckIssue::OrphanedClusters {
+        clusters: vec![b[1], b[2]],
+    }));
+}
+
+#[test]
+fn test_chains_leaving_the_fat() {
+    let mut vmc = card_with_saves();
+    let a = chain_of(&mut vmc, "SAVE-A/data.bin");
+    let b = chain_of(&mut vmc, "SAVE-B/data.bin");
+    let max_clusters = vmc.superblock.max_allocatable_clusters;
+
+    let (mut vmc, _) = corrupt(vmc, |image, layout| {
+        layout.set_fat(image, a[1], 0x80FF_FFF0);
+        layout.set_fat(image, b[1], 0x8000_0000 | (max_clusters + 1));
+        layout.set_fat(image, b[2], 0x7FFF_FFFF);
+    });
+    let issues = vmc.check().unwrap().issues;
+    assert!(issues.contains(&CheckIssue::ChainOutOfFat {
+        path: "SAVE-A/data.bin".to_string(),
+        cluster: a[1],
+    }));
+    assert!(issues.contains(&CheckIssue::ClusterOutOfRange {
+        path: "SAVE-B/data.bin".to_string(),
+        cluster: max_clusters + 1,
+    }));
+    // Both files are now shorter than their length
+    let short = issues
+        .iter()
+        .filter(|i| matches!(i, CheckIssue::FileExceedsChain { .. }))
+        .count();
+    assert_eq!(short, 2);
+}
+
+#[test]
+fn test_length_mismatches() {
+    let mut vmc = card_with_saves();
+    let root = vmc.build_cluster_chain(vmc.superblock.rootdir_cluster);
+    let save_a = chain_of(&mut vmc, "SAVE-A");
+
+    let (mut vmc, _) = corrupt(vmc, |image, layout| {
+        // Root entry 3 is SAVE-B; entry 2 of SAVE-A is data.bin
+        layout.set_length(image, &root, 3, 40);
+        layout.set_length(image, &save_a, 2, 9000);
+    });
+    let issues = vmc.check().unwrap().issues;
+    assert!(issues.contains(&CheckIssue::DirLengthMismatch {
+        path: "SAVE-B".to_string(),
+        length: 40,
+        capacity: 4,
+    }));
+    assert!(issues.contains(&CheckIssue::FileExceedsChain {
+        path: "SAVE-A/data.bin".to_string(),
+        length: 9000,
+        chain_bytes: 3072,
+    }));
+}
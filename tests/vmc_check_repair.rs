@@ -0,0 +1,131 @@
+mod common;
+
+use alfatch_vmc::model::vmc_check::{CheckIssue, LOST_FOUND_DIR};
+use common::{card_with_saves, chain_of, corrupt};
+
+#[test]
+fn test_repair_clean_card_changes_nothing() {
+    let vmc = card_with_saves();
+    let (mut vmc, image) = corrupt(vmc, |_, _| {});
+    let mut backup = Vec::new();
+    let report = vmc.repair(&mut backup).unwrap();
+    assert!(report.found.is_clean());
+    assert!(report.recovered.is_empty());
+    assert_eq!(backup, image);
+    assert_eq!(vmc.into_inner().into_inner(), image);
+}
+
+#[test]
+fn test_repair_loop_cross_link_and_orphans() {
+    let mut vmc = card_with_saves();
+    let a = chain_of(&mut vmc, "SAVE-A/data.bin");
+    let b = chain_of(&mut vmc, "SAVE-B/data.bin");
+    let (mut vmc, damaged) = corrupt(vmc, |image, layout| {
+        layout.set_fat(image, a[2], 0x8000_0000 | a[0]);
+        layout.set_fat(image, b[0], 0x8000_0000 | a[1]);
+    });
+
+    let mut backup = Vec::new();
+    let report = vmc.repair(&mut backup).unwrap();
+    assert_eq!(backup, damaged);
+    assert!(!report.found.is_clean());
+    assert!(report.remaining.is_clean(), "{:?}", report.remaining.issues);
+
+    // A keeps all its data, B is cut to its own first cluster
+    assert_eq!(
+        vmc.read_file("SAVE-A", "data.bin").unwrap(),
+        vec![1u8; 3000]
+    );
+    assert_eq!(
+        vmc.read_file("SAVE-B", "data.bin").unwrap(),
+        vec![2u8; 1024]
+    );
+
+    // B's unreachable tail is filed under LOST.FOUND
+    assert_eq!(
+        report.recovered,
+        vec![format!("{LOST_FOUND_DIR}/FILE0000.CHK")]
+    );
+    let lost = vmc.read_file(LOST_FOUND_DIR, "FILE0000.CHK").unwrap();
+    assert_eq!(lost.len(), 2048);
+    assert!(lost[..3000 - 1024].iter().all(|&x| x == 2));
+    assert_eq!(chain_of(&mut vmc, "LOST.FOUND/FILE0000.CHK"), b[1..]);
+}
+
+#[test]
+fn test_repair_chains_leaving_the_fat() {
+    let mut vmc = card_with_saves();
+    let a = chain_of(&mut vmc, "SAVE-A/data.bin");
+    let b = chain_of(&mut vmc, "SAVE-B/data.bin");
+    let max_clusters = vmc.superblock.max_allocatable_clusters;
+    let (mut vmc, _) = corrupt(vmc, |image, layout| {
+        layout.set_fat(image, a[1], 0x80FF_FFF0);
+        layout.set_fat(image, b[1], 0x8000_0000 | (max_clusters + 1));
+    });
+
+    let report = vmc.repair(&mut Vec::new()).unwrap();
+    assert!(report.remaining.is_clean(), "{:?}", report.remaining.issues);
+    assert_eq!(report.truncated_chains, 2);
+    assert_eq!(report.fixed_lengths, 2);
+    assert_eq!(vmc.metadata("SAVE-A/data.bin").unwrap().length, 2048);
+    assert_eq!(vmc.metadata("SAVE-B/data.bin").unwrap().length, 2048);
+    // a[2] and b[2] were cut off and end up in LOST.FOUND
+    assert_eq!(report.recovered.len(), 2);
+}
+
+#[test]
+fn test_repair_length_mismatches() {
+    let mut vmc = card_with_saves();
+    let root = vmc.build_cluster_chain(vmc.superblock.rootdir_cluster);
+    let save_a = chain_of(&mut vmc, "SAVE-A");
+    let (mut vmc, _) = corrupt(vmc, |image, layout| {
+        layout.set_length(image, &root, 3, 40);
+        layout.set_length(image, &save_a, 2, 9000);
+    });
+
+    let report = vmc.repair(&mut Vec::new()).unwrap();
+    assert!(report.remaining.is_clean(), "{:?}", report.remaining.issues);
+    assert_eq!(vmc.metadata("SAVE-B").unwrap().length, 4);
+    assert_eq!(vmc.metadata("SAVE-A/data.bin").unwrap().length, 3072);
+    let names: Vec<String> = vmc
+        .read_dir("SAVE-B")
+        .unwrap()
+        .into_iter()
+        .map(|e| e.name)
+        .collect();
+    assert_eq!(names, ["data.bin", "SUB"]);
+}
+
+#[test]
+fn test_repair_chain_into_free_cluster() {
+    let mut vmc = card_with_saves();
+    let a = chain_of(&mut vmc, "SAVE-A/data.bin");
+    let (mut vmc, _) = corrupt(vmc, |image, layout| {
+        layout.set_fat(image, a[1], 0x7FFF_FFFF);
+    });
+
+    let report = vmc.repair(&mut Vec::new()).unwrap();
+    assert!(
+        report
+            .found
+            .issues
+            .contains(&CheckIssue::ChainIntoFreeCluster {
+                path: "SAVE-A/data.bin".to_string(),
+                cluster: a[1],
+            })
+    );
+    assert!(report.remaining.is_clean(), "{:?}", report.remaining.issues);
+
+    // The formerly free cluster becomes the file's last one and the length follows it
+    assert_eq!(report.truncated_chains, 1);
+    assert_eq!(chain_of(&mut vmc, "SAVE-A/data.bin"), a[..2]);
+    assert_eq!(vmc.metadata("SAVE-A/data.bin").unwrap().length, 2048);
+    assert_eq!(
+        vmc.read_file("SAVE-A", "data.bin").unwrap(),
+        vec![1u8; 2048]
+    );
+
+    // The old tail is no longer reachable and goes to LOST.FOUND
+    assert_eq!(report.recovered.len(), 1);
+    assert_eq!(chain_of(&mut vmc, "LOST.FOUND/FILE0000.CHK"), a[2..]);
+}
@@ -0,0 +1,146 @@
+use alfatch_vmc::model::vmc_core_model::Vmc;
+use alfatch_vmc::vmc::vmc_core::extract_save_directories;
+use std::io::{ErrorKind, Read, Seek, SeekFrom};
+use tempfile::tempdir;
+
+fn pattern(len: usize) -> Vec<u8> {
+    (0..len).map(|i| (i * 7 % 251) as u8).collect()
+}
+
+fn card_with_save(path: &std::path::Path, data: &[u8]) -> Vmc {
+    let mut vmc = Vmc::format(path).unwrap();
+    vmc.create_dir("BASLUS-21050").unwrap();
+    vmc.write_file("BASLUS-21050", "save.bin", data).unwrap();
+    vmc
+}
+
+#[test]
+fn test_stream_file_in_small_reads() {
+    let dir = tempdir().unwrap();
+    let data = pattern(5000);
+    let mut vmc = card_with_save(&dir.path().join("card.ps2"), &data);
+
+    let mut file = vmc.open("BASLUS-21050/save.bin").unwrap();
+    assert_eq!(file.len(), 5000);
+
+    let mut out = Vec::new();
+    let mut chunk = [0u8; 333];
+    loop {
+        let n = file.read(&mut chunk).unwrap();
+        if n == 0 {
+            break;
+        }
+        out.extend_from_slice(&chunk[..n]);
+    }
+    assert_eq!(out, data);
+}
+
+#[test]
+fn test_seek_within_file() {
+    let dir = tempdir().unwrap();
+    let data = pattern(5000);
+    let mut vmc = card_with_save(&dir.path().join("card.ps2"), &data);
+    let mut file = vmc.open("BASLUS-21050/save.bin").unwrap();
+
+    let mut buf = [0u8; 100];
+    file.seek(SeekFrom::Start(3000)).unwrap();
+    file.read_exact(&mut buf).unwrap();
+    assert_eq!(&buf[..], &data[3000..3100]);
+
+    // Backwards across a cluster boundary
+    file.seek(SeekFrom::Current(-1100)).unwrap();
+    file.read_exact(&mut buf).unwrap();
+    assert_eq!(&buf[..], &data[2000..2100]);
+
+    assert_eq!(file.seek(SeekFrom::End(-10)).unwrap(), 4990);
+    let mut tail = Vec::new();
+    file.read_to_end(&mut tail).unwrap();
+    assert_eq!(tail, &data[4990..]);
+
+    file.seek(SeekFrom::Start(6000)).unwrap();
+    assert_eq!(file.read(&mut buf).unwrap(), 0);
+    assert!(file.seek(SeekFrom::Current(-7000)).is_err());
+}
+
+#[test]
+fn test_open_rejects_directories_and_missing_files() {
+    let dir = tempdir().unwrap();
+    let mut vmc = card_with_save(&dir.path().join("card.ps2"), &pattern(10));
+
+    let err = vmc.open("BASLUS-21050").err().unwrap();
+    assert_eq!(err.kind(), ErrorKind::InvalidInput);
+    let err = vmc.open("BASLUS-21050/missing.bin").err().unwrap();
+    assert_eq!(err.kind(), ErrorKind::NotFound);
+}
+
+#[test]
+fn test_short_chain_reports_eof() {
+    let dir = tempdir().unwrap();
+    let mut vmc = card_with_save(&dir.path().join("card.ps2"), &pattern(1500));
+    let cluster = vmc.metadata("BASLUS-21050/save.bin").unwrap().cluster;
+
+    // Claim the file is longer than its two-cluster chain
+    let mut file = vmc.open_chain(cluster, 4000);
+    let mut out = Vec::new();
+    let err = file.read_to_end(&mut out).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::UnexpectedEof);
+    assert_eq!(out.len(), 2048);
+}
+
+#[test]
+fn test_out_of_range_start_cluster_is_invalid_data() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("card.ps2");
+    let mut vmc = card_with_save(&path, &pattern(1500));
+    // save.bin is entry 2 of the save: the first slot of the directory's second cluster
+    let save_cluster = vmc.metadata("BASLUS-21050").unwrap().cluster;
+    let dir_cluster = vmc.build_cluster_chain(save_cluster)[1];
+    let offset = (vmc.superblock.alloc_offset + dir_cluster) as usize * 1024 + 16;
+    drop(vmc);
+
+    let mut image = std::fs::read(&path).unwrap();
+    image[offset..offset + 4].copy_from_slice(&0xFFFF_FFF0u32.to_le_bytes());
+    std::fs::write(&path, image).unwrap();
+
+    let mut vmc = Vmc::new(&path).unwrap();
+    let mut out = Vec::new();
+    let err = vmc
+        .open("BASLUS-21050/save.bin")
+        .unwrap()
+        .read_to_end(&mut out)
+        .unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::InvalidData);
+    let err = vmc.read_file("BASLUS-21050", "save.bin").unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::InvalidData);
+}
+
+#[test]
+fn test_extract_skips_file_with_oversized_length() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("card.ps2");
+    let mut vmc = Vmc::format(&path).unwrap();
+    vmc.create_dir("BASLUS-21050").unwrap();
+    vmc.write_file("BASLUS-21050", "big.bin", &pattern(1500))
+        .unwrap();
+    vmc.write_file("BASLUS-21050", "ok.bin", &pattern(700))
+        .unwrap();
+    // big.bin is entry 2: the first slot of the save's second cluster
+    let save_cluster = vmc.metadata("BASLUS-21050").unwrap().cluster;
+    let dir_cluster = vmc.build_cluster_chain(save_cluster)[1];
+    let offset = (vmc.superblock.alloc_offset + dir_cluster) as usize * 1024 + 4;
+    drop(vmc);
+
+    let mut image = std::fs::read(&path).unwrap();
+    image[offset..offset + 4].copy_from_slice(&u32::MAX.to_le_bytes());
+    std::fs::write(&path, image).unwrap();
+
+    // The 4 GB length is read as far as the chain goes, then the file is skipped
+    let out = dir.path().join("out");
+    let mut vmc = Vmc::new(&path).unwrap();
+    extract_save_directories(&mut vmc, out.to_str().unwrap()).unwrap();
+    assert!(!out.join("BASLUS-21050/big.bin").exists());
+    assert_eq!(
+        std::fs::read(out.join("BASLUS-21050/ok.bin")).unwrap(),
+        pattern(700)
+    );
+}
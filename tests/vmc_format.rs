@@ -0,0 +1,125 @@
+use alfatch_vmc::model::vmc_core_model::Vmc;
+use alfatch_vmc::vmc::vmc_core::validate_mc_file;
+use tempfile::tempdir;
+
+#[test]
+fn test_format_standard_card_layout() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("blank.ps2");
+    Vmc::format(&path).unwrap();
+
+    assert_eq!(std::fs::metadata(&path).unwrap().len(), 8 * 1024 * 1024);
+    assert!(validate_mc_file(path.to_str().unwrap()).unwrap());
+
+    let mut vmc = Vmc::new(&path).unwrap();
+    let sb = &vmc.superblock;
+    assert_eq!(sb.version, "1.2.0.0");
+    assert_eq!(sb.page_size, 512);
+    assert_eq!(sb.pages_per_cluster, 2);
+    assert_eq!(sb.cluster_size, 1024);
+    assert_eq!(sb.alloc_offset, 41);
+    assert_eq!(sb.max_allocatable_clusters, 8135);
+    assert_eq!(sb.rootdir_cluster, 0);
+    assert_eq!(sb.backup_block1, 1023);
+    assert_eq!(sb.backup_block2, 1022);
+    assert_eq!(sb.ifc_ptr_list[0], 8);
+    assert_eq!(sb.ifc_ptr_list[1], 0);
+    assert_eq!(sb.cardtype, 2);
+    assert_eq!(sb.cardflags, 0x52);
+
+    let entries = vmc.list_root_directory().unwrap();
+    let names: Vec<&str> = entries.iter().map(|e| e.name.as_str()).collect();
+    assert_eq!(names, [".", ".."]);
+    assert!(entries.iter().all(|e| e.is_directory));
+}
+
+#[test]
+fn test_formatted_card_accepts_saves() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("blank.ps2");
+    let mut vmc = Vmc::format(&path).unwrap();
+
+    vmc.create_dir("BASLUS-21050").unwrap();
+    vmc.write_file("BASLUS-21050", "icon.sys", &[3u8; 964])
+        .unwrap();
+
+    let mut vmc = Vmc::new(&path).unwrap();
+    assert_eq!(vmc.list_root_directory().unwrap().len(), 3);
+    assert_eq!(
+        vmc.read_file("BASLUS-21050", "icon.sys").unwrap(),
+        [3u8; 964]
+    );
+}
+
+#[test]
+fn test_format_larger_cards() {
+    let dir = tempdir().unwrap();
+
+    // (size in MB, clusters per card, alloc offset, max allocatable clusters)
+    let cases = [
+        (16, 16384, 73, 16295),
+        (32, 32768, 137, 32615),
+        (64, 65536, 265, 65255),
+    ];
+
+    for (size_mb, clusters, alloc_offset, max_allocatable) in cases {
+        let path = dir.path().join(format!("card{size_mb}.ps2"));
+        Vmc::format_with_size(&path, size_mb).unwrap();
+        assert_eq!(
+            std::fs::metadata(&path).unwrap().len(),
+            size_mb as u64 * 1024 * 1024
+        );
+
+        let mut vmc = Vmc::open_writable(&path).unwrap();
+        assert_eq!(vmc.superblock.clusters_per_card, clusters);
+        assert_eq!(vmc.superblock.pages_per_block, 16);
+        assert_eq!(vmc.superblock.alloc_offset, alloc_offset);
+        assert_eq!(vmc.superblock.max_allocatable_clusters, max_allocatable);
+        assert_eq!(vmc.count_free_clusters(), max_allocatable - 1);
+
+        vmc.create_dir("BASLUS-21050").unwrap();
+        let data = vec![0x5Au8; 200 * 1024];
+        vmc.write_file("BASLUS-21050", "BASLUS-21050", &data)
+            .unwrap();
+
+        let mut vmc = Vmc::new(&path).unwrap();
+        assert_eq!(vmc.read_file("BASLUS-21050", "BASLUS-21050").unwrap(), data);
+    }
+}
+
+#[test]
+fn test_free_space_of_blank_card() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("blank.ps2");
+    let vmc = Vmc::format(&path).unwrap();
+
+    // Every allocatable cluster except the root directory
+    assert_eq!(vmc.count_free_clusters(), 8134);
+    assert!(Vmc::format_with_size(dir.path().join("bad.ps2"), 12).is_err());
+}
+
+#[test]
+fn test_reject_truncated_card() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("blank.ps2");
+    Vmc::format_with_size(&path, 16).unwrap();
+
+    let file = std::fs::OpenOptions::new().write(true).open(&path).unwrap();
+    file.set_len(8 * 1024 * 1024).unwrap();
+    assert!(Vmc::new(&path).is_err());
+}
+
+#[test]
+fn test_streamed_format_matches_in_memory_image() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("card32.ps2");
+    let vmc = Vmc::format_with_size(&path, 32).unwrap();
+    let root = (vmc.superblock.alloc_offset + vmc.superblock.rootdir_cluster) as usize * 1024;
+    let on_disk = std::fs::read(&path).unwrap();
+    let in_memory = Vmc::format_in_memory(32).unwrap().into_inner().into_inner();
+
+    // Only the timestamps of the root's "." and ".." entries may differ
+    assert_eq!(on_disk.len(), in_memory.len());
+    assert_eq!(on_disk[..root], in_memory[..root]);
+    assert_eq!(on_disk[root + 1024..], in_memory[root + 1024..]);
+}
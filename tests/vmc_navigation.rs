@@ -0,0 +1,132 @@
+use alfatch_vmc::model::vmc_core_model::Vmc;
+use alfatch_vmc::vmc::vmc_core::extract_save_directories;
+use std::io::ErrorKind;
+use tempfile::tempdir;
+
+fn nested_card(path: &std::path::Path) -> Vmc {
+    let mut vmc = Vmc::format(path).unwrap();
+    vmc.create_dir("BASLUS-21050").unwrap();
+    vmc.write_file("BASLUS-21050", "icon.sys", &[1u8; 964])
+        .unwrap();
+    vmc.create_dir("BASLUS-21050/DATA").unwrap();
+    vmc.create_dir("BASLUS-21050/DATA/SLOT0").unwrap();
+    vmc.write_file("BASLUS-21050/DATA/SLOT0", "save.bin", &[7u8; 3000])
+        .unwrap();
+    vmc
+}
+
+#[test]
+fn test_nested_paths_resolve() {
+    let dir = tempdir().unwrap();
+    let mut vmc = nested_card(&dir.path().join("card.ps2"));
+
+    let names: Vec<String> = vmc
+        .read_dir("BASLUS-21050")
+        .unwrap()
+        .into_iter()
+        .map(|e| e.name)
+        .collect();
+    assert_eq!(names, ["icon.sys", "DATA"]);
+
+    let meta = vmc.metadata("BASLUS-21050/DATA/SLOT0/save.bin").unwrap();
+    assert!(!meta.is_directory);
+    assert_eq!(meta.length, 3000);
+
+    let meta = vmc.metadata("/BASLUS-21050/DATA/../DATA/SLOT0/").unwrap();
+    assert!(meta.is_directory);
+    assert_eq!(meta.name, "SLOT0");
+
+    assert_eq!(
+        vmc.read_file("BASLUS-21050/DATA/SLOT0", "save.bin")
+            .unwrap(),
+        vec![7u8; 3000]
+    );
+
+    // Reopening must see the same tree
+    drop(vmc);
+    let mut vmc = Vmc::new(dir.path().join("card.ps2")).unwrap();
+    assert_eq!(vmc.read_dir("BASLUS-21050/DATA").unwrap()[0].name, "SLOT0");
+}
+
+#[test]
+fn test_lookup_errors() {
+    let dir = tempdir().unwrap();
+    let mut vmc = nested_card(&dir.path().join("card.ps2"));
+
+    let err = vmc.metadata("BASLUS-21050/missing").unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::NotFound);
+
+    let err = vmc.read_dir("BASLUS-21050/icon.sys").unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::InvalidInput);
+
+    let err = vmc.metadata("BASLUS-21050/icon.sys/x").unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::NotFound);
+}
+
+#[test]
+fn test_walk_visits_every_entry_depth_first() {
+    let dir = tempdir().unwrap();
+    let mut vmc = nested_card(&dir.path().join("card.ps2"));
+    vmc.create_dir("BESLES-55673").unwrap();
+
+    let paths: Vec<String> = vmc.walk().map(|e| e.unwrap().path).collect();
+    assert_eq!(
+        paths,
+        [
+            "BASLUS-21050",
+            "BASLUS-21050/icon.sys",
+            "BASLUS-21050/DATA",
+            "BASLUS-21050/DATA/SLOT0",
+            "BASLUS-21050/DATA/SLOT0/save.bin",
+            "BESLES-55673",
+        ]
+    );
+}
+
+#[test]
+fn test_remove_nested_dir_frees_everything() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("card.ps2");
+    let mut vmc = Vmc::format(&path).unwrap();
+    vmc.create_dir("BASLUS-21050").unwrap();
+    vmc.write_file("BASLUS-21050", "icon.sys", &[1u8; 964])
+        .unwrap();
+    let free_before = vmc.count_free_clusters();
+
+    vmc.create_dir("BASLUS-21050/DATA").unwrap();
+    vmc.write_file("BASLUS-21050/DATA", "save.bin", &[7u8; 5000])
+        .unwrap();
+    vmc.remove_dir("BASLUS-21050/DATA").unwrap();
+
+    assert_eq!(vmc.count_free_clusters(), free_before);
+    assert_eq!(vmc.read_dir("BASLUS-21050").unwrap().len(), 1);
+    assert!(vmc.remove_dir("").is_err());
+}
+
+#[test]
+fn test_extract_stops_at_directory_loop() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("card.ps2");
+    let mut vmc = Vmc::format(&path).unwrap();
+    vmc.create_dir("A").unwrap();
+    vmc.create_dir("A/B").unwrap();
+    vmc.create_dir("A/B/C").unwrap();
+    vmc.write_file("A/B", "f.bin", &[4u8; 10]).unwrap();
+    let a = vmc.metadata("A").unwrap().cluster;
+    let b = vmc.metadata("A/B").unwrap().cluster;
+    // C is entry 2 of B: the first slot of B's second cluster
+    let c_cluster = vmc.superblock.alloc_offset + vmc.build_cluster_chain(b)[1];
+    drop(vmc);
+
+    // Point C back at A
+    let mut image = std::fs::read(&path).unwrap();
+    let offset = c_cluster as usize * 1024 + 16;
+    image[offset..offset + 4].copy_from_slice(&a.to_le_bytes());
+    std::fs::write(&path, image).unwrap();
+
+    let out = dir.path().join("out");
+    let mut vmc = Vmc::new(&path).unwrap();
+    extract_save_directories(&mut vmc, out.to_str().unwrap()).unwrap();
+    assert_eq!(std::fs::read(out.join("A/B/f.bin")).unwrap(), [4u8; 10]);
+    assert!(!out.join("A/B/C").exists());
+}
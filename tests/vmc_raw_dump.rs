@@ -0,0 +1,142 @@
+use alfatch_vmc::model::vmc_core_model::Vmc;
+use alfatch_vmc::vmc::ecc::ecc_calculate_page;
+use alfatch_vmc::vmc::vmc_core::{convert_card, verify_ecc};
+use std::path::Path;
+use tempfile::tempdir;
+
+// Re-lay a plain image as a hardware dump: every 512-byte page followed by its ECC
+fn to_raw_dump(plain: &Path, raw: &Path) {
+    let data = std::fs::read(plain).unwrap();
+    let mut out = Vec::with_capacity(data.len() / 512 * 528);
+    for page in data.chunks(512) {
+        out.extend_from_slice(page);
+        out.extend_from_slice(&ecc_calculate_page(page, 16));
+    }
+    std::fs::write(raw, out).unwrap();
+}
+
+#[test]
+fn test_read_raw_dump_with_spare_areas() {
+    let dir = tempdir().unwrap();
+    let plain = dir.path().join("plain.ps2");
+    let raw = dir.path().join("dump.bin");
+
+    let data: Vec<u8> = (0..5000u32).map(|i| (i * 7) as u8).collect();
+    let mut vmc = Vmc::format(&plain).unwrap();
+    vmc.create_dir("BESLES-55673SAVEDATA").unwrap();
+    vmc.write_file("BESLES-55673SAVEDATA", "BESLES-55673", &data)
+        .unwrap();
+    to_raw_dump(&plain, &raw);
+    assert_eq!(std::fs::metadata(&raw).unwrap().len(), 8_650_752);
+
+    let mut vmc = Vmc::new(&raw).unwrap();
+    assert!(vmc.has_ecc());
+    // Root growth, save directory (2 clusters) and five data clusters
+    assert_eq!(vmc.count_free_clusters(), 8134 - 8);
+
+    let names: Vec<String> = vmc
+        .list_root_directory()
+        .unwrap()
+        .into_iter()
+        .map(|e| e.name)
+        .collect();
+    assert_eq!(names, [".", "..", "BESLES-55673SAVEDATA"]);
+    assert_eq!(
+        vmc.read_file("BESLES-55673SAVEDATA", "BESLES-55673")
+            .unwrap(),
+        data
+    );
+}
+
+#[test]
+fn test_plain_image_has_no_ecc() {
+    let dir = tempdir().unwrap();
+    let plain = dir.path().join("plain.ps2");
+    let vmc = Vmc::format(&plain).unwrap();
+    assert!(!vmc.has_ecc());
+}
+
+#[test]
+fn test_verify_and_correct_raw_dump() {
+    let dir = tempdir().unwrap();
+    let plain = dir.path().join("plain.ps2");
+    let raw = dir.path().join("dump.bin");
+
+    let mut vmc = Vmc::format(&plain).unwrap();
+    vmc.create_dir("BASLUS-21050").unwrap();
+    vmc.write_file("BASLUS-21050", "icon.sys", &[0x42; 964])
+        .unwrap();
+    to_raw_dump(&plain, &raw);
+
+    let report = verify_ecc(raw.to_str().unwrap()).unwrap();
+    assert_eq!(report.pages_good, 16384);
+    assert_eq!(report.pages_corrected, 0);
+
+    // Flip one bit in the first page of icon.sys (cluster 4, after the root growth and
+    // two directory clusters), and two bits in an unused page
+    let mut vmc = Vmc::new(&raw).unwrap();
+    let first_cluster = vmc.superblock.alloc_offset as usize + 4;
+    let mut bytes = std::fs::read(&raw).unwrap();
+    bytes[first_cluster * 2 * 528 + 10] ^= 0x08;
+    bytes[100 * 528] ^= 0x01;
+    bytes[100 * 528 + 1] ^= 0x01;
+    std::fs::write(&raw, bytes).unwrap();
+
+    let report = vmc.verify_ecc().unwrap();
+    assert_eq!(report.pages_corrected, 1);
+    assert_eq!(report.pages_uncorrectable, 1);
+    assert_eq!(report.uncorrectable_pages, [100]);
+    assert_eq!(
+        vmc.read_file("BASLUS-21050", "icon.sys").unwrap(),
+        [0x42; 964]
+    );
+}
+
+#[test]
+fn test_write_to_raw_dump_regenerates_ecc() {
+    let dir = tempdir().unwrap();
+    let plain = dir.path().join("plain.ps2");
+    let raw = dir.path().join("dump.bin");
+    Vmc::format(&plain).unwrap();
+    to_raw_dump(&plain, &raw);
+
+    let mut vmc = Vmc::open_writable(&raw).unwrap();
+    vmc.create_dir("BASLUS-21050").unwrap();
+    vmc.write_file("BASLUS-21050", "BASLUS-21050", &[9u8; 3000])
+        .unwrap();
+
+    let mut vmc = Vmc::new(&raw).unwrap();
+    let report = vmc.verify_ecc().unwrap();
+    assert_eq!(report.pages_good, 16384);
+    assert_eq!(
+        vmc.read_file("BASLUS-21050", "BASLUS-21050").unwrap(),
+        [9u8; 3000]
+    );
+}
+
+#[test]
+fn test_convert_round_trip() {
+    let dir = tempdir().unwrap();
+    let plain = dir.path().join("plain.ps2");
+    let raw = dir.path().join("dump.bin");
+    let back = dir.path().join("back.ps2");
+
+    let mut vmc = Vmc::format(&plain).unwrap();
+    vmc.create_dir("BASLUS-21050").unwrap();
+    vmc.write_file("BASLUS-21050", "icon.sys", &[0x24; 964])
+        .unwrap();
+
+    let p = |path: &Path| path.to_str().unwrap().to_string();
+    assert!(convert_card(&p(&plain), &p(&raw)).unwrap());
+    assert_eq!(std::fs::metadata(&raw).unwrap().len(), 8_650_752);
+    assert_eq!(verify_ecc(&p(&raw)).unwrap().pages_good, 16384);
+
+    assert!(!convert_card(&p(&raw), &p(&back)).unwrap());
+    assert_eq!(std::fs::metadata(&back).unwrap().len(), 8_388_608);
+    assert_eq!(
+        std::fs::read(&back).unwrap(),
+        std::fs::read(&plain).unwrap()
+    );
+
+    assert!(convert_card(&p(&plain), &p(&plain)).is_err());
+}
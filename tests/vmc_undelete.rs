@@ -0,0 +1,113 @@
+use alfatch_vmc::model::vmc_core_model::Vmc;
+use alfatch_vmc::model::vmc_undelete::Recoverability;
+use std::io::{Cursor, ErrorKind};
+
+type MemVmc = Vmc<Cursor<Vec<u8>>>;
+
+fn card_with_save() -> MemVmc {
+    let mut vmc = Vmc::format_in_memory(8).unwrap();
+    vmc.create_dir("SAVE-A").unwrap();
+    vmc.write_file("SAVE-A", "icon.sys", &[7u8; 964]).unwrap();
+    vmc.write_file("SAVE-A", "data.bin", &[1u8; 3000]).unwrap();
+    vmc
+}
+
+#[test]
+fn test_undelete_file() {
+    let mut vmc = card_with_save();
+    vmc.delete_file("SAVE-A", "data.bin").unwrap();
+    assert!(vmc.read_file("SAVE-A", "data.bin").is_err());
+
+    let deleted = vmc.deleted_entries().unwrap();
+    assert_eq!(deleted.len(), 1);
+    assert_eq!(deleted[0].path, "SAVE-A/data.bin");
+    assert_eq!(deleted[0].length, 3000);
+    assert_eq!(deleted[0].recoverability, Recoverability::Contiguous);
+
+    vmc.undelete(&deleted[0]).unwrap();
+    assert_eq!(
+        vmc.read_file("SAVE-A", "data.bin").unwrap(),
+        vec![1u8; 3000]
+    );
+    assert!(vmc.deleted_entries().unwrap().is_empty());
+    assert!(vmc.check().unwrap().is_clean());
+}
+
+#[test]
+fn test_undelete_save_directory() {
+    let mut vmc = card_with_save();
+    vmc.remove_dir("SAVE-A").unwrap();
+
+    let deleted = vmc.deleted_entries().unwrap();
+    assert_eq!(deleted.len(), 1);
+    assert!(deleted[0].is_dir);
+    vmc.undelete(&deleted[0]).unwrap();
+
+    assert_eq!(vmc.read_file("SAVE-A", "icon.sys").unwrap(), vec![7u8; 964]);
+    assert_eq!(
+        vmc.read_file("SAVE-A", "data.bin").unwrap(),
+        vec![1u8; 3000]
+    );
+    assert!(vmc.check().unwrap().is_clean());
+}
+
+#[test]
+fn test_overwritten_file_is_not_restored() {
+    let mut vmc = card_with_save();
+    vmc.delete_file("SAVE-A", "data.bin").unwrap();
+    // A new save elsewhere takes the freed clusters but not the slot
+    vmc.create_dir("SAVE-B").unwrap();
+    vmc.write_file("SAVE-B", "other.bin", &[9u8; 100]).unwrap();
+
+    let deleted = vmc.deleted_entries().unwrap();
+    assert_eq!(deleted[0].recoverability, Recoverability::Overwritten);
+    let err = vmc.undelete(&deleted[0]).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::InvalidData);
+    assert_eq!(
+        vmc.read_file("SAVE-B", "other.bin").unwrap(),
+        vec![9u8; 100]
+    );
+}
+
+#[test]
+fn test_fragmented_chain_skips_clusters_in_use() {
+    let mut vmc = card_with_save();
+    // P and X take two consecutive clusters; with P gone, A lands around X
+    vmc.write_file("SAVE-A", "p.bin", &[3u8; 1024]).unwrap();
+    vmc.write_file("SAVE-A", "x.bin", &[4u8; 1024]).unwrap();
+    vmc.delete_file("SAVE-A", "p.bin").unwrap();
+    vmc.write_file("SAVE-A", "a.bin", &[5u8; 2048]).unwrap();
+    vmc.delete_file("SAVE-A", "a.bin").unwrap();
+
+    let deleted = vmc.deleted_entries().unwrap();
+    let a = deleted.iter().find(|e| e.path == "SAVE-A/a.bin").unwrap();
+    assert_eq!(a.recoverability, Recoverability::Fragmented);
+    vmc.undelete(a).unwrap();
+    assert_eq!(vmc.read_file("SAVE-A", "a.bin").unwrap(), vec![5u8; 2048]);
+    assert_eq!(vmc.read_file("SAVE-A", "x.bin").unwrap(), vec![4u8; 1024]);
+    assert!(vmc.check().unwrap().is_clean());
+}
+
+#[test]
+fn test_scan_stops_at_directory_loop() {
+    let mut vmc = Vmc::format_in_memory(8).unwrap();
+    vmc.create_dir("A").unwrap();
+    vmc.create_dir("A/B").unwrap();
+    vmc.create_dir("A/B/C").unwrap();
+    vmc.write_file("A/B", "f.bin", &[4u8; 10]).unwrap();
+    vmc.delete_file("A/B", "f.bin").unwrap();
+    let a = vmc.metadata("A").unwrap().cluster;
+    let b = vmc.metadata("A/B").unwrap().cluster;
+    // C is entry 2 of B: the first slot of B's second cluster
+    let c_cluster = vmc.superblock.alloc_offset + vmc.build_cluster_chain(b)[1];
+
+    // Point C back at A
+    let mut image = vmc.into_inner().into_inner();
+    let offset = c_cluster as usize * 1024 + 16;
+    image[offset..offset + 4].copy_from_slice(&a.to_le_bytes());
+    let mut vmc = Vmc::from_backend(Cursor::new(image)).unwrap();
+
+    let deleted = vmc.deleted_entries().unwrap();
+    let paths: Vec<&str> = deleted.iter().map(|e| e.path.as_str()).collect();
+    assert_eq!(paths, ["A/B/f.bin"]);
+}
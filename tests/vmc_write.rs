@@ -0,0 +1,240 @@
+use alfatch_vmc::model::vmc_core_model::{DIR_MODE, DOTDOT_MODE, Ps2Time, RawFSEntry, Vmc};
+use byteorder::{LittleEndian, WriteBytesExt};
+use std::io::{Cursor, ErrorKind, Write};
+use tempfile::NamedTempFile;
+
+const CLUSTER_SIZE: usize = 1024;
+const IFC_CLUSTER: usize = 8;
+const FAT_CLUSTER: usize = 9;
+const ALLOC_OFFSET: usize = 10;
+const ALLOC_CLUSTERS: usize = 64;
+
+fn put_u32(image: &mut [u8], offset: usize, value: u32) {
+    (&mut image[offset..offset + 4])
+        .write_u32::<LittleEndian>(value)
+        .unwrap();
+}
+
+fn put_entry(image: &mut [u8], cluster: usize, slot: usize, entry: RawFSEntry) {
+    let offset = (ALLOC_OFFSET + cluster) * CLUSTER_SIZE + slot * 512;
+    image[offset..offset + 512].copy_from_slice(&entry.to_bytes());
+}
+
+// Small card with one indirect FAT cluster, one FAT cluster and 64 data clusters.
+// The root directory spans clusters 0 -> 2 and holds one save, "BASLUS-21050", at cluster 1.
+fn blank_card() -> NamedTempFile {
+    let mut image = vec![0u8; (ALLOC_OFFSET + ALLOC_CLUSTERS) * CLUSTER_SIZE];
+
+    image[..28].copy_from_slice(b"Sony PS2 Memory Card Format ");
+    image[28..35].copy_from_slice(b"1.2.0.0");
+    (&mut image[0x28..0x2A])
+        .write_u16::<LittleEndian>(512)
+        .unwrap();
+    (&mut image[0x2A..0x2C])
+        .write_u16::<LittleEndian>(2)
+        .unwrap();
+    (&mut image[0x2C..0x2E])
+        .write_u16::<LittleEndian>(16)
+        .unwrap();
+    put_u32(&mut image, 0x30, (ALLOC_OFFSET + ALLOC_CLUSTERS) as u32);
+    put_u32(&mut image, 0x34, ALLOC_OFFSET as u32);
+    put_u32(&mut image, 0x50, IFC_CLUSTER as u32);
+    for i in 1..32 {
+        put_u32(&mut image, 0x50 + i * 4, 0xFFFFFFFF);
+    }
+    put_u32(&mut image, 0x154, CLUSTER_SIZE as u32);
+    put_u32(&mut image, 0x170, ALLOC_CLUSTERS as u32);
+
+    let ifc = IFC_CLUSTER * CLUSTER_SIZE;
+    put_u32(&mut image, ifc, FAT_CLUSTER as u32);
+    for i in 1..256 {
+        put_u32(&mut image, ifc + i * 4, 0xFFFFFFFF);
+    }
+
+    let fat = FAT_CLUSTER * CLUSTER_SIZE;
+    for i in 0..256 {
+        let value = match i {
+            0 => 0x80000002,
+            1 | 2 => 0xFFFFFFFF,
+            _ => 0x7FFFFFFF,
+        };
+        put_u32(&mut image, fat + i * 4, value);
+    }
+
+    let now = Ps2Time::now();
+    put_entry(&mut image, 0, 0, RawFSEntry::new(".", DIR_MODE, 3, 0, now));
+    put_entry(
+        &mut image,
+        0,
+        1,
+        RawFSEntry::new("..", DOTDOT_MODE, 0, 0, now),
+    );
+    put_entry(
+        &mut image,
+        2,
+        0,
+        RawFSEntry::new("BASLUS-21050", DIR_MODE, 2, 1, now),
+    );
+    put_entry(&mut image, 1, 0, RawFSEntry::new(".", DIR_MODE, 0, 0, now));
+    put_entry(
+        &mut image,
+        1,
+        1,
+        RawFSEntry::new("..", DOTDOT_MODE, 0, 0, now),
+    );
+
+    let mut file = NamedTempFile::new().unwrap();
+    file.write_all(&image).unwrap();
+    file
+}
+
+#[test]
+fn test_write_and_read_back_file() {
+    let card = blank_card();
+    let data: Vec<u8> = (0..2500u32).map(|i| (i % 251) as u8).collect();
+
+    let mut vmc = Vmc::open_writable(card.path()).unwrap();
+    let free_before = vmc.count_free_clusters();
+    vmc.write_file("BASLUS-21050", "BASLUS-21050", &data)
+        .unwrap();
+    // Three data clusters plus a second directory cluster for the new entry
+    assert_eq!(vmc.count_free_clusters(), free_before - 4);
+
+    // Reopen to make sure the FAT and directory were flushed to disk
+    let mut vmc = Vmc::new(card.path()).unwrap();
+    assert_eq!(vmc.read_file("BASLUS-21050", "BASLUS-21050").unwrap(), data);
+    assert_eq!(vmc.count_free_clusters(), free_before - 4);
+}
+
+#[test]
+fn test_overwrite_file_reuses_entry() {
+    let card = blank_card();
+    let mut vmc = Vmc::open_writable(card.path()).unwrap();
+    let free_before = vmc.count_free_clusters();
+
+    vmc.write_file("BASLUS-21050", "icon.sys", &[1u8; 3000])
+        .unwrap();
+    vmc.write_file("BASLUS-21050", "icon.sys", b"short")
+        .unwrap();
+
+    let mut vmc = Vmc::new(card.path()).unwrap();
+    assert_eq!(vmc.read_file("BASLUS-21050", "icon.sys").unwrap(), b"short");
+    assert_eq!(vmc.count_free_clusters(), free_before - 2);
+}
+
+#[test]
+fn test_directory_grows_and_delete_frees_clusters() {
+    let card = blank_card();
+    let mut vmc = Vmc::open_writable(card.path()).unwrap();
+    let free_before = vmc.count_free_clusters();
+
+    // Third and fourth entries need a second directory cluster
+    vmc.write_file("BASLUS-21050", "a.bin", &[0xAA; 10])
+        .unwrap();
+    vmc.write_file("BASLUS-21050", "b.bin", &[0xBB; 10])
+        .unwrap();
+    vmc.write_file("BASLUS-21050", "c.bin", &[0xCC; 10])
+        .unwrap();
+    assert_eq!(vmc.count_free_clusters(), free_before - 5);
+
+    vmc.delete_file("BASLUS-21050", "b.bin").unwrap();
+    assert_eq!(vmc.count_free_clusters(), free_before - 4);
+    assert!(vmc.read_file("BASLUS-21050", "b.bin").is_err());
+
+    // The freed slot is reused instead of growing the directory
+    vmc.write_file("BASLUS-21050", "d.bin", &[0xDD; 10])
+        .unwrap();
+    assert_eq!(vmc.count_free_clusters(), free_before - 5);
+    assert_eq!(vmc.read_file("BASLUS-21050", "a.bin").unwrap(), [0xAA; 10]);
+    assert_eq!(vmc.read_file("BASLUS-21050", "d.bin").unwrap(), [0xDD; 10]);
+}
+
+#[test]
+fn test_write_fails_when_card_is_full() {
+    let card = blank_card();
+    let mut vmc = Vmc::open_writable(card.path()).unwrap();
+    let free_before = vmc.count_free_clusters();
+
+    let too_big = vec![0u8; (ALLOC_CLUSTERS + 1) * CLUSTER_SIZE];
+    assert!(vmc.write_file("BASLUS-21050", "big.bin", &too_big).is_err());
+    assert_eq!(vmc.count_free_clusters(), free_before);
+    assert!(vmc.write_file("MISSING", "a.bin", b"x").is_err());
+}
+
+#[test]
+fn test_create_and_remove_save_directory() {
+    let card = blank_card();
+    let mut vmc = Vmc::open_writable(card.path()).unwrap();
+    let free_before = vmc.count_free_clusters();
+
+    vmc.create_dir("BESLES-55673SAVEDATA").unwrap();
+    assert!(vmc.create_dir("BESLES-55673SAVEDATA").is_err());
+    vmc.write_file("BESLES-55673SAVEDATA", "icon.sys", &[7u8; 964])
+        .unwrap();
+
+    let mut vmc = Vmc::open_writable(card.path()).unwrap();
+    let names: Vec<String> = vmc
+        .list_root_directory()
+        .unwrap()
+        .into_iter()
+        .map(|e| e.name)
+        .collect();
+    assert!(names.contains(&"BESLES-55673SAVEDATA".to_string()));
+    assert_eq!(
+        vmc.read_file("BESLES-55673SAVEDATA", "icon.sys").unwrap(),
+        [7u8; 964]
+    );
+
+    vmc.remove_dir("BESLES-55673SAVEDATA").unwrap();
+    assert_eq!(vmc.count_free_clusters(), free_before);
+    let names: Vec<String> = vmc
+        .list_root_directory()
+        .unwrap()
+        .into_iter()
+        .map(|e| e.name)
+        .collect();
+    assert!(!names.contains(&"BESLES-55673SAVEDATA".to_string()));
+}
+
+#[test]
+fn test_create_dir_reuses_freed_root_slot() {
+    let card = blank_card();
+    let mut vmc = Vmc::open_writable(card.path()).unwrap();
+
+    // Root holds ".", ".." and the existing save; the fourth slot fits in cluster 2
+    vmc.create_dir("SAVE-A").unwrap();
+    let free_after_first = vmc.count_free_clusters();
+    vmc.remove_dir("SAVE-A").unwrap();
+    vmc.create_dir("SAVE-B").unwrap();
+    assert_eq!(vmc.count_free_clusters(), free_after_first);
+
+    // A fifth entry grows the root chain by one cluster
+    vmc.create_dir("SAVE-C").unwrap();
+    assert_eq!(vmc.count_free_clusters(), free_after_first - 2);
+    assert_eq!(vmc.list_root_directory().unwrap().len(), 5);
+}
+
+#[test]
+fn test_remove_dir_refuses_directory_loop() {
+    let mut vmc = Vmc::format_in_memory(8).unwrap();
+    vmc.create_dir("A").unwrap();
+    vmc.create_dir("A/B").unwrap();
+    vmc.create_dir("A/B/C").unwrap();
+    vmc.write_file("A/B", "f.bin", &[4u8; 10]).unwrap();
+    let a = vmc.metadata("A").unwrap().cluster;
+    let b = vmc.metadata("A/B").unwrap().cluster;
+    // C is entry 2 of B: the first slot of B's second cluster
+    let c_cluster = vmc.superblock.alloc_offset + vmc.build_cluster_chain(b)[1];
+
+    // Point C back at A
+    let mut image = vmc.into_inner().into_inner();
+    let offset = c_cluster as usize * CLUSTER_SIZE + 16;
+    image[offset..offset + 4].copy_from_slice(&a.to_le_bytes());
+    let mut vmc = Vmc::from_backend(Cursor::new(image)).unwrap();
+    let free_before = vmc.count_free_clusters();
+
+    let err = vmc.remove_dir("A").unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::InvalidData);
+    assert_eq!(vmc.count_free_clusters(), free_before);
+    assert_eq!(vmc.read_file("A/B", "f.bin").unwrap(), [4u8; 10]);
+}